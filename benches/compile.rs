@@ -0,0 +1,101 @@
+//! `lr::compile` のベンチマーク。`cargo bench --bench compile` で実行する。
+//!
+//! 文法は大きさを変えて機械的に作る。非終端記号は大文字1文字に限られるので、
+//! 規則数は終端記号（CJK の文字も使う）の組み合わせで増やす。
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use lr0_parser_rs::grammar::{Grammar, parse_grammar_text};
+use lr0_parser_rs::lr::compile;
+
+/// `B` の右辺が `alphabet` の `depth` 文字の全組み合わせになる文法。状態は接頭辞の木になる。
+fn prefix_tree(alphabet: &str, depth: u32) -> String {
+    let mut words = vec![String::new()];
+    for _ in 0..depth {
+        words = words
+            .iter()
+            .flat_map(|word| alphabet.chars().map(move |c| format!("{word}{c}")))
+            .collect();
+    }
+    let mut text = String::from("S -> SB\nS -> B\n");
+    for word in words {
+        text.push_str(&format!("B -> {word}\n"));
+    }
+    text
+}
+
+/// `P -> c` を `count` 個の異なる文字で並べた幅の広い文法。
+fn wide_alternatives(count: u32) -> String {
+    let mut text = String::from("S -> SP\nS -> P\n");
+    for c in (0x4E00..0x4E00 + count).filter_map(char::from_u32) {
+        text.push_str(&format!("P -> {c}\n"));
+    }
+    text
+}
+
+/// `A -> aBb | c`, `B -> dCe | f`, ... と 25 段入れ子になる文法。各段に幅 `width` の別名を足す。
+fn nested_brackets(width: u32) -> String {
+    let letters: Vec<char> = ('A'..='Y').collect();
+    let mut terminal = 0x3041u32;
+    let mut next = || {
+        terminal += 1;
+        char::from_u32(terminal).unwrap()
+    };
+
+    let mut text = String::new();
+    for (level, left) in letters.iter().enumerate() {
+        for _ in 0..width {
+            match letters.get(level + 1) {
+                Some(inner) => text.push_str(&format!("{left} -> {}{inner}{}\n", next(), next())),
+                None => text.push_str(&format!("{left} -> {}\n", next())),
+            }
+        }
+        text.push_str(&format!("{left} -> {}\n", next()));
+    }
+    text
+}
+
+fn measure(name: &str, grammar: &Grammar) {
+    let started = Instant::now();
+    let machine = compile(grammar).expect("benchmark grammars are LR(0)");
+    let first = started.elapsed();
+
+    // 1 秒か 100 回のどちらかに達するまで繰り返して平均を取る。
+    let mut runs = 0u32;
+    let started = Instant::now();
+    while runs < 100 && started.elapsed() < Duration::from_secs(1) {
+        black_box(compile(black_box(grammar)).unwrap());
+        runs += 1;
+    }
+    let average = started.elapsed() / runs.max(1);
+
+    println!(
+        "{:<28} {:>5} rules {:>6} states  first {:>10.3} ms  mean {:>10.3} ms ({} runs)",
+        name,
+        grammar.productions.len(),
+        machine.state_count(),
+        first.as_secs_f64() * 1000.0,
+        average.as_secs_f64() * 1000.0,
+        runs
+    );
+}
+
+fn main() {
+    let cases = [
+        ("reducer", include_str!("../reducer").to_string()),
+        ("prefix-tree 4^3", prefix_tree("abcd", 3)),
+        ("prefix-tree 6^3", prefix_tree("abcdef", 3)),
+        ("prefix-tree 8^3", prefix_tree("abcdefgh", 3)),
+        ("prefix-tree 5^4", prefix_tree("abcde", 4)),
+        ("wide-alternatives 250", wide_alternatives(250)),
+        ("wide-alternatives 1000", wide_alternatives(1000)),
+        ("nested-brackets x4", nested_brackets(4)),
+        ("nested-brackets x16", nested_brackets(16)),
+    ];
+
+    for (name, text) in cases {
+        let grammar = parse_grammar_text(&text).expect("benchmark grammar text");
+        measure(name, &grammar);
+    }
+}
@@ -0,0 +1,63 @@
+//! `runtime` の駆動ループのベンチマーク。`cargo bench --bench runtime` で実行する。
+//!
+//! 既定文法（`reducer`）に 10^5〜10^6 トークンの式を流し、`run`、トレースの記録、
+//! 密な表（`packed::DenseTable`）からの `run` の時間を比べる。
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use lr0_parser_rs::grammar::{Symbol, parse_grammar_text, parse_input_text};
+use lr0_parser_rs::lr::compile;
+use lr0_parser_rs::packed::DenseTable;
+use lr0_parser_rs::runtime::{run, trace};
+
+/// `1+0*1+0*...` を `tokens` トークン（'$' を除く）ぶん作る。
+fn expression(tokens: usize) -> Vec<Symbol> {
+    let text: String = "1+0*".chars().cycle().take(tokens | 1).collect();
+    parse_input_text(&text).expect("benchmark input")
+}
+
+fn measure(name: &str, tokens: usize, mut body: impl FnMut()) {
+    let started = Instant::now();
+    body();
+    let first = started.elapsed();
+
+    // 2 秒か 20 回のどちらかに達するまで繰り返して平均を取る。
+    let mut runs = 0u32;
+    let started = Instant::now();
+    while runs < 20 && started.elapsed() < Duration::from_secs(2) {
+        body();
+        runs += 1;
+    }
+    let average = started.elapsed() / runs.max(1);
+
+    println!(
+        "{:<16} {:>8} tokens  first {:>9.3} ms  mean {:>9.3} ms  {:>7.1} ns/token ({} runs)",
+        name,
+        tokens,
+        first.as_secs_f64() * 1000.0,
+        average.as_secs_f64() * 1000.0,
+        average.as_secs_f64() * 1e9 / tokens as f64,
+        runs
+    );
+}
+
+fn main() {
+    let grammar = parse_grammar_text(include_str!("../reducer")).expect("reducer grammar");
+    let machine = compile(&grammar).expect("reducer is LR(0)");
+    let dense = DenseTable::from_parser(&machine);
+
+    for tokens in [100_000, 300_000, 1_000_000] {
+        let input = expression(tokens);
+        // 木の drop も測る時間に入る。`AstNode` の drop は再帰しないので深い木でも溢れない。
+        measure("run", tokens, || {
+            black_box(run(&machine, black_box(&input)).unwrap());
+        });
+        measure("run (dense)", tokens, || {
+            black_box(run(&dense, black_box(&input)).unwrap());
+        });
+        measure("trace", tokens, || {
+            black_box(trace(&machine, black_box(&input)).unwrap().len());
+        });
+    }
+}
@@ -1,11 +1,22 @@
-use crate::generator_engine::GeneratorEngine;
+use crate::evaluator::{Environment, EvalError};
+use crate::generator_engine::{GeneratorEngine, cross_check_report};
+use crate::role_check::RoleWarning;
+use crate::roles::{Arity, Operation, RoleDefinition, RoleRegistry, TerminalRole};
+use crate::runner::{Job, RunLimits};
 use eframe::{App, egui};
-use lr0_parser_rs::grammar::{Grammar, parse_grammar_text};
-use lr0_parser_rs::lr::{self, CompiledParser};
+use crate::workspace::WorkspaceFile;
+use lr0_parser_rs::grammar::{Grammar, parse_grammar_text, read_file};
+use lr0_parser_rs::diagram;
+use lr0_parser_rs::lr::CompiledParser;
+pub use lr0_parser_rs::table::{ParseTableAction, TableFormat, build_parse_table, export_table};
 use lr0_parser_rs::{AstNode, ParseStep, StateInfo, StepAction, build_trace};
 use std::collections::HashMap;
+use std::path::Path;
 use std::time::{Duration, Instant};
 
+/// eframe の永続化ストレージに、最後に開いた/保存したワークスペースのパスを置くキー。
+const LAST_WORKSPACE_KEY: &str = "last_workspace";
+
 // ── Parse result types ────────────────────────────────────────────────────────
 
 pub struct ParseArtifacts {
@@ -13,6 +24,7 @@ pub struct ParseArtifacts {
     pub table: Vec<Vec<ParseTableAction>>,
     pub state_infos: Vec<StateInfo>,
     pub accept_states: Vec<usize>,
+    pub rules: Vec<String>,
 }
 
 pub enum ParserStatus {
@@ -75,33 +87,28 @@ pub enum ParserKind {
 }
 
 impl ParserKind {
-    pub fn label(self) -> &'static str {
+    pub const ALL: [ParserKind; 4] = [Self::Lr0, Self::Slr, Self::Lalr, Self::Lr1];
+
+    /// ワークスペースファイルに書く名前。
+    pub fn key(self) -> &'static str {
         match self {
-            Self::Lr0 => "LR(0)",
-            Self::Slr => "SLR(1)",
-            Self::Lalr => "LALR(1)",
-            Self::Lr1 => "LR(1)",
+            Self::Lr0 => "lr0",
+            Self::Slr => "slr",
+            Self::Lalr => "lalr",
+            Self::Lr1 => "lr1",
         }
     }
-}
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum ParseTableAction {
-    Shift(usize),
-    Reduce(usize),
-    Accept,
-    Goto(usize),
-    Error,
-}
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.key() == key)
+    }
 
-impl ParseTableAction {
-    pub fn as_label(self) -> String {
+    pub fn label(self) -> &'static str {
         match self {
-            Self::Shift(state) => format!("s{}", state),
-            Self::Reduce(production) => format!("r{}", production),
-            Self::Accept => "acc".to_string(),
-            Self::Goto(state) => format!("g{}", state),
-            Self::Error => String::new(),
+            Self::Lr0 => "LR(0)",
+            Self::Slr => "SLR(1)",
+            Self::Lalr => "LALR(1)",
+            Self::Lr1 => "LR(1)",
         }
     }
 }
@@ -111,6 +118,11 @@ impl ParseTableAction {
 pub struct UiState {
     pub current_page: Page,
     pub fonts_initialized: bool,
+    /// File メニューで開く/保存するワークスペースのパス。
+    pub workspace_path: String,
+    /// 最後に開けた/保存できたワークスペースのパス。次回の起動時にはこれを開き直す。
+    pub last_workspace: Option<String>,
+    pub file_message: String,
 }
 
 pub struct AppServices {
@@ -121,17 +133,39 @@ pub struct WorkspaceState {
     pub input_string: String,
     pub reducer_string: String,
     pub terminals: Vec<char>,
-    pub terminal_types: HashMap<char, String>,
+    pub terminal_roles: HashMap<char, TerminalRole>,
+    /// ユーザーが定義した演算子の役割。ワークスペースと一緒に保存する。
+    pub role_registry: RoleRegistry,
+    /// Ident の終端記号に与える値。ワークスペースと一緒に保存する。
+    pub variables: Environment,
 }
 
 pub struct GeneratorPageState {
     pub generate_result: String,
+    pub parser_module: String,
     pub ast_preview: String,
     pub source_preview: String,
     pub expression_preview: String,
     pub notes: Vec<String>,
+    pub role_warnings: Vec<RoleWarning>,
     pub run_result: String,
+    /// 評価式をコンパイルした命令列の逆アセンブルと、VM での実行結果。
+    pub disassembly: String,
+    pub vm_result: String,
+    /// `generate_result` が翻訳テンプレートの出力かどうか。
+    pub translated: bool,
+    pub evaluation: Option<Result<i64, EvalError>>,
+    /// 実行中の突き合わせ。終わるまで `update` が毎フレーム見に行く。
+    pub job: Option<Job>,
+    /// Custom Roles の入力欄。
+    pub role_draft: RoleDefinition,
+    pub role_message: String,
+    /// Variables の入力欄。
+    pub variable_name: String,
+    pub variable_value: i64,
+    pub variable_message: String,
     pub ast: Option<AstNode>,
+    pub rules: Vec<String>,
 }
 
 pub struct ParserPageState {
@@ -160,6 +194,9 @@ impl Default for ParserApp {
             ui: UiState {
                 current_page: Page::Parser,
                 fonts_initialized: false,
+                workspace_path: String::from("workspace.json"),
+                last_workspace: None,
+                file_message: String::new(),
             },
             services: AppServices {
                 generator_engine: GeneratorEngine::new(),
@@ -168,16 +205,38 @@ impl Default for ParserApp {
                 input_string: String::new(),
                 reducer_string: String::from("E -> E*B\nE -> E+B\nE -> B\nB -> 0\nB -> 1"),
                 terminals: vec![],
-                terminal_types: HashMap::new(),
+                terminal_roles: HashMap::new(),
+                role_registry: RoleRegistry::default(),
+                variables: Environment::new(),
             },
             generator: GeneratorPageState {
                 generate_result: String::new(),
+                parser_module: String::new(),
                 ast_preview: String::new(),
                 source_preview: String::new(),
                 expression_preview: String::new(),
                 notes: Vec::new(),
+                role_warnings: Vec::new(),
                 run_result: String::new(),
+                disassembly: String::new(),
+                vm_result: String::new(),
+                translated: false,
+                evaluation: None,
+                job: None,
+                role_draft: RoleDefinition {
+                    name: String::from("Pow"),
+                    source: String::new(),
+                    evaluation: String::from("i64::pow({0}, {1} as u32)"),
+                    arity: Arity::Binary,
+                    precedence: 3,
+                    operation: Operation::Pow,
+                },
+                role_message: String::new(),
+                variable_name: String::new(),
+                variable_value: 0,
+                variable_message: String::new(),
                 ast: None,
+                rules: Vec::new(),
             },
             parser: ParserPageState {
                 result: String::new(),
@@ -193,21 +252,38 @@ impl Default for ParserApp {
 }
 
 impl App for ParserApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        // 入力欄に打っただけのパスや、開けなかったパスは覚えない。
+        storage.set_string(LAST_WORKSPACE_KEY, self.ui.last_workspace.clone().unwrap_or_default());
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         if self.parser.anim_playing {
-            if let Some(last) = self.parser.anim_last_advance {
-                if last.elapsed() >= Duration::from_millis(700) {
-                    if self.parser.trace_cursor + 1 < self.parser.parse_trace.len() {
-                        self.parser.trace_cursor += 1;
-                        self.parser.anim_last_advance = Some(Instant::now());
-                    } else {
-                        self.parser.anim_playing = false;
-                    }
+            if let Some(last) = self.parser.anim_last_advance
+                && last.elapsed() >= Duration::from_millis(700)
+            {
+                if self.parser.trace_cursor + 1 < self.parser.parse_trace.len() {
+                    self.parser.trace_cursor += 1;
+                    self.parser.anim_last_advance = Some(Instant::now());
+                } else {
+                    self.parser.anim_playing = false;
                 }
             }
             ctx.request_repaint_after(Duration::from_millis(50));
         }
 
+        if let Some(job) = &mut self.generator.job {
+            match job.poll() {
+                Some(outcome) => {
+                    let tool = self.services.generator_engine.target.emitter().toolchain().program;
+                    self.generator.run_result =
+                        cross_check_report(&outcome, self.generator.evaluation.as_ref(), tool);
+                    self.generator.job = None;
+                }
+                None => ctx.request_repaint_after(Duration::from_millis(100)),
+            }
+        }
+
         if !self.ui.fonts_initialized {
             self.setup_fonts(ctx);
             self.ui.fonts_initialized = true;
@@ -216,7 +292,9 @@ impl App for ParserApp {
         egui::TopBottomPanel::top("app_header")
             .resizable(false)
             .show(ctx, |ui| {
-                ui.add_space(10.0);
+                ui.add_space(4.0);
+                egui::MenuBar::new().ui(ui, |ui| self.show_file_menu(ui));
+                ui.add_space(6.0);
                 ui.heading("LR(0) Parser GUI");
                 ui.add_space(10.0);
                 self.show_tabs(ui);
@@ -233,6 +311,146 @@ impl App for ParserApp {
 }
 
 impl ParserApp {
+    /// 前回開いた/保存したワークスペースがあれば読み直して起動する。
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let mut app = Self::default();
+        if let Some(path) = cc
+            .storage
+            .and_then(|storage| storage.get_string(LAST_WORKSPACE_KEY))
+            .filter(|path| !path.is_empty())
+        {
+            app.ui.workspace_path = path;
+            if Path::new(&app.ui.workspace_path).is_file() {
+                app.open_workspace();
+            }
+        }
+        app
+    }
+
+    fn show_file_menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("File", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Workspace:");
+                ui.text_edit_singleline(&mut self.ui.workspace_path);
+            });
+            if ui.button("Open").clicked() {
+                self.open_workspace();
+                ui.close();
+            }
+            if ui.button("Save").clicked() {
+                self.save_workspace();
+                ui.close();
+            }
+            ui.separator();
+            for path in ["reducer", "paren_reducer"] {
+                if ui.button(format!("Load grammar `{}`", path)).clicked() {
+                    self.load_grammar_file(path);
+                    ui.close();
+                }
+            }
+        });
+        if !self.ui.file_message.is_empty() {
+            ui.label(egui::RichText::new(&self.ui.file_message).size(13.0).color(egui::Color32::GRAY));
+        }
+    }
+
+    pub fn workspace_file(&self) -> WorkspaceFile {
+        WorkspaceFile {
+            grammar: self.workspace.reducer_string.clone(),
+            input: self.workspace.input_string.clone(),
+            terminal_roles: self
+                .workspace
+                .terminal_roles
+                .iter()
+                .map(|(&terminal, role)| (terminal, role.clone()))
+                .collect(),
+            role_definitions: self.workspace.role_registry.custom().to_vec(),
+            variables: self.workspace.variables.clone(),
+            parser_kind: self.parser.selected_kind,
+        }
+    }
+
+    pub fn open_workspace(&mut self) {
+        match WorkspaceFile::load(Path::new(&self.ui.workspace_path)) {
+            Ok(file) => {
+                self.workspace.role_registry = file.role_registry();
+                self.workspace.terminal_roles = file.terminal_roles.into_iter().collect();
+                self.workspace.variables = file.variables;
+                self.set_grammar(file.grammar);
+                self.workspace.input_string = file.input;
+                self.parser.selected_kind = file.parser_kind;
+                self.ui.file_message = format!("Opened {}", self.ui.workspace_path);
+                self.ui.last_workspace = Some(self.ui.workspace_path.clone());
+            }
+            Err(error) => {
+                self.ui.file_message = format!("{}: {}", self.ui.workspace_path, error);
+            }
+        }
+    }
+
+    pub fn save_workspace(&mut self) {
+        self.ui.file_message = match self.workspace_file().save(Path::new(&self.ui.workspace_path)) {
+            Ok(()) => {
+                self.ui.last_workspace = Some(self.ui.workspace_path.clone());
+                format!("Saved {}", self.ui.workspace_path)
+            }
+            Err(error) => format!("{}: {}", self.ui.workspace_path, error),
+        };
+    }
+
+    pub fn load_grammar_file(&mut self, path: &str) {
+        match read_file(path) {
+            Ok(text) => {
+                self.set_grammar(text);
+                self.ui.file_message = format!("Loaded grammar {}", path);
+            }
+            Err(error) => self.ui.file_message = format!("{}: {}", path, error),
+        }
+    }
+
+    /// 状態遷移図をワークスペースと同じ名前の `.dot` / `.svg` に書き出す。
+    pub fn export_state_machine(&mut self, extension: &str) {
+        let ParserStatus::Ready(artifacts) = &self.parser.status else {
+            return;
+        };
+        let text = match extension {
+            "dot" => diagram::to_dot(&artifacts.state_infos, &artifacts.accept_states),
+            _ => diagram::to_svg(&artifacts.state_infos, &artifacts.accept_states),
+        };
+        let path = Path::new(&self.ui.workspace_path).with_extension(extension);
+        self.ui.file_message = match std::fs::write(&path, text) {
+            Ok(()) => format!("Exported {}", path.display()),
+            Err(error) => format!("{}: {}", path.display(), error),
+        };
+    }
+
+    /// action/goto 表をワークスペースと同じ名前で、形式ごとの拡張子を付けて書き出す。
+    pub fn export_parse_table(&mut self, format: TableFormat) {
+        let ParserStatus::Ready(artifacts) = &self.parser.status else {
+            return;
+        };
+        let text = export_table(&artifacts.symbols, &artifacts.table, &artifacts.rules, format);
+        let path = Path::new(&self.ui.workspace_path).with_extension(format.extension());
+        self.ui.file_message = match std::fs::write(&path, text) {
+            Ok(()) => format!("Exported {}", path.display()),
+            Err(error) => format!("{}: {}", path.display(), error),
+        };
+    }
+
+    // 文法を差し替え、古い文法に対するパース結果と生成物を捨てる。
+    fn set_grammar(&mut self, grammar: String) {
+        self.workspace.reducer_string = grammar;
+        self.workspace.terminals = parse_grammar_text(&self.workspace.reducer_string)
+            .map(|grammar| terminals_from_grammar(&grammar))
+            .unwrap_or_default();
+        self.apply_default_terminal_roles();
+        self.parser.result.clear();
+        self.parser.parse_trace.clear();
+        self.parser.trace_cursor = 0;
+        self.parser.anim_playing = false;
+        self.parser.status = ParserStatus::Empty;
+    }
+
     fn setup_fonts(&self, ctx: &egui::Context) {
         let mut style = (*ctx.style()).clone();
         style.text_styles = [
@@ -277,7 +495,7 @@ impl ParserApp {
                 self.workspace.terminals = parse_grammar_text(&self.workspace.reducer_string)
                     .map(|grammar| terminals_from_grammar(&grammar))
                     .unwrap_or_default();
-                self.apply_default_terminal_types();
+                self.apply_default_terminal_roles();
             }
         });
     }
@@ -285,7 +503,8 @@ impl ParserApp {
     pub fn generate_code(&mut self) {
         self.services
             .generator_engine
-            .set_terminal_types(self.workspace.terminal_types.clone());
+            .set_roles(self.workspace.terminal_roles.clone(), self.workspace.role_registry.clone());
+        self.services.generator_engine.variables = self.workspace.variables.clone();
         match self
             .services
             .generator_engine
@@ -293,37 +512,93 @@ impl ParserApp {
         {
             Ok(output) => {
                 self.generator.ast_preview = output.ast_preview;
-                self.generator.ast = output.ast;
+                // プレビューには木整形ディレクティブ適用後の AST を出す
+                self.generator.ast = output.lowered_ast;
+                self.generator.rules = output.rules;
                 self.generator.source_preview = output.source_preview;
                 self.generator.expression_preview =
                     output.evaluation_expression.unwrap_or_else(|| "<not available>".to_string());
                 self.generator.notes = output.notes;
+                self.generator.role_warnings = output.role_warnings;
                 self.generator.generate_result = output.generated_code;
-                self.generator.run_result.clear();
+                self.generator.translated = output.translated;
+                self.generator.parser_module = output.parser_module;
+                self.generator.run_result = match &output.evaluation {
+                    Some(Ok(value)) => format!("Evaluated value: {}", value),
+                    Some(Err(error)) => format!("Evaluation failed: {}", error),
+                    None => "Evaluated value: <not available for current terminal mappings>".to_string(),
+                };
+                self.generator.evaluation = output.evaluation;
+                match &output.bytecode {
+                    Some(program) => {
+                        self.generator.disassembly = program.disassemble();
+                        self.generator.vm_result = match program.run(&self.workspace.variables) {
+                            Ok(execution) => format!(
+                                "VM value: {} ({} instructions, max stack depth {})",
+                                execution.value,
+                                program.code.len(),
+                                execution.max_depth
+                            ),
+                            Err(error) => format!("VM failed: {}", error),
+                        };
+                    }
+                    None => {
+                        self.generator.disassembly.clear();
+                        self.generator.vm_result.clear();
+                    }
+                }
             }
             Err(err) => {
                 self.generator.ast_preview.clear();
                 self.generator.ast = None;
+                self.generator.rules.clear();
                 self.generator.source_preview.clear();
                 self.generator.expression_preview.clear();
                 self.generator.notes = vec![err.clone()];
+                self.generator.role_warnings.clear();
                 self.generator.generate_result = err;
+                self.generator.translated = false;
+                self.generator.parser_module.clear();
                 self.generator.run_result.clear();
+                self.generator.disassembly.clear();
+                self.generator.vm_result.clear();
+                self.generator.evaluation = None;
             }
         }
     }
 
-    pub fn run_rust_code(&mut self) {
-        let code = self.generator.generate_result.clone();
-        self.generator.run_result = crate::generator_engine::run_generated_code(&code);
+    /// 生成したプログラムを、生成したときの言語の処理系で別スレッドで走らせる。
+    /// 結果は `update` で受け取り、その場での評価結果と突き合わせる。翻訳テンプレートの出力は走らせない。
+    pub fn cross_check(&mut self) {
+        if self.generator.generate_result.trim().is_empty() {
+            self.generator.run_result = "No code to run. Please generate code first.".to_string();
+            return;
+        }
+        if self.generator.translated {
+            self.generator.run_result =
+                "The output is a translation, not a program; there is nothing to cross-check.".to_string();
+            return;
+        }
+        // 前のジョブは Drop で取り消される
+        self.generator.job = Some(Job::spawn(
+            self.generator.generate_result.clone(),
+            self.services.generator_engine.target.emitter().toolchain(),
+            RunLimits::default(),
+        ));
+    }
+
+    pub fn cancel_cross_check(&mut self) {
+        if let Some(job) = &self.generator.job {
+            job.cancel();
+        }
     }
 
-    pub fn apply_default_terminal_types(&mut self) {
+    pub fn apply_default_terminal_roles(&mut self) {
         for &terminal in &self.workspace.terminals {
             self.workspace
-                .terminal_types
+                .terminal_roles
                 .entry(terminal)
-                .or_insert_with(|| default_terminal_role(terminal).to_string());
+                .or_insert_with(|| TerminalRole::default_for(terminal));
         }
     }
 
@@ -356,20 +631,6 @@ impl ParserApp {
     }
 }
 
-pub fn default_terminal_role(symbol: char) -> &'static str {
-    match symbol {
-        '+' => "Add",
-        '-' => "Sub",
-        '*' => "Mul",
-        '/' => "Div",
-        '%' => "Mod",
-        '(' | '<' | '[' | '{' => "LParen",
-        ')' | '>' | ']' | '}' => "RParen",
-        '0'..='9' => "Num",
-        _ => "Token",
-    }
-}
-
 pub fn terminals_from_grammar(grammar: &Grammar) -> Vec<char> {
     grammar
         .terminals()
@@ -378,46 +639,13 @@ pub fn terminals_from_grammar(grammar: &Grammar) -> Vec<char> {
         .collect()
 }
 
-pub fn build_parse_table(
-    grammar: &Grammar,
-    compiled_parser: &CompiledParser,
-) -> (Vec<char>, Vec<Vec<ParseTableAction>>) {
-    let mut symbols: Vec<char> =
-        grammar.terminals().into_iter().map(|terminal| terminal.0).collect();
-    if !symbols.contains(&'$') {
-        symbols.push('$');
-    }
-    symbols.extend(
-        grammar
-            .non_terminals()
-            .into_iter()
-            .map(|non_terminal| non_terminal.0),
-    );
-
-    let mut table =
-        vec![vec![ParseTableAction::Error; symbols.len()]; compiled_parser.state_count()];
-
-    for state in 0..compiled_parser.state_count() {
-        for (column, symbol) in symbols.iter().copied().enumerate() {
-            table[state][column] = if symbol == '$' || !symbol.is_ascii_uppercase() {
-                compiled_parser
-                    .action(state, lr0_parser_rs::grammar::Terminal(symbol))
-                    .map(|action| match action {
-                        lr::Action::Shift(next) => ParseTableAction::Shift(next),
-                        lr::Action::Reduce(production) => ParseTableAction::Reduce(production),
-                        lr::Action::Accept => ParseTableAction::Accept,
-                    })
-                    .unwrap_or(ParseTableAction::Error)
-            } else {
-                compiled_parser
-                    .goto(state, lr0_parser_rs::grammar::NonTerminal(symbol))
-                    .map(ParseTableAction::Goto)
-                    .unwrap_or(ParseTableAction::Error)
-            };
-        }
-    }
-
-    (symbols, table)
+/// 生成規則 id で引ける規則ラベル（`"E -> E+B"` 形式）。id 0 は拡大規則。
+pub fn rule_labels(compiled_parser: &CompiledParser) -> Vec<String> {
+    compiled_parser
+        .productions()
+        .iter()
+        .map(ToString::to_string)
+        .collect()
 }
 
 pub fn build_animation_trace(
@@ -1,30 +1,574 @@
+use std::collections::{BTreeMap, HashSet};
 use std::fmt;
 
+/// 入力トークン列上の半開区間 `[start, end)`。
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// ノードに付与する任意のユーザー属性。
+pub type Attributes = BTreeMap<String, String>;
+
+/// シフトされた終端記号。`symbol` は文法上の記号、`lexeme` は入力上の綴り。
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Token {
+    pub symbol: char,
+    pub lexeme: String,
+    pub span: Option<Span>,
+    pub attributes: Attributes,
+}
+
+impl Token {
+    pub fn new(symbol: char) -> Self {
+        Self {
+            symbol,
+            lexeme: symbol.to_string(),
+            span: None,
+            attributes: Attributes::new(),
+        }
+    }
+}
+
+/// 還元で作られた非終端ノードの情報。`production` は `CompiledParser` 上の生成規則 id。
 #[derive(PartialEq, Eq, Debug, Clone)]
+pub struct NodeInfo {
+    pub symbol: char,
+    pub production: Option<usize>,
+    pub span: Option<Span>,
+    pub attributes: Attributes,
+}
+
+impl NodeInfo {
+    pub fn new(symbol: char) -> Self {
+        Self {
+            symbol,
+            production: None,
+            span: None,
+            attributes: Attributes::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub enum AstNode {
-    Terminal(char),
-    NonTerminal(char, Vec<AstNode>),
+    Terminal(Token),
+    NonTerminal(NodeInfo, Vec<AstNode>),
+}
+
+// 自動の drop は深さぶん再帰するので、長い入力から作った左に深い木（`1+1+...+1` の導出など）で
+// スタックが溢れる。子を作業用のスタックに移しながら捨て、各ノードは子のない状態で drop させる。
+impl Drop for AstNode {
+    fn drop(&mut self) {
+        let Self::NonTerminal(_, children) = self else {
+            return;
+        };
+        let mut pending = std::mem::take(children);
+        while let Some(mut node) = pending.pop() {
+            if let Self::NonTerminal(_, children) = &mut node {
+                pending.append(children);
+            }
+        }
+    }
 }
 
-// ASTNode の表示用補助関数
-fn print_ast(node: &AstNode, f: &mut fmt::Formatter, indent: usize) -> fmt::Result {
-    match node {
-        AstNode::Terminal(c) => {
-            writeln!(f, "{:indent$}{}", "", c, indent = indent * 4)?;
+impl AstNode {
+    pub fn terminal(symbol: char) -> Self {
+        Self::Terminal(Token::new(symbol))
+    }
+
+    pub fn non_terminal(symbol: char, children: Vec<AstNode>) -> Self {
+        Self::NonTerminal(NodeInfo::new(symbol), children)
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        match &mut self {
+            Self::Terminal(token) => token.span = Some(span),
+            Self::NonTerminal(info, _) => info.span = Some(span),
+        }
+        self
+    }
+
+    pub fn with_production(mut self, production: usize) -> Self {
+        if let Self::NonTerminal(info, _) = &mut self {
+            info.production = Some(production);
+        }
+        self
+    }
+
+    pub fn symbol(&self) -> char {
+        match self {
+            Self::Terminal(token) => token.symbol,
+            Self::NonTerminal(info, _) => info.symbol,
         }
-        AstNode::NonTerminal(name, children) => {
-            writeln!(f, "{:indent$}{}", "", name, indent = indent * 4)?;
-            for child in children {
-                print_ast(child, f, indent + 1)?;
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Terminal(_))
+    }
+
+    pub fn children(&self) -> &[AstNode] {
+        match self {
+            Self::Terminal(_) => &[],
+            Self::NonTerminal(_, children) => children,
+        }
+    }
+
+    pub fn production(&self) -> Option<usize> {
+        match self {
+            Self::Terminal(_) => None,
+            Self::NonTerminal(info, _) => info.production,
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Terminal(token) => token.span,
+            Self::NonTerminal(info, _) => info.span,
+        }
+    }
+
+    pub fn attributes(&self) -> &Attributes {
+        match self {
+            Self::Terminal(token) => &token.attributes,
+            Self::NonTerminal(info, _) => &info.attributes,
+        }
+    }
+
+    pub fn attributes_mut(&mut self) -> &mut Attributes {
+        match self {
+            Self::Terminal(token) => &mut token.attributes,
+            Self::NonTerminal(info, _) => &mut info.attributes,
+        }
+    }
+
+    /// 画面表示用のラベル。終端記号は字句、非終端記号は記号名を返す。
+    pub fn label(&self) -> String {
+        match self {
+            Self::Terminal(token) => token.lexeme.clone(),
+            Self::NonTerminal(info, _) => info.symbol.to_string(),
+        }
+    }
+
+    /// 行きがけ順（親 → 子）の反復子。
+    pub fn pre_order(&self) -> PreOrder<'_> {
+        PreOrder { stack: vec![self] }
+    }
+
+    /// 帰りがけ順（子 → 親）の反復子。
+    pub fn post_order(&self) -> PostOrder<'_> {
+        PostOrder {
+            stack: vec![(self, false)],
+        }
+    }
+
+    /// 左から順に並んだ葉（シフトされた終端記号）。
+    pub fn leaves(&self) -> impl Iterator<Item = &Token> {
+        self.pre_order().filter_map(|node| match node {
+            Self::Terminal(token) => Some(token),
+            Self::NonTerminal(..) => None,
+        })
+    }
+
+    /// 葉の字句を連結した文字列。CST なら入力文字列（空白除去後）と一致する。
+    pub fn yield_string(&self) -> String {
+        self.leaves().map(|token| token.lexeme.as_str()).collect()
+    }
+
+    /// 根を深さ 1 とした木の高さ。
+    pub fn depth(&self) -> usize {
+        let mut deepest = 0;
+        let mut stack = vec![(self, 1)];
+        while let Some((node, depth)) = stack.pop() {
+            deepest = deepest.max(depth);
+            stack.extend(node.children().iter().map(|child| (child, depth + 1)));
+        }
+        deepest
+    }
+
+    /// ノード総数。
+    pub fn size(&self) -> usize {
+        self.pre_order().count()
+    }
+
+    pub fn stats(&self) -> TreeStats {
+        self.fold(&mut StatsFold)
+    }
+
+    pub fn walk<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        let mut stack = vec![(self, 0, false)];
+        while let Some((node, depth, leaving)) = stack.pop() {
+            if leaving {
+                visitor.leave(node, depth);
+                continue;
             }
+            visitor.enter(node, depth);
+            stack.push((node, depth, true));
+            stack.extend(node.children().iter().rev().map(|child| (child, depth + 1, false)));
         }
     }
-    Ok(())
+
+    /// 帰りがけ順にたどって畳み込む。子の結果は積んでおき、親の番で取り出す。
+    pub fn fold<F: Fold + ?Sized>(&self, folder: &mut F) -> F::Output {
+        let mut results: Vec<F::Output> = Vec::new();
+        for node in self.post_order() {
+            let result = match node {
+                Self::Terminal(token) => folder.fold_terminal(token),
+                Self::NonTerminal(info, children) => {
+                    let children = results.split_off(results.len() - children.len());
+                    folder.fold_non_terminal(info, children)
+                }
+            };
+            results.push(result);
+        }
+        results.pop().expect("post-order ends at the root")
+    }
+
+    /// 行きがけ順（`pre_order` と同じ順）に各ノードを書き換える。
+    pub fn for_each_mut(&mut self, mut visit: impl FnMut(&mut AstNode)) {
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            visit(node);
+            if let Self::NonTerminal(_, children) = node {
+                stack.extend(children.iter_mut().rev());
+            }
+        }
+    }
+
+    /// `E/B/*` 形式のパスに一致するノードを行きがけ順で返す。
+    /// 各セグメントは記号1文字、任意の1ノードに一致する `*`、0段以上の子孫に一致する `**`。
+    /// 先頭セグメントは根に対して照合する。
+    pub fn query(&self, path: &str) -> Result<Vec<&AstNode>, QueryError> {
+        let segments = parse_query(path)?;
+        let matched = query_matches(self, &segments);
+        Ok(self
+            .pre_order()
+            .filter(|node| matched.contains(&std::ptr::from_ref(*node)))
+            .collect())
+    }
+}
+
+pub struct PreOrder<'a> {
+    stack: Vec<&'a AstNode>,
 }
 
-// Displayトレイトの実装
+impl<'a> Iterator for PreOrder<'a> {
+    type Item = &'a AstNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children().iter().rev());
+        Some(node)
+    }
+}
+
+pub struct PostOrder<'a> {
+    stack: Vec<(&'a AstNode, bool)>,
+}
+
+impl<'a> Iterator for PostOrder<'a> {
+    type Item = &'a AstNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, expanded) = self.stack.pop()?;
+            if expanded || node.children().is_empty() {
+                return Some(node);
+            }
+            self.stack.push((node, true));
+            self.stack
+                .extend(node.children().iter().rev().map(|child| (child, false)));
+        }
+    }
+}
+
+/// 深さ優先で木をたどるときに呼ばれるフック。`depth` は根が 0。
+pub trait Visitor {
+    fn enter(&mut self, _node: &AstNode, _depth: usize) {}
+    fn leave(&mut self, _node: &AstNode, _depth: usize) {}
+}
+
+/// 子の結果から親の結果を組み立てる、葉から根への畳み込み。
+pub trait Fold {
+    type Output;
+
+    fn fold_terminal(&mut self, token: &Token) -> Self::Output;
+    fn fold_non_terminal(&mut self, info: &NodeInfo, children: Vec<Self::Output>) -> Self::Output;
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct TreeStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub depth: usize,
+    pub max_branching: usize,
+}
+
+struct StatsFold;
+
+struct CloneFold;
+
+impl Fold for CloneFold {
+    type Output = AstNode;
+
+    fn fold_terminal(&mut self, token: &Token) -> AstNode {
+        AstNode::Terminal(token.clone())
+    }
+
+    fn fold_non_terminal(&mut self, info: &NodeInfo, children: Vec<AstNode>) -> AstNode {
+        AstNode::NonTerminal(info.clone(), children)
+    }
+}
+
+impl Fold for StatsFold {
+    type Output = TreeStats;
+
+    fn fold_terminal(&mut self, _token: &Token) -> TreeStats {
+        TreeStats {
+            nodes: 1,
+            leaves: 1,
+            depth: 1,
+            max_branching: 0,
+        }
+    }
+
+    fn fold_non_terminal(&mut self, _info: &NodeInfo, children: Vec<TreeStats>) -> TreeStats {
+        children.iter().fold(
+            TreeStats {
+                nodes: 1,
+                leaves: 0,
+                depth: 1,
+                max_branching: children.len(),
+            },
+            |acc, child| TreeStats {
+                nodes: acc.nodes + child.nodes,
+                leaves: acc.leaves + child.leaves,
+                depth: acc.depth.max(child.depth + 1),
+                max_branching: acc.max_branching.max(child.max_branching),
+            },
+        )
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum QueryError {
+    EmptySegment,
+    InvalidSegment(String),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum QuerySegment {
+    Symbol(char),
+    Any,
+    Descendants,
+}
+
+fn parse_query(path: &str) -> Result<Vec<QuerySegment>, QueryError> {
+    path.split('/')
+        .map(|segment| {
+            let segment = segment.trim();
+            let mut chars = segment.chars();
+            match (segment, chars.next(), chars.next()) {
+                ("", ..) => Err(QueryError::EmptySegment),
+                ("*", ..) => Ok(QuerySegment::Any),
+                ("**", ..) => Ok(QuerySegment::Descendants),
+                (_, Some(symbol), None) => Ok(QuerySegment::Symbol(symbol)),
+                _ => Err(QueryError::InvalidSegment(segment.to_string())),
+            }
+        })
+        .collect()
+}
+
+// （ノード, 次に照合するセグメント）を状態にしてスタックで辿る。
+// 同じ状態は一度しか見ないので、`**` が重なっても同じ部分木を何度も調べない。
+fn query_matches(root: &AstNode, segments: &[QuerySegment]) -> HashSet<*const AstNode> {
+    let mut matched = HashSet::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![(root, 0)];
+    while let Some((node, index)) = stack.pop() {
+        if !seen.insert((std::ptr::from_ref(node), index)) {
+            continue;
+        }
+        let Some(segment) = segments.get(index) else {
+            matched.insert(std::ptr::from_ref(node));
+            continue;
+        };
+        let descend = match segment {
+            QuerySegment::Descendants => {
+                stack.push((node, index + 1));
+                stack.extend(node.children().iter().map(|child| (child, index)));
+                continue;
+            }
+            QuerySegment::Any => true,
+            QuerySegment::Symbol(symbol) => node.symbol() == *symbol,
+        };
+        // 現在のノードが一致したので、残りのセグメントを子へ渡す。
+        if !descend {
+            continue;
+        }
+        if index + 1 == segments.len() {
+            matched.insert(std::ptr::from_ref(node));
+        } else {
+            stack.extend(node.children().iter().map(|child| (child, index + 1)));
+        }
+    }
+    matched
+}
+
+// 深い木でもスタックを使い切らないよう、Clone・比較・表示は再帰せずにたどる。
+impl Clone for AstNode {
+    fn clone(&self) -> Self {
+        self.fold(&mut CloneFold)
+    }
+}
+
+impl PartialEq for AstNode {
+    fn eq(&self, other: &Self) -> bool {
+        let mut pending = vec![(self, other)];
+        while let Some(pair) = pending.pop() {
+            match pair {
+                (Self::Terminal(left), Self::Terminal(right)) if left == right => {}
+                (Self::NonTerminal(left, left_children), Self::NonTerminal(right, right_children))
+                    if left == right && left_children.len() == right_children.len() =>
+                {
+                    pending.extend(left_children.iter().zip(right_children));
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+impl Eq for AstNode {}
+
 impl fmt::Display for AstNode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        print_ast(self, f, 0)
+        let mut stack = vec![(self, 0)];
+        while let Some((node, indent)) = stack.pop() {
+            // 幅指定は u16 までなので、深い木の字下げは 1 段ずつ書く。
+            for _ in 0..indent {
+                f.write_str("    ")?;
+            }
+            writeln!(f, "{}", node.label())?;
+            stack.extend(node.children().iter().rev().map(|child| (child, indent + 1)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1+0 を既定文法でパースしたときの導出木
+    fn sample() -> AstNode {
+        AstNode::non_terminal(
+            'E',
+            vec![
+                AstNode::non_terminal(
+                    'E',
+                    vec![AstNode::non_terminal('B', vec![AstNode::terminal('1')])],
+                ),
+                AstNode::terminal('+'),
+                AstNode::non_terminal('B', vec![AstNode::terminal('0')]),
+            ],
+        )
+    }
+
+    fn labels<'a>(nodes: impl Iterator<Item = &'a AstNode>) -> String {
+        nodes.map(|node| node.symbol()).collect()
+    }
+
+    #[test]
+    fn traversal_orders_visit_every_node() {
+        let ast = sample();
+
+        assert_eq!(labels(ast.pre_order()), "EEB1+B0");
+        assert_eq!(labels(ast.post_order()), "1BE+0BE");
+        assert_eq!(ast.yield_string(), "1+0");
+    }
+
+    #[test]
+    fn stats_report_size_and_shape() {
+        let ast = sample();
+
+        assert_eq!(ast.size(), 7);
+        assert_eq!(ast.depth(), 4);
+        assert_eq!(
+            ast.stats(),
+            TreeStats {
+                nodes: 7,
+                leaves: 3,
+                depth: 4,
+                max_branching: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn visitor_sees_depths_in_walk_order() {
+        struct Depths(Vec<(char, usize)>);
+        impl Visitor for Depths {
+            fn enter(&mut self, node: &AstNode, depth: usize) {
+                self.0.push((node.symbol(), depth));
+            }
+        }
+
+        let mut visitor = Depths(Vec::new());
+        sample().walk(&mut visitor);
+
+        assert_eq!(visitor.0[..3], [('E', 0), ('E', 1), ('B', 2)]);
+    }
+
+    #[test]
+    fn deep_trees_are_walked_without_recursion() {
+        // 1+1+...+1 の導出と同じ、左に深い木
+        let mut ast = AstNode::non_terminal('B', vec![AstNode::terminal('1')]);
+        for _ in 0..200_000 {
+            ast = AstNode::non_terminal('E', vec![ast, AstNode::terminal('+'), AstNode::terminal('1')]);
+        }
+
+        let mut copy = ast.clone();
+        assert!(copy == ast);
+        assert_eq!(ast.depth(), 200_002);
+        assert_eq!(ast.stats().leaves, 400_001);
+        assert_eq!(ast.query("**/E/**/1").unwrap().len(), 200_001);
+        copy.for_each_mut(|node| {
+            node.attributes_mut().insert("seen".to_string(), String::new());
+        });
+        assert!(copy != ast);
+        // 深い木はそのまま drop しても再帰しない。
+        drop(copy);
+        drop(ast);
+    }
+
+    #[test]
+    fn query_matches_paths_from_the_root() {
+        let ast = sample();
+
+        assert_eq!(labels(ast.query("E/B/*").unwrap().into_iter()), "0");
+        assert_eq!(labels(ast.query("E/*").unwrap().into_iter()), "E+B");
+        assert_eq!(labels(ast.query("**/B").unwrap().into_iter()), "BB");
+        assert_eq!(labels(ast.query("**/B/*").unwrap().into_iter()), "10");
+        // 重なった `**` でも同じノードは一度だけ、行きがけ順で返す
+        assert_eq!(labels(ast.query("**/**/*").unwrap().into_iter()), labels(ast.pre_order()));
+        assert_eq!(ast.query("E//B"), Err(QueryError::EmptySegment));
+        assert_eq!(ast.query("E/BB"), Err(QueryError::InvalidSegment("BB".to_string())));
     }
 }
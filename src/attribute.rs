@@ -0,0 +1,569 @@
+use crate::ast::{AstNode, Span};
+use crate::grammar::{Grammar, Production, Symbol};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// 属性の持ち主。`$$` は規則の左辺（自分自身）、`$i` は右辺 i 番目の子。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Position {
+    Parent,
+    Child(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AttrRef {
+    pub position: Position,
+    pub name: String,
+}
+
+impl fmt::Display for AttrRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Position::Parent => write!(f, "$$.{}", self.name),
+            Position::Child(index) => write!(f, "${}.{}", index, self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttrExpr {
+    Number(i64),
+    Ref(AttrRef),
+    Neg(Box<AttrExpr>),
+    Binary(BinOp, Box<AttrExpr>, Box<AttrExpr>),
+}
+
+impl AttrExpr {
+    fn refs<'a>(&'a self, out: &mut Vec<&'a AttrRef>) {
+        match self {
+            Self::Number(_) => {}
+            Self::Ref(reference) => out.push(reference),
+            Self::Neg(operand) => operand.refs(out),
+            Self::Binary(_, left, right) => {
+                left.refs(out);
+                right.refs(out);
+            }
+        }
+    }
+}
+
+/// 属性方程式 `target = value`。target が `$$` なら合成属性、`$i` なら子の継承属性。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Equation {
+    pub target: AttrRef,
+    pub value: AttrExpr,
+}
+
+/// `@` 行の方程式を `;` 区切りで読む。終端記号の子は `$i.val`（綴りを整数として読んだ値）だけを参照できる。
+pub fn parse_equations(text: &str, production: &Production) -> Result<Vec<Equation>, String> {
+    let mut equations: Vec<Equation> = Vec::new();
+    for source in text.split(';').map(str::trim).filter(|source| !source.is_empty()) {
+        let (target, value) = source
+            .split_once('=')
+            .ok_or_else(|| format!("'{}' is not an equation (use: $$.name = ...)", source))?;
+        let target = Parser::new(target.trim()).whole(Parser::reference)?;
+        let value = Parser::new(value.trim()).whole(Parser::sum)?;
+
+        check_position(&target, production)?;
+        if matches!(target.position, Position::Child(index) if is_terminal(production, index)) {
+            return Err(format!("{}: terminals cannot be assigned attributes", target));
+        }
+        if equations.iter().any(|equation| equation.target == target) {
+            return Err(format!("{} is defined twice", target));
+        }
+        let mut refs = Vec::new();
+        value.refs(&mut refs);
+        for reference in refs {
+            check_position(reference, production)?;
+            if matches!(reference.position, Position::Child(index) if is_terminal(production, index))
+                && reference.name != "val"
+            {
+                return Err(format!("{}: terminals only have `val`", reference));
+            }
+        }
+        equations.push(Equation { target, value });
+    }
+    Ok(equations)
+}
+
+fn check_position(reference: &AttrRef, production: &Production) -> Result<(), String> {
+    match reference.position {
+        Position::Child(index) if index >= production.right.len() => Err(format!(
+            "{}: production has only {} symbols",
+            reference,
+            production.right.len()
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn is_terminal(production: &Production, index: usize) -> bool {
+    matches!(production.right.get(index), Some(Symbol::Terminal(_)))
+}
+
+// 方程式の右辺の再帰下降パーサー。空白は読み飛ばす。
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { rest: text }
+    }
+
+    fn whole<T>(mut self, part: fn(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        let value = part(&mut self)?;
+        self.skip_space();
+        if !self.rest.is_empty() {
+            return Err(format!("unexpected '{}'", self.rest));
+        }
+        Ok(value)
+    }
+
+    fn skip_space(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_space();
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn take_while(&mut self, keep: impl Fn(char) -> bool) -> &'a str {
+        let end = self.rest.find(|c| !keep(c)).unwrap_or(self.rest.len());
+        let (taken, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        taken
+    }
+
+    fn reference(&mut self) -> Result<AttrRef, String> {
+        if !self.eat('$') {
+            return Err(format!("expected an attribute like $$.val, found '{}'", self.rest));
+        }
+        let position = if self.eat('$') {
+            Position::Parent
+        } else {
+            let digits = self.take_while(|c| c.is_ascii_digit());
+            Position::Child(digits.parse().map_err(|_| "expected $$ or $<index>".to_string())?)
+        };
+        if !self.eat('.') {
+            return Err("expected '.' after the attribute owner".to_string());
+        }
+        let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        if name.is_empty() {
+            return Err("missing attribute name".to_string());
+        }
+        Ok(AttrRef { position, name: name.to_string() })
+    }
+
+    fn sum(&mut self) -> Result<AttrExpr, String> {
+        let mut left = self.product()?;
+        loop {
+            let op = if self.eat('+') {
+                BinOp::Add
+            } else if self.eat('-') {
+                BinOp::Sub
+            } else {
+                return Ok(left);
+            };
+            left = AttrExpr::Binary(op, Box::new(left), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<AttrExpr, String> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                BinOp::Mul
+            } else if self.eat('/') {
+                BinOp::Div
+            } else if self.eat('%') {
+                BinOp::Mod
+            } else {
+                return Ok(left);
+            };
+            left = AttrExpr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<AttrExpr, String> {
+        if self.eat('-') {
+            return Ok(AttrExpr::Neg(Box::new(self.unary()?)));
+        }
+        if self.eat('(') {
+            let inner = self.sum()?;
+            if !self.eat(')') {
+                return Err("missing ')'".to_string());
+            }
+            return Ok(inner);
+        }
+        self.skip_space();
+        if self.rest.starts_with('$') {
+            return self.reference().map(AttrExpr::Ref);
+        }
+        let digits = self.take_while(|c| c.is_ascii_digit());
+        digits
+            .parse()
+            .map(AttrExpr::Number)
+            .map_err(|_| format!("expected a number or an attribute, found '{}'", self.rest))
+    }
+}
+
+/// 生成規則 id ごとの属性方程式。番号付けは `LoweringRules` と同じ。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeRules {
+    equations: Vec<Vec<Equation>>,
+}
+
+impl AttributeRules {
+    pub fn from_grammar(grammar: &Grammar) -> Self {
+        let mut equations = vec![Vec::new()];
+        equations.extend(grammar.attributes.iter().cloned());
+        Self { equations }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.equations.iter().all(Vec::is_empty)
+    }
+
+    pub fn equations(&self, production: usize) -> &[Equation] {
+        self.equations.get(production).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+/// 木の上の属性 1 つ（どのノードのどの属性か）。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeInstance {
+    pub symbol: char,
+    pub attribute: String,
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for AttributeInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.symbol, self.attribute)?;
+        if let Some(span) = self.span {
+            write!(f, " at {}..{}", span.start, span.end)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeError {
+    /// 参照されたが、どの方程式も値を決めていない。
+    Undefined(AttributeInstance),
+    /// 合成属性と継承属性の両方で値が決められている。
+    Redefined(AttributeInstance),
+    /// `$i.val` の終端記号の綴りが整数ではない。
+    NotANumber { symbol: char, span: Option<Span> },
+    /// 循環する依存。先頭の属性は末尾の属性に依存する。
+    Circular(Vec<AttributeInstance>),
+    DivisionByZero(AttributeInstance),
+    Overflow(AttributeInstance),
+}
+
+impl fmt::Display for AttributeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Undefined(instance) => write!(f, "{} is used but never defined", instance),
+            Self::Redefined(instance) => {
+                write!(f, "{} is defined both by its own production and by its parent", instance)
+            }
+            Self::NotANumber { symbol, span } => {
+                write!(f, "'{}' has no integer value", symbol)?;
+                if let Some(span) = span {
+                    write!(f, " at {}..{}", span.start, span.end)?;
+                }
+                Ok(())
+            }
+            Self::Circular(cycle) => {
+                let names: Vec<String> = cycle.iter().map(ToString::to_string).collect();
+                write!(f, "circular attribute dependency: {} -> {}", names.join(" -> "), names[0])
+            }
+            Self::DivisionByZero(instance) => write!(f, "division by zero while computing {}", instance),
+            Self::Overflow(instance) => write!(f, "integer overflow while computing {}", instance),
+        }
+    }
+}
+
+// 先行順で番号を振ったノード。
+struct Flat<'a> {
+    node: &'a AstNode,
+    children: Vec<usize>,
+}
+
+type Key = (usize, String);
+
+// 属性の値を決める方程式と、それを持つ規則で還元されたノード。
+struct Source<'a> {
+    owner: usize,
+    equation: &'a Equation,
+}
+
+/// 具象構文木の属性を依存順に計算し、値をノードの `attributes` に書き込んだ木を返す。
+/// 値は方程式を持つ属性と、それに必要な属性だけが計算される。
+/// 循環はこの木の上の依存だけで調べるので、文法に循環しうる規則があっても、この入力で閉じなければエラーにならない。
+pub fn decorate(ast: &AstNode, rules: &AttributeRules) -> Result<AstNode, AttributeError> {
+    let nodes = flatten(ast);
+
+    let mut sources: HashMap<Key, Source> = HashMap::new();
+    let mut order: Vec<Key> = Vec::new();
+    for (index, flat) in nodes.iter().enumerate() {
+        let AstNode::NonTerminal(info, _) = flat.node else { continue };
+        let Some(production) = info.production else { continue };
+        for equation in rules.equations(production) {
+            let key = (resolve(&nodes, index, equation.target.position), equation.target.name.clone());
+            if sources.contains_key(&key) {
+                return Err(AttributeError::Redefined(instance(&nodes, &key)));
+            }
+            order.push(key.clone());
+            sources.insert(key, Source { owner: index, equation });
+        }
+    }
+
+    // 依存辺を張る。終端記号の `val` はその場で値にしておく。
+    let mut constants: HashMap<Key, i64> = HashMap::new();
+    let mut dependents: HashMap<Key, Vec<Key>> = HashMap::new();
+    let mut pending: HashMap<Key, usize> = HashMap::new();
+    for key in &order {
+        let Source { owner, equation } = &sources[key];
+        let mut refs = Vec::new();
+        equation.value.refs(&mut refs);
+        let mut count = 0;
+        for reference in refs {
+            let dependency = (resolve(&nodes, *owner, reference.position), reference.name.clone());
+            if let AstNode::Terminal(token) = nodes[dependency.0].node {
+                let value = token
+                    .lexeme
+                    .parse()
+                    .map_err(|_| AttributeError::NotANumber { symbol: token.symbol, span: token.span })?;
+                constants.insert(dependency, value);
+                continue;
+            }
+            if !sources.contains_key(&dependency) {
+                return Err(AttributeError::Undefined(instance(&nodes, &dependency)));
+            }
+            dependents.entry(dependency).or_default().push(key.clone());
+            count += 1;
+        }
+        pending.insert(key.clone(), count);
+    }
+
+    // Kahn の方法で依存順に計算する。
+    let mut values = constants;
+    let mut ready: Vec<Key> = order.iter().filter(|key| pending[*key] == 0).cloned().collect();
+    while let Some(key) = ready.pop() {
+        let Source { owner, equation } = &sources[&key];
+        let value = evaluate(&equation.value, &nodes, *owner, &values)
+            .map_err(|overflow| {
+                let instance = instance(&nodes, &key);
+                if overflow { AttributeError::Overflow(instance) } else { AttributeError::DivisionByZero(instance) }
+            })?;
+        values.insert(key.clone(), value);
+        for dependent in dependents.get(&key).into_iter().flatten() {
+            let count = pending.get_mut(dependent).expect("every dependent has a pending count");
+            *count -= 1;
+            if *count == 0 {
+                ready.push(dependent.clone());
+            }
+        }
+    }
+
+    if let Some(start) = order.iter().find(|key| !values.contains_key(*key)) {
+        return Err(AttributeError::Circular(cycle_from(start, &sources, &nodes, &values)));
+    }
+
+    let mut by_node: Vec<BTreeMap<String, i64>> = vec![BTreeMap::new(); nodes.len()];
+    for key in order {
+        let value = values[&key];
+        by_node[key.0].insert(key.1, value);
+    }
+    // `flatten` と同じ行きがけ順に値を書き込む。
+    let mut decorated = ast.clone();
+    let mut values = by_node.into_iter();
+    decorated.for_each_mut(|node| {
+        let values = values.next().expect("one entry per flattened node");
+        if let AstNode::NonTerminal(info, _) = node {
+            for (name, value) in values {
+                info.attributes.insert(name, value.to_string());
+            }
+        }
+    });
+    Ok(decorated)
+}
+
+fn flatten(ast: &AstNode) -> Vec<Flat<'_>> {
+    let mut nodes = Vec::new();
+    let mut stack: Vec<(&AstNode, Option<usize>)> = vec![(ast, None)];
+    while let Some((node, parent)) = stack.pop() {
+        let index = nodes.len();
+        nodes.push(Flat { node, children: Vec::new() });
+        if let Some(parent) = parent {
+            nodes[parent].children.push(index);
+        }
+        if let AstNode::NonTerminal(_, children) = node {
+            stack.extend(children.iter().rev().map(|child| (child, Some(index))));
+        }
+    }
+    nodes
+}
+
+fn resolve(nodes: &[Flat], owner: usize, position: Position) -> usize {
+    match position {
+        Position::Parent => owner,
+        Position::Child(index) => nodes[owner].children[index],
+    }
+}
+
+fn instance(nodes: &[Flat], (node, attribute): &Key) -> AttributeInstance {
+    let (symbol, span) = match nodes[*node].node {
+        AstNode::Terminal(token) => (token.symbol, token.span),
+        AstNode::NonTerminal(info, _) => (info.symbol, info.span),
+    };
+    AttributeInstance { symbol, attribute: attribute.clone(), span }
+}
+
+// 値の決まらなかった属性から、まだ決まっていない依存をたどって一周するまで進む。
+fn cycle_from(
+    start: &Key,
+    sources: &HashMap<Key, Source>,
+    nodes: &[Flat],
+    values: &HashMap<Key, i64>,
+) -> Vec<AttributeInstance> {
+    let mut path: Vec<Key> = vec![start.clone()];
+    loop {
+        let current = path.last().expect("path starts non-empty");
+        let Source { owner, equation } = &sources[current];
+        let mut refs = Vec::new();
+        equation.value.refs(&mut refs);
+        let next = refs
+            .into_iter()
+            .map(|reference| (resolve(nodes, *owner, reference.position), reference.name.clone()))
+            .find(|key| sources.contains_key(key) && !values.contains_key(key))
+            .expect("an undecided attribute waits on another undecided attribute");
+        if let Some(position) = path.iter().position(|key| *key == next) {
+            return path[position..].iter().map(|key| instance(nodes, key)).collect();
+        }
+        path.push(next);
+    }
+}
+
+// Err(true) は桁あふれ、Err(false) は 0 除算。
+fn evaluate(expr: &AttrExpr, nodes: &[Flat], owner: usize, values: &HashMap<Key, i64>) -> Result<i64, bool> {
+    match expr {
+        AttrExpr::Number(value) => Ok(*value),
+        AttrExpr::Ref(reference) => {
+            Ok(values[&(resolve(nodes, owner, reference.position), reference.name.clone())])
+        }
+        AttrExpr::Neg(operand) => evaluate(operand, nodes, owner, values)?.checked_neg().ok_or(true),
+        AttrExpr::Binary(op, left, right) => {
+            let left = evaluate(left, nodes, owner, values)?;
+            let right = evaluate(right, nodes, owner, values)?;
+            if matches!(op, BinOp::Div | BinOp::Mod) && right == 0 {
+                return Err(false);
+            }
+            match op {
+                BinOp::Add => left.checked_add(right),
+                BinOp::Sub => left.checked_sub(right),
+                BinOp::Mul => left.checked_mul(right),
+                BinOp::Div => left.checked_div(right),
+                BinOp::Mod => left.checked_rem(right),
+            }
+            .ok_or(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{GrammarError, parse_grammar_text, parse_input_text};
+    use crate::lr::compile;
+    use crate::runtime::run;
+
+    fn decorated(grammar: &str, input: &str) -> Result<AstNode, AttributeError> {
+        let grammar = parse_grammar_text(grammar).unwrap();
+        let machine = compile(&grammar).unwrap();
+        let ast = run(&machine, &parse_input_text(input).unwrap()).unwrap().ast;
+        decorate(&ast, &AttributeRules::from_grammar(&grammar))
+    }
+
+    fn attribute(node: &AstNode, name: &str) -> Option<String> {
+        match node {
+            AstNode::NonTerminal(info, _) => info.attributes.get(name).cloned(),
+            AstNode::Terminal(_) => None,
+        }
+    }
+
+    #[test]
+    fn synthesized_attributes_compute_values() {
+        let grammar = "E -> E+B\n@ $$.val = $0.val + $2.val\nE -> E*B\n@ $$.val = $0.val * $2.val\n\
+                       E -> B\n@ $$.val = $0.val\nB -> 1\n@ $$.val = $0.val\nB -> 2\n@ $$.val = $0.val";
+        let ast = decorated(grammar, "1+2*2").unwrap();
+        assert_eq!(attribute(&ast, "val").as_deref(), Some("6"));
+    }
+
+    #[test]
+    fn inherited_attributes_flow_down_the_tree() {
+        // 各 B に、左から数えた位置を継承属性 index として渡す。
+        let grammar = "S -> L;\n@ $0.start = 0; $$.count = $0.count\n\
+                       L -> LB\n@ $0.start = $$.start; $1.index = $$.start + $0.count; $$.count = $0.count + 1\n\
+                       L -> B\n@ $0.index = $$.start; $$.count = 1\n\
+                       B -> 1\n@ $$.pos = $$.index * 10 + $0.val";
+        let ast = decorated(grammar, "111;").unwrap();
+        assert_eq!(attribute(&ast, "count").as_deref(), Some("3"));
+
+        let AstNode::NonTerminal(_, children) = &ast else { panic!() };
+        let AstNode::NonTerminal(_, list) = &children[0] else { panic!() };
+        assert_eq!(attribute(&list[1], "pos").as_deref(), Some("21"));
+    }
+
+    #[test]
+    fn circular_and_undefined_attributes_are_reported() {
+        let circular = "S -> A\n@ $0.down = $0.up; $$.val = $0.up\nA -> 1\n@ $$.up = $$.down";
+        match decorated(circular, "1") {
+            Err(AttributeError::Circular(cycle)) => {
+                let names: Vec<&str> = cycle.iter().map(|instance| instance.attribute.as_str()).collect();
+                assert_eq!(cycle.len(), 2);
+                assert!(names.contains(&"up") && names.contains(&"down"), "{:?}", names);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let undefined = "S -> A\n@ $$.val = $0.val\nA -> 1";
+        assert!(matches!(
+            decorated(undefined, "1"),
+            Err(AttributeError::Undefined(AttributeInstance { symbol: 'A', .. }))
+        ));
+    }
+
+    #[test]
+    fn equations_are_checked_against_their_production() {
+        for bad in [
+            "S -> 1\n@ $1.val = 0",
+            "S -> 1\n@ $0.val = 2",
+            "S -> 1\n@ $$.val = $0.len",
+            "S -> A\n@ $$.val = 1; $$.val = 2\nA -> 1",
+            "@ $$.val = 1\nS -> 1",
+            "S -> 1\n@ $$.val = (1",
+        ] {
+            assert!(matches!(parse_grammar_text(bad), Err(GrammarError::InvalidAttribute(_))), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use crate::evaluator::{Environment, EvalError, Expr, apply, apply_unary};
+use crate::roles::Operation;
+use lr0_parser_rs::ast::Span;
+use std::fmt;
+
+/// スタックマシンの命令。演算は被演算子をスタックから取り、結果を積む。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Push(i64),
+    /// 環境から変数の値を積む。
+    Load(String, Option<Span>),
+    Unary(Operation, Option<Span>),
+    Binary(Operation, Option<Span>),
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Push(value) => write!(f, "push {}", value),
+            Self::Load(name, _) => write!(f, "load {}", name),
+            Self::Unary(operation, _) | Self::Binary(operation, _) => write!(f, "{}", operation.key()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program {
+    pub code: Vec<Instruction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+    /// `pc` 番目の命令で演算が失敗した。
+    Eval { pc: usize, error: EvalError },
+    StackUnderflow { pc: usize },
+    /// 実行を終えたときにスタックに残った値の数が 1 ではない。
+    Unbalanced { depth: usize },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Eval { pc, error } => write!(f, "{:04}: {}", pc, error),
+            Self::StackUnderflow { pc } => write!(f, "{:04}: stack underflow", pc),
+            Self::Unbalanced { depth } => write!(f, "program left {} values on the stack", depth),
+        }
+    }
+}
+
+/// 実行結果と、そのとき使ったスタックの深さの最大値。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Execution {
+    pub value: i64,
+    pub max_depth: usize,
+}
+
+impl Program {
+    /// 式を後行順にたどって命令列にする。被演算子を積んでから演算を置く。
+    pub fn compile(expr: &Expr) -> Self {
+        let mut code = Vec::new();
+        emit(expr, &mut code);
+        Self { code }
+    }
+
+    pub fn run(&self, environment: &Environment) -> Result<Execution, VmError> {
+        let mut stack: Vec<i64> = Vec::new();
+        let mut max_depth = 0;
+        for (pc, instruction) in self.code.iter().enumerate() {
+            let pop = |stack: &mut Vec<i64>| stack.pop().ok_or(VmError::StackUnderflow { pc });
+            let value = match *instruction {
+                Instruction::Push(value) => value,
+                Instruction::Load(ref name, span) => environment.get(name).copied().ok_or_else(|| VmError::Eval {
+                    pc,
+                    error: EvalError::Unbound { name: name.clone(), span },
+                })?,
+                Instruction::Unary(operation, span) => {
+                    let operand = pop(&mut stack)?;
+                    apply_unary(operation, operand, span).map_err(|error| VmError::Eval { pc, error })?
+                }
+                Instruction::Binary(operation, span) => {
+                    let right = pop(&mut stack)?;
+                    let left = pop(&mut stack)?;
+                    apply(operation, left, right, span).map_err(|error| VmError::Eval { pc, error })?
+                }
+            };
+            stack.push(value);
+            max_depth = max_depth.max(stack.len());
+        }
+        match stack.as_slice() {
+            [value] => Ok(Execution { value: *value, max_depth }),
+            _ => Err(VmError::Unbalanced { depth: stack.len() }),
+        }
+    }
+
+    /// 1 行 1 命令の逆アセンブル。演算と変数には元になった終端記号の位置を添える。
+    pub fn disassemble(&self) -> String {
+        let mut text = String::new();
+        for (pc, instruction) in self.code.iter().enumerate() {
+            let line = format!("{:04}  {}", pc, instruction);
+            match instruction {
+                Instruction::Load(_, Some(span))
+                | Instruction::Unary(_, Some(span))
+                | Instruction::Binary(_, Some(span)) => {
+                    text.push_str(&format!("{:<20}; {}..{}\n", line, span.start, span.end));
+                }
+                _ => {
+                    text.push_str(&line);
+                    text.push('\n');
+                }
+            }
+        }
+        text
+    }
+}
+
+fn emit(expr: &Expr, code: &mut Vec<Instruction>) {
+    match expr {
+        Expr::Number(value) => code.push(Instruction::Push(*value)),
+        Expr::Variable { name, span } => code.push(Instruction::Load(name.clone(), *span)),
+        Expr::Unary { operation, operand, span, .. } => {
+            emit(operand, code);
+            code.push(Instruction::Unary(*operation, *span));
+        }
+        Expr::Binary { operation, left, right, span, .. } => {
+            emit(left, code);
+            emit(right, code);
+            code.push(Instruction::Binary(*operation, *span));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::expression;
+    use crate::roles::{RoleRegistry, TerminalRole};
+    use lr0_parser_rs::grammar::{parse_grammar_text, parse_input_text};
+    use lr0_parser_rs::lr::compile;
+    use lr0_parser_rs::runtime::run;
+
+    const ARITHMETIC: &str = "E -> E+B\nE -> E-B\nE -> E*B\nE -> E/B\nE -> B\n\
+                              B -> (E)\nB -> -B\nB -> 0\nB -> 1\nB -> 2\nB -> 7";
+
+    fn compiled(input: &str) -> (Program, Result<i64, EvalError>) {
+        let machine = compile(&parse_grammar_text(ARITHMETIC).unwrap()).unwrap();
+        let ast = run(&machine, &parse_input_text(input).unwrap()).unwrap().ast;
+        let registry = RoleRegistry::default();
+        let expr = expression(&ast, &registry, &TerminalRole::default_for).unwrap();
+        (Program::compile(&expr), expr.value(&Environment::new()))
+    }
+
+    #[test]
+    fn programs_are_postfix_and_agree_with_the_evaluator() {
+        let (program, value) = compiled("1+2*7");
+        assert_eq!(
+            program.disassemble(),
+            "0000  push 1\n\
+             0001  push 2\n\
+             0002  add           ; 1..2\n\
+             0003  push 7\n\
+             0004  mul           ; 3..4\n"
+        );
+        assert_eq!(program.run(&Environment::new()), Ok(Execution { value: 21, max_depth: 2 }));
+        assert_eq!(value, Ok(21));
+
+        let (program, value) = compiled("7-(2-1)*-2");
+        assert_eq!(program.run(&Environment::new()).map(|execution| execution.value).ok(), value.ok());
+        assert_eq!(program.run(&Environment::new()).unwrap().max_depth, 3);
+    }
+
+    #[test]
+    fn runtime_errors_point_at_the_instruction() {
+        let (program, value) = compiled("7/(1-1)");
+        assert_eq!(
+            program.run(&Environment::new()),
+            Err(VmError::Eval { pc: 4, error: value.unwrap_err() })
+        );
+
+        let broken = Program { code: vec![Instruction::Push(1), Instruction::Binary(Operation::Add, None)] };
+        assert_eq!(broken.run(&Environment::new()), Err(VmError::StackUnderflow { pc: 1 }));
+        let leftover = Program { code: vec![Instruction::Push(1), Instruction::Push(2)] };
+        assert_eq!(leftover.run(&Environment::new()), Err(VmError::Unbalanced { depth: 2 }));
+
+        let load = Program { code: vec![Instruction::Load("x".to_string(), Some(Span::new(0, 1)))] };
+        assert_eq!(load.disassemble(), "0000  load x        ; 0..1\n");
+        assert_eq!(load.run(&Environment::from([("x".to_string(), 3)])).map(|execution| execution.value), Ok(3));
+        assert_eq!(
+            load.run(&Environment::new()),
+            Err(VmError::Eval {
+                pc: 0,
+                error: EvalError::Unbound { name: "x".to_string(), span: Some(Span::new(0, 1)) },
+            })
+        );
+    }
+}
@@ -0,0 +1,444 @@
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use lr0_parser_rs::codegen::rust::{RustOptions, RustTarget};
+use lr0_parser_rs::codegen::{self, Target};
+use lr0_parser_rs::corpus::{CorpusError, load_corpus, run_corpus};
+use lr0_parser_rs::grammar::{self, Grammar};
+use lr0_parser_rs::lower::{LoweringRules, lower};
+use lr0_parser_rs::lr::{self, CompiledParser};
+use lr0_parser_rs::runtime::{self, format_step};
+use lr0_parser_rs::packed::encoding_sizes;
+use lr0_parser_rs::serialize::{to_json_string, to_sexpr_string};
+use lr0_parser_rs::table::{TableFormat, build_parse_table, export_table};
+
+const USAGE: &str = "\
+usage: lr0-parser <command> [options]
+
+commands:
+  check <grammar>                 文法の統計と LR(0) 衝突を表示する
+  table <grammar>                 action/goto 表を表示する
+  parse <grammar> <input>         入力をパースして木を表示する
+  trace <grammar> <input>         入力のパース過程を1ステップずつ表示する
+  batch <grammar> <corpus>        コーパス（ファイルまたはディレクトリ）を一括でパースする
+  generate <grammar>              このクレートに依存しないパーサーのソースを出力する
+
+options:
+  --format text|json|sexpr        parse/trace の出力形式（既定: text）
+  --format markdown|csv|html|latex
+                                  table を文書向けの表と生成規則の凡例で出力する
+  --lower                         parse の木に文法の木整形ディレクティブを適用する
+  --sizes                         table の代わりに詰めた表現ごとの大きさを表示する（--format とは併用できない）
+  --lang rust|c|python            generate の出力言語（既定: rust）
+  --main                          generate (rust) の出力に入力を読んで木を表示する main を付ける
+
+オプションはそれを使うコマンドにしか渡せない。
+
+<input> に `-` を渡すと標準入力から読む。
+コーパスは1行1入力で、`+ <input>` が受理、`- <input>` が拒否を期待する。`#` 行は無視する。
+
+exit codes:
+  0  成功
+  1  衝突がある / 入力が受理されなかった / コーパスに期待外れがある
+  2  引数が不正
+  3  ファイルを読めない / 文法が不正";
+
+const EXIT_REJECTED: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_INPUT: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+    SExpr,
+    Table(TableFormat),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Check { grammar: String },
+    Table { grammar: String, sizes: bool, format: Format },
+    Parse { grammar: String, input: String, format: Format, lower: bool },
+    Trace { grammar: String, input: String, format: Format },
+    Batch { grammar: String, corpus: String },
+    Generate { grammar: String, lang: String, with_main: bool },
+    Help,
+}
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Io(String, std::io::Error),
+    Grammar(grammar::GrammarError),
+    Parser(lr::ParserError),
+    Rejected(runtime::RuntimeError),
+    Conflicts(usize),
+    Corpus(CorpusError),
+    Failures(usize),
+}
+
+impl CliError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Self::Usage(_) => EXIT_USAGE,
+            Self::Io(..) | Self::Grammar(_) | Self::Parser(_) | Self::Corpus(_) => EXIT_INPUT,
+            Self::Rejected(_) | Self::Conflicts(_) | Self::Failures(_) => EXIT_REJECTED,
+        }
+    }
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            Self::Io(path, error) => write!(f, "cannot read {}: {}", path, error),
+            Self::Grammar(error) => write!(f, "invalid grammar: {:?}", error),
+            Self::Parser(error) => write!(f, "cannot build parser: {:?}", error),
+            Self::Rejected(error) => write!(f, "input rejected: {:?}", error),
+            Self::Conflicts(count) => write!(f, "grammar is not LR(0): {} conflict(s)", count),
+            Self::Corpus(error) => write!(f, "{}", error),
+            Self::Failures(count) => write!(f, "{} corpus case(s) did not match the expectation", count),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match parse_args(&args).and_then(execute) {
+        Ok(output) => {
+            print!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::from(error.exit_code())
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Command, CliError> {
+    let mut positional = Vec::new();
+    let mut format = Format::Text;
+    let mut lower = false;
+    let mut with_main = false;
+    let mut sizes = false;
+    let mut lang = "rust".to_string();
+    let mut options = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if matches!(arg.as_str(), "--lower" | "--main" | "--sizes" | "--lang" | "--format") {
+            options.push(arg.as_str());
+        }
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--lower" => lower = true,
+            "--main" => with_main = true,
+            "--sizes" => sizes = true,
+            "--lang" => {
+                lang = iter
+                    .next()
+                    .ok_or_else(|| CliError::Usage("--lang needs a value".to_string()))?
+                    .clone();
+                if codegen::target(&lang).is_none() {
+                    return Err(CliError::Usage(format!("unknown language `{}`", lang)));
+                }
+            }
+            "--format" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| CliError::Usage("--format needs a value".to_string()))?;
+                format = match value.as_str() {
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    "sexpr" => Format::SExpr,
+                    other => match TableFormat::from_name(other) {
+                        Some(table) => Format::Table(table),
+                        None => return Err(CliError::Usage(format!("unknown format `{}`", other))),
+                    },
+                };
+            }
+            "-" => positional.push(arg.clone()),
+            flag if flag.starts_with('-') => {
+                return Err(CliError::Usage(format!("unknown option `{}`", flag)));
+            }
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let Some((command, rest)) = positional.split_first() else {
+        return Ok(Command::Help);
+    };
+    let (expected, accepted): (usize, &[&str]) = match command.as_str() {
+        "help" => (0, &[]),
+        "check" => (1, &[]),
+        "table" => (1, &["--format", "--sizes"]),
+        "generate" => (1, &["--lang", "--main"]),
+        "parse" => (2, &["--format", "--lower"]),
+        "trace" => (2, &["--format"]),
+        "batch" => (2, &[]),
+        other => return Err(CliError::Usage(format!("unknown command `{}`", other))),
+    };
+    // ほかのコマンドのオプションを黙って無視しない
+    if let Some(option) = options.iter().find(|option| !accepted.contains(option)) {
+        return Err(CliError::Usage(format!("`{}` does not take {}", command, option)));
+    }
+    if rest.len() != expected {
+        return Err(CliError::Usage(format!(
+            "`{}` takes {} argument(s), got {}",
+            command,
+            expected,
+            rest.len()
+        )));
+    }
+
+    let accepts_format = match format {
+        Format::Text => true,
+        Format::Json | Format::SExpr => matches!(command.as_str(), "parse" | "trace"),
+        Format::Table(_) => command == "table",
+    };
+    if !accepts_format {
+        return Err(CliError::Usage(format!("`{}` does not support this --format", command)));
+    }
+    if sizes && format != Format::Text {
+        return Err(CliError::Usage("--sizes cannot be combined with --format".to_string()));
+    }
+    // C と Python の出力はいつも main を持つ
+    if with_main && lang != "rust" {
+        return Err(CliError::Usage("--main only applies to --lang rust".to_string()));
+    }
+
+    let grammar = rest.first().cloned().unwrap_or_default();
+    Ok(match command.as_str() {
+        "check" => Command::Check { grammar },
+        "table" => Command::Table { grammar, sizes, format },
+        "generate" => Command::Generate { grammar, lang, with_main },
+        "parse" => Command::Parse { grammar, input: rest[1].clone(), format, lower },
+        "trace" => Command::Trace { grammar, input: rest[1].clone(), format },
+        "batch" => Command::Batch { grammar, corpus: rest[1].clone() },
+        _ => Command::Help,
+    })
+}
+
+fn execute(command: Command) -> Result<String, CliError> {
+    match command {
+        Command::Help => Ok(format!("{}\n", USAGE)),
+        Command::Check { grammar } => check(&load_grammar(&grammar)?),
+        Command::Table { grammar, sizes, format } => {
+            let grammar = load_grammar(&grammar)?;
+            let machine = compile(&grammar)?;
+            if sizes {
+                return Ok(encoding_sizes(&machine)
+                    .iter()
+                    .map(|size| format!("{}\n", size))
+                    .collect());
+            }
+            if let Format::Table(format) = format {
+                let (symbols, table) = build_parse_table(&grammar, &machine);
+                return Ok(export_table(&symbols, &table, machine.productions(), format));
+            }
+            Ok(render_table(&grammar, &machine))
+        }
+        Command::Parse { grammar, input, format, lower: apply_lower } => {
+            let grammar = load_grammar(&grammar)?;
+            let machine = compile(&grammar)?;
+            let symbols = read_input(&input)?;
+            let result = runtime::run(&machine, &symbols).map_err(CliError::Rejected)?;
+            let ast = if apply_lower {
+                lower(&result.ast, &LoweringRules::from_grammar(&grammar))
+            } else {
+                result.ast
+            };
+            let output = match format {
+                Format::Text => ast.to_string(),
+                Format::Json => format!("{}\n", to_json_string(&ast)),
+                Format::SExpr => format!("{}\n", to_sexpr_string(&ast)),
+                Format::Table(_) => unreachable!("rejected by parse_args"),
+            };
+            Ok(output)
+        }
+        Command::Trace { grammar, input, format } => {
+            let grammar = load_grammar(&grammar)?;
+            let machine = compile(&grammar)?;
+            let symbols = read_input(&input)?;
+            let trace = runtime::trace(&machine, &symbols).map_err(CliError::Rejected)?;
+            Ok(match format {
+                // テキストは木を見ないので、木の写しを作らずに1手ずつ書き出す。
+                Format::Text => {
+                    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+                    for (index, step) in trace.steps_without_trees().enumerate() {
+                        if stdout.write_all(format_step(index + 1, &step).as_bytes()).is_err() {
+                            break;
+                        }
+                    }
+                    String::new()
+                }
+                Format::Json => format!("{}\n", to_json_string(&trace.steps().collect::<Vec<_>>())),
+                Format::SExpr => format!("{}\n", to_sexpr_string(&trace.steps().collect::<Vec<_>>())),
+                Format::Table(_) => unreachable!("rejected by parse_args"),
+            })
+        }
+        Command::Generate { grammar, lang, with_main } => {
+            let machine = compile(&load_grammar(&grammar)?)?;
+            let target: Box<dyn Target> = match codegen::target(&lang) {
+                Some(target) if target.name() == "rust" => {
+                    Box::new(RustTarget { options: RustOptions { with_main } })
+                }
+                Some(target) => target,
+                None => return Err(CliError::Usage(format!("unknown language `{}`", lang))),
+            };
+            Ok(codegen::emit(target.as_ref(), &machine))
+        }
+        Command::Batch { grammar, corpus } => {
+            let machine = compile(&load_grammar(&grammar)?)?;
+            let cases = load_corpus(Path::new(&corpus)).map_err(CliError::Corpus)?;
+            let report = run_corpus(&machine, &cases);
+            if report.all_passed() {
+                return Ok(report.to_string());
+            }
+            print!("{}", report);
+            Err(CliError::Failures(report.failures().count()))
+        }
+    }
+}
+
+fn load_grammar(path: &str) -> Result<Grammar, CliError> {
+    let text = grammar::read_file(path).map_err(|error| CliError::Io(path.to_string(), error))?;
+    grammar::parse_grammar_text(&text).map_err(CliError::Grammar)
+}
+
+fn read_input(argument: &str) -> Result<Vec<grammar::Symbol>, CliError> {
+    let text = if argument == "-" {
+        let mut buffer = String::new();
+        std::io::stdin()
+            .read_to_string(&mut buffer)
+            .map_err(|error| CliError::Io("<stdin>".to_string(), error))?;
+        buffer.trim_end_matches(['\r', '\n']).to_string()
+    } else {
+        argument.to_string()
+    };
+    grammar::parse_input_text(&text).map_err(CliError::Grammar)
+}
+
+fn compile(grammar: &Grammar) -> Result<CompiledParser, CliError> {
+    match lr::compile(grammar) {
+        Err(lr::ParserError::ConflictReducer) => {
+            let conflicts = lr::conflicts(grammar).map_err(CliError::Parser)?;
+            Err(CliError::Conflicts(conflicts.len()))
+        }
+        other => other.map_err(CliError::Parser),
+    }
+}
+
+fn check(grammar: &Grammar) -> Result<String, CliError> {
+    let terminals: String = grammar.terminals().iter().map(|terminal| terminal.0).collect();
+    let non_terminals: String = grammar
+        .non_terminals()
+        .iter()
+        .map(|non_terminal| non_terminal.0)
+        .collect();
+
+    let mut output = String::new();
+    output.push_str(&format!("productions:   {}\n", grammar.productions.len()));
+    output.push_str(&format!("terminals:     {}\n", terminals));
+    output.push_str(&format!("non-terminals: {}\n", non_terminals));
+
+    let conflicts = lr::conflicts(grammar).map_err(CliError::Parser)?;
+    if !conflicts.is_empty() {
+        // 衝突の一覧は標準出力に出し、終了コードで失敗を知らせる。
+        for conflict in &conflicts {
+            output.push_str(&format!("{}\n", conflict));
+        }
+        print!("{}", output);
+        return Err(CliError::Conflicts(conflicts.len()));
+    }
+
+    let machine = compile(grammar)?;
+    output.push_str(&format!("states:        {}\n", machine.state_count()));
+    output.push_str("conflicts:     none\n");
+    Ok(output)
+}
+
+fn render_table(grammar: &Grammar, machine: &CompiledParser) -> String {
+    let (symbols, table) = build_parse_table(grammar, machine);
+    let cells: Vec<Vec<String>> = table
+        .iter()
+        .map(|row| row.iter().map(|cell| cell.as_label()).collect())
+        .collect();
+    let width = cells
+        .iter()
+        .flatten()
+        .map(String::len)
+        .max()
+        .unwrap_or(0)
+        .max(3);
+
+    let mut output = format!("{:>5} |", "state");
+    for symbol in &symbols {
+        output.push_str(&format!(" {:>width$}", symbol, width = width));
+    }
+    output.push('\n');
+    output.push_str(&"-".repeat(7 + symbols.len() * (width + 1)));
+    output.push('\n');
+    for (state, row) in cells.iter().enumerate() {
+        output.push_str(&format!("{:>5} |", state));
+        for cell in row {
+            output.push_str(&format!(" {:>width$}", cell, width = width));
+        }
+        output.truncate(output.trim_end().len());
+        output.push('\n');
+    }
+
+    output.push('\n');
+    for (id, production) in machine.productions().iter().enumerate() {
+        output.push_str(&format!("r{}: {}\n", id, production));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn options_may_appear_anywhere() {
+        assert_eq!(
+            parse_args(&args("parse --format sexpr reducer 1+0 --lower")).unwrap(),
+            Command::Parse {
+                grammar: "reducer".to_string(),
+                input: "1+0".to_string(),
+                format: Format::SExpr,
+                lower: true,
+            }
+        );
+    }
+
+    #[test]
+    fn bad_arguments_are_usage_errors() {
+        for line in ["frobnicate g", "check", "parse g", "trace g 1 --format xml", "table g --fast",
+            "parse g 1 --format csv", "table g --format json"] {
+            let error = parse_args(&args(line)).unwrap_err();
+            assert_eq!(error.exit_code(), EXIT_USAGE, "{}", line);
+        }
+    }
+
+    #[test]
+    fn table_lists_every_state_and_the_productions() {
+        let grammar = grammar::parse_grammar_text(include_str!("../reducer")).unwrap();
+        let machine = lr::compile(&grammar).unwrap();
+        let output = render_table(&grammar, &machine);
+
+        assert!(output.starts_with("state |"));
+        assert!(output.contains("r5: B -> 1"));
+        assert_eq!(
+            output.lines().filter(|line| line.contains(" |")).count(),
+            machine.state_count() + 1
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use super::{Target, lines, prefix_lines};
+use crate::lr::{Action, LrTable};
+use crate::packed::DenseTable;
+
+/// 表と駆動ループを1つの `.c` ファイルにまとめる。`LR_PARSER_MAIN` を定義すると実行可能になる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CTarget;
+
+impl Target for CTarget {
+    fn name(&self) -> &'static str {
+        "c"
+    }
+
+    fn extension(&self) -> &'static str {
+        "c"
+    }
+
+    // 行末の `\` が次の行を巻き込まないよう、行コメントではなくブロックコメントにする。
+    fn comment(&self, text: &str) -> String {
+        format!("/*\n{} */\n", prefix_lines(" *", &text.replace("*/", "* /")))
+    }
+
+    fn template(&self) -> &'static str {
+        include_str!("templates/parser.c.tmpl")
+    }
+
+    fn bindings(&self, tables: &DenseTable) -> Vec<(&'static str, String)> {
+        let action = |action: &Option<Action>| match action {
+            Some(Action::Shift(next)) => format!("{{LR_SHIFT, {}}}", next),
+            Some(Action::Reduce(production)) => format!("{{LR_REDUCE, {}}}", production),
+            Some(Action::Accept) => "{LR_ACCEPT, 0}".to_string(),
+            None => "{LR_ERROR, 0}".to_string(),
+        };
+
+        vec![
+            ("terminal_count", tables.symbols().terminals.len().to_string()),
+            ("non_terminal_count", tables.symbols().non_terminals.len().to_string()),
+            ("state_count", tables.state_count().to_string()),
+            ("production_count", tables.productions().len().to_string()),
+            ("start_state", tables.start_state().to_string()),
+            ("terminals", c_string(&tables.symbols().terminals.iter().collect::<String>())),
+            ("non_terminals", c_string(&tables.symbols().non_terminals.iter().collect::<String>())),
+            (
+                "productions",
+                lines("    ", ",", tables.productions().iter().map(|production| {
+                    let right: String =
+                        production.right.iter().map(|symbol| symbol.as_char()).collect();
+                    format!("{{{}, {}}}", c_char(production.left.0), c_string(&right))
+                })),
+            ),
+            (
+                "action_rows",
+                lines("    ", ",", (0..tables.state_count()).map(|state| (state, tables.action_row(state))).map(|(state, row)| {
+                    let cells: Vec<String> = row.iter().map(action).collect();
+                    format!("/* {} */ {{{}}}", state, cells.join(", "))
+                })),
+            ),
+            (
+                "goto_rows",
+                lines("    ", ",", (0..tables.state_count()).map(|state| tables.goto_row(state)).map(|row| {
+                    let cells: Vec<String> = row
+                        .iter()
+                        .map(|next| next.map_or("-1".to_string(), |next| next.to_string()))
+                        .collect();
+                    format!("{{{}}}", cells.join(", "))
+                })),
+            ),
+        ]
+    }
+}
+
+fn c_escape(c: char) -> String {
+    match c {
+        '\\' => "\\\\".to_string(),
+        '\'' => "\\'".to_string(),
+        '"' => "\\\"".to_string(),
+        '?' => "\\?".to_string(),
+        c if c.is_ascii_graphic() || c == ' ' => c.to_string(),
+        c => {
+            let mut buffer = [0; 4];
+            c.encode_utf8(&mut buffer)
+                .bytes()
+                .map(|byte| format!("\\{:03o}", byte))
+                .collect()
+        }
+    }
+}
+
+fn c_char(c: char) -> String {
+    format!("'{}'", c_escape(c))
+}
+
+fn c_string(value: &str) -> String {
+    format!("\"{}\"", value.chars().map(c_escape).collect::<String>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::emit;
+    use crate::grammar::{parse_grammar_text, parse_input_text};
+    use crate::lr::compile;
+    use crate::runtime::run;
+    use std::process::Command;
+
+    #[test]
+    fn c_parser_prints_the_same_tree_as_the_runtime() {
+        let grammar = parse_grammar_text("S -> SP\nS -> P\nP -> <>\nP -> <S>\nP -> '?\\").unwrap();
+        let machine = compile(&grammar).unwrap();
+        let dir = std::env::temp_dir().join(format!("lr0-codegen-c-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("parser.c");
+        let binary = dir.join(format!("parser{}", std::env::consts::EXE_SUFFIX));
+        std::fs::write(&source, emit(&CTarget, &machine)).unwrap();
+
+        let compiled = match Command::new("cc")
+            .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-DLR_PARSER_MAIN"])
+            .arg(&source)
+            .arg("-o")
+            .arg(&binary)
+            .output()
+        {
+            Ok(output) => output,
+            Err(_) => {
+                eprintln!("cc not found; skipping the C build check");
+                return;
+            }
+        };
+        assert!(compiled.status.success(), "{}", String::from_utf8_lossy(&compiled.stderr));
+
+        for input in ["<>", "<'?\\<>>"] {
+            let output = Command::new(&binary).arg(input).output().unwrap();
+            let expected = run(&machine, &parse_input_text(input).unwrap()).unwrap().ast;
+            assert_eq!(String::from_utf8_lossy(&output.stdout), expected.to_string());
+        }
+
+        let rejected = Command::new(&binary).arg("<<>").output().unwrap();
+        assert_eq!(rejected.status.code(), Some(1));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
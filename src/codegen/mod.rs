@@ -0,0 +1,78 @@
+//! `CompiledParser` から、このクレートに依存しない単体のパーサーのソースを書き出す。
+
+pub mod c;
+pub mod python;
+pub mod rust;
+pub mod template;
+
+use crate::lr::CompiledParser;
+use crate::packed::DenseTable;
+
+/// 生成先の言語。テンプレートと、表をその言語のリテラルに書き下す方法だけを持つ。
+/// 新しい言語はこのトレイトを実装するだけで足り、`lr.rs` には手を入れない。
+pub trait Target {
+    fn name(&self) -> &'static str;
+
+    /// 生成ファイルの拡張子（`.` なし）。
+    fn extension(&self) -> &'static str;
+
+    /// 複数行の `text` をその言語のコメントにする。
+    fn comment(&self, text: &str) -> String;
+
+    fn template(&self) -> &'static str;
+
+    /// `{{header}}` 以外のテンプレート変数の値。表は `packed::DenseTable` の列順のまま書き下す。
+    fn bindings(&self, tables: &DenseTable) -> Vec<(&'static str, String)>;
+}
+
+/// `target` のテンプレートに `machine` の表を流し込んだソースを返す。
+pub fn emit(target: &dyn Target, machine: &CompiledParser) -> String {
+    let tables = DenseTable::from_parser(machine);
+    let mut bindings = vec![("header", target.comment(&header(&tables)))];
+    bindings.extend(target.bindings(&tables));
+
+    // テンプレートは組み込みで、すべての言語をテストで描画しているので失敗は実装の誤り。
+    template::render(target.template(), &bindings)
+        .unwrap_or_else(|error| panic!("{} template: {}", target.name(), error))
+}
+
+/// 名前（`rust` / `c` / `python`）から生成先を選ぶ。
+pub fn target(name: &str) -> Option<Box<dyn Target>> {
+    match name {
+        "rust" | "rs" => Some(Box::new(rust::RustTarget::default())),
+        "c" => Some(Box::new(c::CTarget)),
+        "python" | "py" => Some(Box::new(python::PythonTarget)),
+        _ => None,
+    }
+}
+
+fn header(tables: &DenseTable) -> String {
+    let mut header = String::from("Generated by lr0-parser-rs. Do not edit by hand.\n\n");
+    for (id, production) in tables.productions().iter().enumerate() {
+        header.push_str(&format!("  r{id}: {production}\n"));
+    }
+    header
+}
+
+/// `text` の各行の頭に `prefix` を付ける。空行には `prefix` だけを置く。
+fn prefix_lines(prefix: &str, text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() {
+                format!("{prefix}\n")
+            } else {
+                format!("{prefix} {line}\n")
+            }
+        })
+        .collect()
+}
+
+/// 各行を `indent` で字下げし、`separator` で終えて連結する。表のリテラルを組むときに使う。
+fn lines<I>(indent: &str, separator: &str, rows: I) -> String
+where
+    I: IntoIterator<Item = String>,
+{
+    rows.into_iter()
+        .map(|row| format!("{indent}{row}{separator}\n"))
+        .collect()
+}
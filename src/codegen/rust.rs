@@ -0,0 +1,159 @@
+use super::{Target, emit, lines, prefix_lines};
+use crate::lr::{Action, CompiledParser, LrTable};
+use crate::packed::DenseTable;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RustOptions {
+    /// コマンドライン引数（なければ標準入力）をパースして木を表示する `main` を付ける。
+    pub with_main: bool,
+}
+
+/// `machine` を、静的な action/goto 表と駆動ループを持つ単体の Rust モジュールにする。
+/// 生成物は標準ライブラリだけに依存し、`rustc --edition=2024` でそのままコンパイルできる。
+pub fn generate(machine: &CompiledParser, options: RustOptions) -> String {
+    emit(&RustTarget { options }, machine)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RustTarget {
+    pub options: RustOptions,
+}
+
+impl Target for RustTarget {
+    fn name(&self) -> &'static str {
+        "rust"
+    }
+
+    fn extension(&self) -> &'static str {
+        "rs"
+    }
+
+    fn comment(&self, text: &str) -> String {
+        prefix_lines("//", text)
+    }
+
+    fn template(&self) -> &'static str {
+        include_str!("templates/parser.rs.tmpl")
+    }
+
+    fn bindings(&self, tables: &DenseTable) -> Vec<(&'static str, String)> {
+        let action = |action: &Option<Action>| match action {
+            Some(Action::Shift(next)) => format!("Action::Shift({})", next),
+            Some(Action::Reduce(production)) => format!("Action::Reduce({})", production),
+            Some(Action::Accept) => "Action::Accept".to_string(),
+            None => "Action::Error".to_string(),
+        };
+        let arms = |symbols: &[char]| {
+            lines("        ", ",", symbols.iter().enumerate().map(|(column, symbol)| {
+                format!("{:?} => Some({})", symbol, column)
+            }))
+        };
+
+        vec![
+            ("terminal_count", tables.symbols().terminals.len().to_string()),
+            ("terminals", format!("{:?}", tables.symbols().terminals)),
+            ("non_terminal_count", tables.symbols().non_terminals.len().to_string()),
+            ("non_terminals", format!("{:?}", tables.symbols().non_terminals)),
+            ("start_state", tables.start_state().to_string()),
+            ("state_count", tables.state_count().to_string()),
+            ("production_count", tables.productions().len().to_string()),
+            (
+                "productions",
+                lines("    ", ",", tables.productions().iter().map(|production| {
+                    let right: String =
+                        production.right.iter().map(|symbol| symbol.as_char()).collect();
+                    format!("Production {{ left: {:?}, right: {:?} }}", production.left.0, right)
+                })),
+            ),
+            (
+                "action_rows",
+                lines("    ", ",", (0..tables.state_count()).map(|state| (state, tables.action_row(state))).map(|(state, row)| {
+                    let cells: Vec<String> = row.iter().map(action).collect();
+                    format!("// state {}\n    [{}]", state, cells.join(", "))
+                })),
+            ),
+            (
+                "goto_rows",
+                lines("    ", ",", (0..tables.state_count()).map(|state| tables.goto_row(state)).map(|row| {
+                    let cells: Vec<String> = row.iter().map(|next| format!("{:?}", next)).collect();
+                    format!("[{}]", cells.join(", "))
+                })),
+            ),
+            ("terminal_arms", arms(&tables.symbols().terminals)),
+            ("non_terminal_arms", arms(&tables.symbols().non_terminals)),
+            (
+                "main",
+                if self.options.with_main { MAIN.to_string() } else { String::new() },
+            ),
+        ]
+    }
+}
+
+const MAIN: &str = r#"
+fn main() {
+    let mut input = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+    if input.is_empty() {
+        let _ = std::io::Read::read_to_string(&mut std::io::stdin(), &mut input);
+    }
+    match parse(&input) {
+        Ok(tree) => print!("{}", tree),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{parse_grammar_text, parse_input_text};
+    use crate::lr::compile;
+    use crate::runtime::run;
+    use std::process::Command;
+
+    fn reducer() -> CompiledParser {
+        compile(&parse_grammar_text(include_str!("../../reducer")).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn tables_are_laid_out_in_column_order() {
+        let code = generate(&reducer(), RustOptions::default());
+
+        assert!(code.contains("pub const TERMINALS: [char; 5] = ['*', '+', '0', '1', '$'];"));
+        assert!(code.contains("pub static ACTION: [[Action; 5]; 9] = ["));
+        assert!(code.contains("Production { left: 'E', right: \"E+B\" },"));
+        assert!(!code.contains("fn main()"));
+    }
+
+    #[test]
+    fn generated_parser_compiles_and_matches_the_runtime() {
+        let machine = reducer();
+        let dir = std::env::temp_dir().join(format!("lr0-codegen-rust-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("parser.rs");
+        let binary = dir.join(format!("parser{}", std::env::consts::EXE_SUFFIX));
+        std::fs::write(&source, generate(&machine, RustOptions { with_main: true })).unwrap();
+
+        let compiled = Command::new("rustc")
+            .args(["--edition=2024", "-O"])
+            .arg(&source)
+            .arg("-o")
+            .arg(&binary)
+            .output()
+            .unwrap();
+        assert!(compiled.status.success(), "{}", String::from_utf8_lossy(&compiled.stderr));
+
+        let accepted = Command::new(&binary).arg("1+1*0").output().unwrap();
+        let expected = run(&machine, &parse_input_text("1+1*0").unwrap()).unwrap().ast;
+        assert!(accepted.status.success());
+        assert_eq!(String::from_utf8_lossy(&accepted.stdout), expected.to_string());
+
+        let rejected = Command::new(&binary).arg("1+").output().unwrap();
+        assert_eq!(rejected.status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&rejected.stderr).contains("unexpected '$' at position 2"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
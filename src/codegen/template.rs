@@ -0,0 +1,88 @@
+use std::fmt;
+
+/// テンプレート中の `{{name}}` を `bindings` の値で置き換える。
+/// `{{` の後に識別子と `}}` が続かなければそのまま出力するので、生成先の言語の波括弧とはぶつからない。
+pub fn render(template: &str, bindings: &[(&str, String)]) -> Result<String, TemplateError> {
+    let mut output = String::with_capacity(template.len());
+    let mut used = vec![false; bindings.len()];
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let placeholder = after
+            .find("}}")
+            .map(|end| &after[..end])
+            .filter(|name| is_identifier(name));
+
+        match placeholder {
+            Some(name) => {
+                let index = bindings
+                    .iter()
+                    .position(|(key, _)| *key == name)
+                    .ok_or_else(|| TemplateError::Unbound(name.to_string()))?;
+                used[index] = true;
+                output.push_str(&bindings[index].1);
+                rest = &after[name.len() + 2..];
+            }
+            None => {
+                output.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    output.push_str(rest);
+
+    // 使われない値があるのはテンプレートと生成器の食い違いなので、黙って捨てずに知らせる。
+    if let Some(index) = used.iter().position(|used| !used) {
+        return Err(TemplateError::Unused(bindings[index].0.to_string()));
+    }
+    Ok(output)
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    Unbound(String),
+    Unused(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unbound(name) => write!(f, "placeholder {{{{{}}}}} has no value", name),
+            Self::Unused(name) => write!(f, "value `{}` is never used by the template", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_replaced_and_other_braces_kept() {
+        let rendered = render(
+            "fn f() {{ {{body}} }} // {{ x }}",
+            &[("body", "return 1;".to_string())],
+        )
+        .unwrap();
+
+        assert_eq!(rendered, "fn f() {{ return 1; }} // {{ x }}");
+    }
+
+    #[test]
+    fn missing_and_unused_bindings_are_errors() {
+        assert_eq!(
+            render("{{a}}", &[]),
+            Err(TemplateError::Unbound("a".to_string()))
+        );
+        assert_eq!(
+            render("a", &[("b", String::new())]),
+            Err(TemplateError::Unused("b".to_string()))
+        );
+    }
+}
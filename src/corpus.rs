@@ -0,0 +1,309 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::grammar::{GrammarError, Symbol, parse_input_text};
+use crate::lr::{CompiledParser, InternalState};
+use crate::runtime::{ParserState, RuntimeError, run_state};
+
+/// 入力1行に付ける期待。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expectation {
+    Accept,
+    Reject,
+}
+
+/// コーパスの1行。`source:line` で元の場所を指せるようにしておく。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorpusCase {
+    pub source: String,
+    pub line: usize,
+    pub input: String,
+    pub expect: Expectation,
+}
+
+#[derive(Debug)]
+pub enum CorpusError {
+    Io(PathBuf, std::io::Error),
+    InvalidLine { source: String, line: usize, text: String },
+}
+
+impl fmt::Display for CorpusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(path, error) => write!(f, "cannot read {}: {}", path.display(), error),
+            Self::InvalidLine { source, line, text } => write!(
+                f,
+                "{}:{}: expected `+ <input>` or `- <input>`, got `{}`",
+                source, line, text
+            ),
+        }
+    }
+}
+
+/// コーパスの本文を読む。1行1入力で、先頭の `+`（または `accept`）が受理、
+/// `-`（または `reject`）が拒否を期待する。空行と `#` で始まる行は読み飛ばす。
+pub fn parse_corpus(source: &str, text: &str) -> Result<Vec<CorpusCase>, CorpusError> {
+    let mut cases = Vec::new();
+
+    for (index, raw) in text.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (marker, input) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let expect = match marker {
+            "+" | "accept" => Expectation::Accept,
+            "-" | "reject" => Expectation::Reject,
+            _ => {
+                return Err(CorpusError::InvalidLine {
+                    source: source.to_string(),
+                    line: index + 1,
+                    text: raw.to_string(),
+                });
+            }
+        };
+        cases.push(CorpusCase {
+            source: source.to_string(),
+            line: index + 1,
+            input: input.trim().to_string(),
+            expect,
+        });
+    }
+
+    Ok(cases)
+}
+
+/// ファイルならその中身を、ディレクトリなら直下のファイルを名前順にすべて読む。
+pub fn load_corpus(path: &Path) -> Result<Vec<CorpusCase>, CorpusError> {
+    let io_error = |error| CorpusError::Io(path.to_path_buf(), error);
+
+    let mut files = if path.is_dir() {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path).map_err(io_error)? {
+            let entry_path = entry.map_err(io_error)?.path();
+            if entry_path.is_file() {
+                files.push(entry_path);
+            }
+        }
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+    files.sort();
+
+    let mut cases = Vec::new();
+    for file in files {
+        let text = std::fs::read_to_string(&file)
+            .map_err(|error| CorpusError::Io(file.clone(), error))?;
+        cases.extend(parse_corpus(&file.display().to_string(), &text)?);
+    }
+    Ok(cases)
+}
+
+/// 1入力を走らせた結果。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Accepted,
+    /// 実行時エラーで止まった。`state` と `position` は止まった時点の状態番号と入力位置。
+    Rejected {
+        error: RuntimeError,
+        state: Option<InternalState>,
+        position: usize,
+        lookahead: Option<char>,
+    },
+    /// 入力に非終端記号（大文字）が含まれていて記号列にできなかった。
+    InvalidInput(GrammarError),
+}
+
+impl Outcome {
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, Self::Accepted)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseResult {
+    pub case: CorpusCase,
+    pub outcome: Outcome,
+    pub elapsed: Duration,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.outcome.is_accepted() == (self.case.expect == Expectation::Accept)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CorpusReport {
+    pub results: Vec<CaseResult>,
+}
+
+impl CorpusReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|result| result.passed()).count()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &CaseResult> {
+        self.results.iter().filter(|result| !result.passed())
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.results.iter().map(|result| result.elapsed).sum()
+    }
+}
+
+/// すべての入力を `runtime::run_state` にかけ、期待と突き合わせる。
+pub fn run_corpus(machine: &CompiledParser, cases: &[CorpusCase]) -> CorpusReport {
+    let results = cases
+        .iter()
+        .map(|case| {
+            let started = Instant::now();
+            let outcome = match parse_input_text(&case.input) {
+                Ok(symbols) => {
+                    let mut state = ParserState::new(symbols, machine.start_state());
+                    match run_state(machine, &mut state) {
+                        Ok(_) => Outcome::Accepted,
+                        Err(error) => rejected(&state, error),
+                    }
+                }
+                Err(error) => Outcome::InvalidInput(error),
+            };
+            CaseResult {
+                case: case.clone(),
+                outcome,
+                elapsed: started.elapsed(),
+            }
+        })
+        .collect();
+
+    CorpusReport { results }
+}
+
+// 失敗した手は状態を書き換えないので、止まった時点の状態がそのまま残る。
+fn rejected(state: &ParserState, error: RuntimeError) -> Outcome {
+    Outcome::Rejected {
+        error,
+        state: state.state_stack.last().copied(),
+        position: state.position,
+        lookahead: match state.remaining_input().first() {
+            Some(Symbol::Terminal(terminal)) => Some(terminal.0),
+            _ => None,
+        },
+    }
+}
+
+impl fmt::Display for CaseResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verdict = if self.passed() { "ok  " } else { "FAIL" };
+        let expect = match self.case.expect {
+            Expectation::Accept => "accept",
+            Expectation::Reject => "reject",
+        };
+        write!(
+            f,
+            "{} {}:{} `{}` (expected {}): ",
+            verdict, self.case.source, self.case.line, self.case.input, expect
+        )?;
+        match &self.outcome {
+            Outcome::Accepted => write!(f, "accepted")?,
+            Outcome::Rejected { error, state, position, lookahead } => {
+                write!(f, "rejected with {:?} at position {}", error, position)?;
+                if let Some(state) = state {
+                    write!(f, " in state {}", state)?;
+                }
+                if let Some(lookahead) = lookahead {
+                    write!(f, " on '{}'", lookahead)?;
+                }
+            }
+            Outcome::InvalidInput(error) => write!(f, "invalid input: {:?}", error)?,
+        }
+        write!(f, " [{:.3} ms]", self.elapsed.as_secs_f64() * 1000.0)
+    }
+}
+
+impl fmt::Display for CorpusReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for failure in self.failures() {
+            writeln!(f, "{}", failure)?;
+        }
+        let slowest = self.results.iter().max_by_key(|result| result.elapsed);
+        writeln!(
+            f,
+            "{} cases: {} passed, {} failed in {:.3} ms",
+            self.results.len(),
+            self.passed(),
+            self.results.len() - self.passed(),
+            self.total_time().as_secs_f64() * 1000.0
+        )?;
+        if let Some(slowest) = slowest {
+            writeln!(
+                f,
+                "slowest: {}:{} ({:.3} ms)",
+                slowest.case.source,
+                slowest.case.line,
+                slowest.elapsed.as_secs_f64() * 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::parse_grammar_text;
+    use crate::lr::compile;
+
+    fn reducer() -> CompiledParser {
+        compile(&parse_grammar_text(include_str!("../reducer")).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn corpus_lines_carry_expectations_and_locations() {
+        let cases = parse_corpus("c.txt", "# comment\n+ 1+0\n\n- 1+\naccept 1 * 1\n+\n").unwrap();
+
+        assert_eq!(cases.len(), 4);
+        assert_eq!((cases[0].line, cases[0].expect), (2, Expectation::Accept));
+        assert_eq!((cases[1].input.as_str(), cases[1].expect), ("1+", Expectation::Reject));
+        assert_eq!(cases[2].input, "1 * 1");
+        assert_eq!(cases[3].input, "");
+        assert!(parse_corpus("c.txt", "? 1").is_err());
+    }
+
+    #[test]
+    fn report_lists_unexpected_outcomes_with_the_stuck_state() {
+        let cases = parse_corpus("c.txt", "+ 1+0\n- 1+\n+ 1+\n- 0\n").unwrap();
+        let report = run_corpus(&reducer(), &cases);
+
+        assert_eq!(report.passed(), 2);
+        let failures: Vec<_> = report.failures().collect();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(
+            failures[0].outcome,
+            Outcome::Rejected {
+                error: RuntimeError::InvalidAction,
+                state: Some(6),
+                position: 2,
+                lookahead: Some('$'),
+            }
+        );
+        assert_eq!(failures[1].outcome, Outcome::Accepted);
+        assert!(report.to_string().contains("4 cases: 2 passed, 2 failed"));
+    }
+
+    #[test]
+    fn deep_inputs_do_not_abort_the_run() {
+        // 左に深い木を作る長い入力。受理・拒否のどちらの後始末も再帰しない。
+        let deep = "1+0+".repeat(50_000);
+        let text = format!("+ {deep}1\n- {deep}\n");
+        let report = run_corpus(&reducer(), &parse_corpus("c.txt", &text).unwrap());
+
+        assert!(report.all_passed(), "{}", report);
+    }
+}
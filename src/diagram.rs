@@ -0,0 +1,369 @@
+//! LR オートマトン（`StateInfo` の列）を図に書き出す。
+//!
+//! Graphviz の DOT と、外部ツールなしで開ける単体の SVG の2通り。どちらも状態ごとに項目集合を載せ、
+//! 同じ遷移先への辺は記号をまとめて1本にする。
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+
+use crate::grammar::Terminal;
+use crate::lr::{Action, CompiledParser, LrItem, StateInfo};
+
+/// `E -> E.+B` のようにドットを挟んだ項目の表記。
+pub fn item_label(item: &LrItem) -> String {
+    let mut label = format!("{} -> ", item.production.left.0);
+    for (index, symbol) in item.production.right.iter().enumerate() {
+        if index == item.dot_pos {
+            label.push('.');
+        }
+        label.push(symbol.as_char());
+    }
+    if item.dot_pos >= item.production.right.len() {
+        label.push('.');
+    }
+    label
+}
+
+/// '$' で受理する状態。図では二重枠と受理の矢印で示す。
+pub fn accept_states(machine: &CompiledParser) -> Vec<usize> {
+    machine
+        .state_infos()
+        .iter()
+        .map(|state| state.id)
+        .filter(|&state| machine.action(state, Terminal('$')) == Some(Action::Accept))
+        .collect()
+}
+
+/// 状態 0 からの BFS の深さ。届かない状態は最も深い層の次に置く。
+pub fn layers(states: &[StateInfo]) -> Vec<usize> {
+    let count = states.len();
+    let mut layer = vec![usize::MAX; count];
+    if count == 0 {
+        return layer;
+    }
+    let mut queue = VecDeque::from([0]);
+    layer[0] = 0;
+    while let Some(state) = queue.pop_front() {
+        for &(_, next) in &states[state].transitions {
+            if next < count && layer[next] == usize::MAX {
+                layer[next] = layer[state] + 1;
+                queue.push_back(next);
+            }
+        }
+    }
+    let unreached = layer.iter().filter(|&&l| l != usize::MAX).max().map_or(0, |&l| l + 1);
+    for l in layer.iter_mut().filter(|l| **l == usize::MAX) {
+        *l = unreached;
+    }
+    layer
+}
+
+// 遷移先ごとに記号をまとめる。辺の並びは遷移先の状態番号順。
+fn edges(state: &StateInfo) -> BTreeMap<usize, String> {
+    let mut edges: BTreeMap<usize, String> = BTreeMap::new();
+    for (symbol, next) in &state.transitions {
+        let label = edges.entry(*next).or_default();
+        if !label.is_empty() {
+            label.push_str(", ");
+        }
+        label.push(symbol.as_char());
+    }
+    edges
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Graphviz の DOT。`dot -Tsvg` や `dot -Tpdf` でそのまま描ける。`accepting` の状態は二重枠にする。
+pub fn to_dot(states: &[StateInfo], accepting: &[usize]) -> String {
+    let mut output = String::from("digraph lr0 {\n");
+    output.push_str("    rankdir=LR;\n");
+    output.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+    output.push_str("    edge [fontname=\"monospace\"];\n");
+    output.push_str("    start [shape=point];\n");
+    output.push_str("    start -> I0;\n");
+
+    for state in states {
+        let mut label = format!("I{}\\n", state.id);
+        for item in &state.items {
+            label.push_str(&dot_escape(&item_label(item)));
+            label.push_str("\\l");
+        }
+        let peripheries = if accepting.contains(&state.id) { ", peripheries=2" } else { "" };
+        let _ = writeln!(output, "    I{} [label=\"{}\"{}];", state.id, label, peripheries);
+    }
+    for state in states {
+        for (next, label) in edges(state) {
+            let _ = writeln!(
+                output,
+                "    I{} -> I{} [label=\"{}\"];",
+                state.id,
+                next,
+                dot_escape(&label)
+            );
+        }
+    }
+    for state in accepting {
+        let _ = writeln!(output, "    accept{} [shape=doublecircle, label=\"\", width=0.2];", state);
+        let _ = writeln!(output, "    I{} -> accept{} [label=\"$\"];", state, state);
+    }
+    output.push_str("}\n");
+    output
+}
+
+const CHAR_WIDTH: f64 = 7.8;
+const LINE_HEIGHT: f64 = 17.0;
+const PADDING: f64 = 8.0;
+const COLUMN_GAP: f64 = 90.0;
+const ROW_GAP: f64 = 30.0;
+const MARGIN: f64 = 20.0;
+const ACCEPT_ARROW: f64 = 50.0;
+
+struct Area {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl Area {
+    fn center_y(&self) -> f64 {
+        self.y + self.height / 2.0
+    }
+
+    fn right(&self) -> f64 {
+        self.x + self.width
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 外部ツールに頼らず書き出す単体の SVG。状態は BFS の深さで左から右へ列に並べ、
+/// 各箱に項目集合を載せる。前向きの辺は直線、同じ列か後ろ向きの辺は上に膨らむ曲線で描く。
+pub fn to_svg(states: &[StateInfo], accepting: &[usize]) -> String {
+    let layer = layers(states);
+    let column_count = layer.iter().max().map_or(0, |&l| l + 1);
+
+    // 箱の大きさは項目の最長行と行数で決める。
+    let sizes: Vec<(f64, f64)> = states
+        .iter()
+        .map(|state| {
+            let longest = state
+                .items
+                .iter()
+                .map(|item| item_label(item).chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(format!("I{}", state.id).len());
+            let lines = state.items.len() + 1;
+            (
+                longest as f64 * CHAR_WIDTH + PADDING * 2.0,
+                lines as f64 * LINE_HEIGHT + PADDING * 2.0,
+            )
+        })
+        .collect();
+
+    // 後ろ向きの辺の曲線が収まるよう、上に余白を取る。
+    let top = MARGIN + 60.0;
+    let mut boxes: Vec<Area> = states
+        .iter()
+        .map(|_| Area { x: 0.0, y: 0.0, width: 0.0, height: 0.0 })
+        .collect();
+    let mut x = MARGIN;
+    let mut height = top;
+    for column in 0..column_count {
+        let members: Vec<usize> = (0..states.len()).filter(|&s| layer[s] == column).collect();
+        let width = members.iter().map(|&s| sizes[s].0).fold(0.0, f64::max);
+        let mut y = top;
+        for &state in &members {
+            boxes[state] = Area { x, y, width: sizes[state].0, height: sizes[state].1 };
+            y += sizes[state].1 + ROW_GAP;
+        }
+        height = f64::max(height, y);
+        x += width + COLUMN_GAP;
+    }
+    let width = x - COLUMN_GAP + ACCEPT_ARROW + MARGIN;
+    let height = height - ROW_GAP + MARGIN;
+
+    let mut output = String::new();
+    let _ = writeln!(
+        output,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.0}\" height=\"{:.0}\" viewBox=\"0 0 {:.0} {:.0}\" font-family=\"monospace\" font-size=\"13\">",
+        width, height, width, height
+    );
+    output.push_str("  <defs>\n");
+    output.push_str("    <marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"8\" markerHeight=\"8\" orient=\"auto-start-reverse\">\n");
+    output.push_str("      <path d=\"M 0 0 L 10 5 L 0 10 z\" fill=\"#555\"/>\n");
+    output.push_str("    </marker>\n");
+    output.push_str("  </defs>\n");
+    output.push_str("  <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n");
+
+    for state in states {
+        let from = &boxes[state.id];
+        for (next, label) in edges(state) {
+            let Some(to) = boxes.get(next) else { continue };
+            let label = xml_escape(&label);
+            if layer.get(next) > layer.get(state.id) {
+                let (x1, y1, x2, y2) = (from.right(), from.center_y(), to.x, to.center_y());
+                let _ = writeln!(
+                    output,
+                    "  <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#555\" marker-end=\"url(#arrow)\"/>",
+                    x1, y1, x2, y2
+                );
+                let _ = writeln!(
+                    output,
+                    "  <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"#333\">{}</text>",
+                    (x1 + x2) / 2.0,
+                    (y1 + y2) / 2.0 - 5.0,
+                    label
+                );
+            } else {
+                // 自己ループと後ろ向きの辺は箱の上辺どうしを曲線で結ぶ。
+                let (x1, x2) = if state.id == next {
+                    (from.x + from.width * 0.35, from.x + from.width * 0.65)
+                } else {
+                    (from.x + from.width / 2.0, to.x + to.width / 2.0)
+                };
+                let (y1, y2) = (from.y, to.y);
+                let lift = 30.0 + (x1 - x2).abs() * 0.1;
+                let (cx, cy) = ((x1 + x2) / 2.0, y1.min(y2) - lift);
+                let _ = writeln!(
+                    output,
+                    "  <path d=\"M {:.1} {:.1} Q {:.1} {:.1} {:.1} {:.1}\" fill=\"none\" stroke=\"#555\" marker-end=\"url(#arrow)\"/>",
+                    x1, y1, cx, cy, x2, y2
+                );
+                let _ = writeln!(
+                    output,
+                    "  <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"#333\">{}</text>",
+                    cx,
+                    (y1.min(y2) + cy) / 2.0 - 3.0,
+                    label
+                );
+            }
+        }
+    }
+
+    for state in states {
+        let area = &boxes[state.id];
+        let accepting = accepting.contains(&state.id);
+        let _ = writeln!(
+            output,
+            "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"#f4f8fb\" stroke=\"{}\" stroke-width=\"{}\"/>",
+            area.x,
+            area.y,
+            area.width,
+            area.height,
+            if accepting { "#7a3fb0" } else { "#2a6f85" },
+            if accepting { 2.5 } else { 1.2 }
+        );
+        let mut baseline = area.y + PADDING + LINE_HEIGHT - 4.0;
+        let _ = writeln!(
+            output,
+            "  <text x=\"{:.1}\" y=\"{:.1}\" font-weight=\"bold\">I{}</text>",
+            area.x + PADDING,
+            baseline,
+            state.id
+        );
+        for item in &state.items {
+            baseline += LINE_HEIGHT;
+            let _ = writeln!(
+                output,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" xml:space=\"preserve\">{}</text>",
+                area.x + PADDING,
+                baseline,
+                xml_escape(&item_label(item))
+            );
+        }
+        if accepting {
+            let (x1, y) = (area.right(), area.center_y());
+            let _ = writeln!(
+                output,
+                "  <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#7a3fb0\" marker-end=\"url(#arrow)\"/>",
+                x1,
+                y,
+                x1 + ACCEPT_ARROW - 10.0,
+                y
+            );
+            let _ = writeln!(
+                output,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"#7a3fb0\">$ accept</text>",
+                x1 + ACCEPT_ARROW / 2.0,
+                y - 6.0
+            );
+        }
+    }
+
+    output.push_str("</svg>\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::parse_grammar_text;
+    use crate::lr::compile;
+
+    fn reducer() -> CompiledParser {
+        compile(&parse_grammar_text(include_str!("../reducer")).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn dot_lists_every_state_item_and_edge() {
+        let machine = reducer();
+        let states = machine.state_infos();
+        let dot = to_dot(states, &accept_states(&machine));
+
+        assert!(dot.starts_with("digraph lr0 {"));
+        for state in states {
+            assert!(dot.contains(&format!("I{} [label=\"I{}\\n", state.id, state.id)));
+        }
+        assert!(dot.contains("F -> .E\\l"));
+        let edge_count: usize = states.iter().map(|state| edges(state).len()).sum();
+        assert_eq!(dot.matches(" -> I").count(), edge_count + 1);
+        assert!(dot.contains("I4 [label=\"I4\\nE -> E.*B\\lE -> E.+B\\lF -> E.\\l\", peripheries=2];"));
+        assert_eq!(dot.matches("peripheries=2").count(), 1);
+    }
+
+    #[test]
+    fn svg_is_standalone_and_escapes_items() {
+        let grammar = parse_grammar_text("S -> <S>\nS -> &").unwrap();
+        let machine = compile(&grammar).unwrap();
+        let states = machine.state_infos();
+        let svg = to_svg(states, &accept_states(&machine));
+
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert!(svg.contains("S -&gt; &lt;.S&gt;"));
+        assert!(svg.contains("S -&gt; &amp;."));
+        assert!(!svg.contains("<S>"));
+        assert_eq!(svg.matches("<rect x=").count(), states.len());
+        assert!(svg.contains("$ accept"));
+    }
+
+    #[test]
+    fn layers_follow_breadth_first_depth() {
+        let machine = reducer();
+        let states = machine.state_infos();
+        let layer = layers(states);
+
+        assert_eq!(layer[0], 0);
+        for state in states {
+            for &(_, next) in &state.transitions {
+                assert!(layer[next] <= layer[state.id] + 1);
+            }
+        }
+    }
+}
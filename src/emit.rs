@@ -0,0 +1,551 @@
+use crate::evaluator::Expr;
+use crate::roles::Operation;
+use crate::runner::Toolchain;
+
+/// 評価式を表示するプログラムの書き出し方。言語ごとにこのトレイトを実装する。
+/// どの言語でも AST とソースのプレビューを埋め込み、最後に `Evaluated value: N` を 1 行出す。
+pub trait Emitter {
+    fn name(&self) -> &'static str;
+
+    /// 生成したプログラムを走らせる処理系。
+    fn toolchain(&self) -> Toolchain;
+
+    /// 変数名をこの言語で使える名前にする。予約語やプログラム内の名前と重なれば `_` を足す。
+    fn variable(&self, name: &str) -> String;
+
+    /// 式をこの言語の式にする。値は `Expr::value` と同じ 64 bit 整数の意味で計算されること。
+    fn expression(&self, expr: &Expr) -> String;
+
+    /// `bindings` の変数を宣言してから `expression` を計算する。
+    /// `expression` が None なら評価値の代わりに割り当て不足を知らせる行を出す。
+    fn program(
+        &self,
+        ast_preview: &str,
+        source_preview: &str,
+        bindings: &[(&str, i64)],
+        expression: Option<&str>,
+    ) -> String;
+}
+
+/// Generator ページで選ぶ生成先の言語。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    #[default]
+    Rust,
+    C,
+    JavaScript,
+    Python,
+}
+
+impl Target {
+    pub const ALL: [Target; 4] = [Self::Rust, Self::C, Self::JavaScript, Self::Python];
+
+    pub fn name(self) -> &'static str {
+        self.emitter().name()
+    }
+
+    pub fn emitter(self) -> &'static dyn Emitter {
+        match self {
+            Self::Rust => &RustEmitter,
+            Self::C => &CEmitter,
+            Self::JavaScript => &JavaScriptEmitter,
+            Self::Python => &PythonEmitter,
+        }
+    }
+}
+
+const HEADER: &str = "Generated by the LR(0) Parser GUI";
+const UNAVAILABLE: &str = "Evaluated value: <not available for current terminal mappings>";
+
+/// 役割の `evaluation` テンプレートをそのまま使う。
+pub struct RustEmitter;
+
+impl Emitter for RustEmitter {
+    fn name(&self) -> &'static str {
+        "Rust"
+    }
+
+    fn toolchain(&self) -> Toolchain {
+        Toolchain::RUSTC
+    }
+
+    fn variable(&self, name: &str) -> String {
+        avoid(name, RUST_RESERVED)
+    }
+
+    fn expression(&self, expr: &Expr) -> String {
+        expr.render(&|name| self.variable(name))
+    }
+
+    fn program(
+        &self,
+        ast_preview: &str,
+        source_preview: &str,
+        bindings: &[(&str, i64)],
+        expression: Option<&str>,
+    ) -> String {
+        let mut generated = String::new();
+        generated.push_str(&format!("// {}\n", HEADER));
+        generated.push_str("fn generated_ast() -> &'static str {\n");
+        generated.push_str(&format!("    \"{}\"\n", escape_rust_string(ast_preview)));
+        generated.push_str("}\n\n");
+        generated.push_str("fn generated_source() -> &'static str {\n");
+        generated.push_str(&format!("    \"{}\"\n", escape_rust_string(source_preview)));
+        generated.push_str("}\n\n");
+        generated.push_str("fn main() {\n");
+        generated.push_str("    println!(\"AST:\\n{}\", generated_ast());\n");
+        generated.push_str("    println!(\"Source preview: {}\", generated_source());\n");
+        match expression {
+            Some(expression) => {
+                for (name, value) in bindings {
+                    generated.push_str(&format!("    let {}: i64 = {};\n", self.variable(name), value));
+                }
+                generated.push_str(&format!("    let value: i64 = {};\n", expression));
+                generated.push_str("    println!(\"Evaluated value: {}\", value);\n");
+            }
+            None => generated.push_str(&format!("    println!(\"{}\");\n", UNAVAILABLE)),
+        }
+        generated.push_str("}\n");
+        generated
+    }
+}
+
+/// `long long` で計算する C99。累乗だけは補助関数を使い、負の指数ではプログラムを失敗させる。
+pub struct CEmitter;
+
+impl Emitter for CEmitter {
+    fn name(&self) -> &'static str {
+        "C"
+    }
+
+    fn toolchain(&self) -> Toolchain {
+        Toolchain { program: "cc", args: &["-std=c99"], extension: "c", compiled: true }
+    }
+
+    fn variable(&self, name: &str) -> String {
+        avoid(name, C_RESERVED)
+    }
+
+    fn expression(&self, expr: &Expr) -> String {
+        infix(expr, &c_literal, &|name| self.variable(name), &|operation, operands| match operation {
+            Operation::Pow => format!("ipow({}, {})", operands[0], operands[1]),
+            Operation::Neg => format!("-{}", operands[0]),
+            Operation::Not => format!("!{}", operands[0]),
+            // && と || は int の 0/1 になり、long long に広がる
+            _ => format!("{} {} {}", operands[0], c_like_operator(operation), operands[1]),
+        })
+    }
+
+    fn program(
+        &self,
+        ast_preview: &str,
+        source_preview: &str,
+        bindings: &[(&str, i64)],
+        expression: Option<&str>,
+    ) -> String {
+        let mut generated = String::new();
+        generated.push_str(&format!("/* {} */\n", HEADER));
+        generated.push_str("#include <stdio.h>\n#include <stdlib.h>\n\n");
+        generated.push_str(&format!(
+            "static const char *generated_ast = \"{}\";\n",
+            escape_string(ast_preview, |c| format!("\\{:03o}", u32::from(c)))
+        ));
+        generated.push_str(&format!(
+            "static const char *generated_source = \"{}\";\n\n",
+            escape_string(source_preview, |c| format!("\\{:03o}", u32::from(c)))
+        ));
+        generated.push_str("static inline long long ipow(long long base, long long exponent) {\n");
+        // 評価器と同じく負の指数は値にしない
+        generated.push_str("    if (exponent < 0) {\n");
+        generated.push_str("        fputs(\"negative exponent\\n\", stderr);\n");
+        generated.push_str("        exit(1);\n");
+        generated.push_str("    }\n");
+        generated.push_str("    long long result = 1;\n");
+        generated.push_str("    while (exponent-- > 0) {\n");
+        generated.push_str("        result *= base;\n");
+        generated.push_str("    }\n");
+        generated.push_str("    return result;\n");
+        generated.push_str("}\n\n");
+        generated.push_str("int main(void) {\n");
+        generated.push_str("    printf(\"AST:\\n%s\\n\", generated_ast);\n");
+        generated.push_str("    printf(\"Source preview: %s\\n\", generated_source);\n");
+        match expression {
+            Some(expression) => {
+                for (name, value) in bindings {
+                    generated.push_str(&format!("    long long {} = {};\n", self.variable(name), c_literal(*value)));
+                }
+                generated.push_str(&format!("    long long value = {};\n", expression));
+                generated.push_str("    printf(\"Evaluated value: %lld\\n\", value);\n");
+            }
+            None => generated.push_str(&format!("    printf(\"{}\\n\");\n", UNAVAILABLE)),
+        }
+        generated.push_str("    return 0;\n");
+        generated.push_str("}\n");
+        generated
+    }
+}
+
+/// BigInt で計算する JavaScript。BigInt の `/` と `%` は Rust と同じく 0 方向に切り捨てる。
+/// 負の指数の `**` は RangeError を投げるので、評価器と同じく値を出さない。
+pub struct JavaScriptEmitter;
+
+impl Emitter for JavaScriptEmitter {
+    fn name(&self) -> &'static str {
+        "JavaScript"
+    }
+
+    fn toolchain(&self) -> Toolchain {
+        Toolchain { program: "node", args: &[], extension: "js", compiled: false }
+    }
+
+    fn variable(&self, name: &str) -> String {
+        avoid(name, JAVASCRIPT_RESERVED)
+    }
+
+    fn expression(&self, expr: &Expr) -> String {
+        infix(expr, &|value| format!("{}n", value), &|name| self.variable(name), &|operation, operands| match operation {
+            Operation::Pow => format!("{} ** {}", operands[0], operands[1]),
+            Operation::And => format!("BigInt({} !== 0n && {} !== 0n)", operands[0], operands[1]),
+            Operation::Or => format!("BigInt({} !== 0n || {} !== 0n)", operands[0], operands[1]),
+            Operation::Neg => format!("-{}", operands[0]),
+            Operation::Not => format!("BigInt({} === 0n)", operands[0]),
+            _ => format!("{} {} {}", operands[0], c_like_operator(operation), operands[1]),
+        })
+    }
+
+    fn program(
+        &self,
+        ast_preview: &str,
+        source_preview: &str,
+        bindings: &[(&str, i64)],
+        expression: Option<&str>,
+    ) -> String {
+        let mut generated = String::new();
+        generated.push_str(&format!("// {}\n", HEADER));
+        generated.push_str(&format!(
+            "const generatedAst = \"{}\";\n",
+            escape_string(ast_preview, unicode_escape)
+        ));
+        generated.push_str(&format!(
+            "const generatedSource = \"{}\";\n\n",
+            escape_string(source_preview, unicode_escape)
+        ));
+        generated.push_str("console.log(\"AST:\\n\" + generatedAst);\n");
+        generated.push_str("console.log(\"Source preview: \" + generatedSource);\n");
+        match expression {
+            Some(expression) => {
+                for (name, value) in bindings {
+                    generated.push_str(&format!("const {} = {}n;\n", self.variable(name), value));
+                }
+                generated.push_str(&format!("const value = {};\n", expression));
+                generated.push_str("console.log(\"Evaluated value: \" + value);\n");
+            }
+            None => generated.push_str(&format!("console.log(\"{}\");\n", UNAVAILABLE)),
+        }
+        generated
+    }
+}
+
+/// Python の整数は桁あふれしないが、`//` と `%` は負の側へ丸めるので補助関数で 0 方向にそろえる。
+/// `**` も負の指数で小数を返すので、補助関数で失敗させる。
+pub struct PythonEmitter;
+
+impl Emitter for PythonEmitter {
+    fn name(&self) -> &'static str {
+        "Python"
+    }
+
+    fn toolchain(&self) -> Toolchain {
+        Toolchain { program: "python3", args: &[], extension: "py", compiled: false }
+    }
+
+    fn variable(&self, name: &str) -> String {
+        avoid(name, PYTHON_RESERVED)
+    }
+
+    fn expression(&self, expr: &Expr) -> String {
+        infix(expr, &|value| value.to_string(), &|name| self.variable(name), &|operation, operands| match operation {
+            Operation::Add => format!("{} + {}", operands[0], operands[1]),
+            Operation::Sub => format!("{} - {}", operands[0], operands[1]),
+            Operation::Mul => format!("{} * {}", operands[0], operands[1]),
+            Operation::Div => format!("_div({}, {})", operands[0], operands[1]),
+            Operation::Mod => format!("_mod({}, {})", operands[0], operands[1]),
+            Operation::Pow => format!("_pow({}, {})", operands[0], operands[1]),
+            Operation::And => format!("int({} != 0 and {} != 0)", operands[0], operands[1]),
+            Operation::Or => format!("int({} != 0 or {} != 0)", operands[0], operands[1]),
+            Operation::Neg => format!("-{}", operands[0]),
+            Operation::Not => format!("int({} == 0)", operands[0]),
+        })
+    }
+
+    fn program(
+        &self,
+        ast_preview: &str,
+        source_preview: &str,
+        bindings: &[(&str, i64)],
+        expression: Option<&str>,
+    ) -> String {
+        let mut generated = String::new();
+        generated.push_str(&format!("# {}\n", HEADER));
+        generated.push_str(&format!("GENERATED_AST = \"{}\"\n", escape_string(ast_preview, unicode_escape)));
+        generated.push_str(&format!(
+            "GENERATED_SOURCE = \"{}\"\n\n\n",
+            escape_string(source_preview, unicode_escape)
+        ));
+        generated.push_str("def _div(left, right):\n");
+        generated.push_str("    quotient = abs(left) // abs(right)\n");
+        generated.push_str("    return quotient if (left < 0) == (right < 0) else -quotient\n\n\n");
+        generated.push_str("def _mod(left, right):\n");
+        generated.push_str("    return left - right * _div(left, right)\n\n\n");
+        // `**` は負の指数で小数を返すので、評価器と同じく失敗させる
+        generated.push_str("def _pow(base, exponent):\n");
+        generated.push_str("    if exponent < 0:\n");
+        generated.push_str("        raise ValueError(\"negative exponent\")\n");
+        generated.push_str("    return base ** exponent\n\n\n");
+        generated.push_str("print(\"AST:\\n\" + GENERATED_AST)\n");
+        generated.push_str("print(\"Source preview: \" + GENERATED_SOURCE)\n");
+        match expression {
+            Some(expression) => {
+                for (name, value) in bindings {
+                    generated.push_str(&format!("{} = {}\n", self.variable(name), value));
+                }
+                generated.push_str(&format!("value = {}\n", expression));
+                generated.push_str("print(\"Evaluated value: \" + str(value))\n");
+            }
+            None => generated.push_str(&format!("print(\"{}\")\n", UNAVAILABLE)),
+        }
+        generated
+    }
+}
+
+// 木の形どおりに組む。数値でない被演算子は括弧で包むので、言語側の優先順位には頼らない。
+fn infix(
+    expr: &Expr,
+    literal: &dyn Fn(i64) -> String,
+    variable: &dyn Fn(&str) -> String,
+    operator: &dyn Fn(Operation, &[String]) -> String,
+) -> String {
+    let grouped = |operand: &Expr| match operand {
+        Expr::Number(_) | Expr::Variable { .. } => infix(operand, literal, variable, operator),
+        _ => format!("({})", infix(operand, literal, variable, operator)),
+    };
+    match expr {
+        Expr::Number(value) => literal(*value),
+        Expr::Variable { name, .. } => variable(name),
+        Expr::Unary { operation, operand, .. } => operator(*operation, &[grouped(operand)]),
+        Expr::Binary { operation, left, right, .. } => {
+            operator(*operation, &[grouped(left), grouped(right)])
+        }
+    }
+}
+
+// C と JavaScript で綴りが同じ二項演算子。
+fn c_like_operator(operation: Operation) -> &'static str {
+    match operation {
+        Operation::Add => "+",
+        Operation::Sub => "-",
+        Operation::Mul => "*",
+        Operation::Div => "/",
+        Operation::Mod => "%",
+        Operation::And => "&&",
+        Operation::Or => "||",
+        Operation::Pow | Operation::Neg | Operation::Not => unreachable!("{} has its own spelling", operation.key()),
+    }
+}
+
+// 生成プログラムの中で変数名に使えない名前。各言語の予約語と、プログラムが使う名前。
+const RUST_RESERVED: &[&str] = &[
+    "_", "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate", "do",
+    "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let", "loop",
+    "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "self", "static",
+    "struct", "super", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual",
+    "where", "while", "yield",
+];
+const C_RESERVED: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum",
+    "exit", "extern", "float", "for", "fputs", "goto", "if", "inline", "int", "ipow", "long", "printf", "register",
+    "restrict", "return", "short", "signed", "sizeof", "static", "stderr", "struct", "switch", "typedef", "union",
+    "unsigned", "value", "void", "volatile", "while",
+];
+const JAVASCRIPT_RESERVED: &[&str] = &[
+    "arguments", "await", "break", "case", "catch", "class", "console", "const", "continue", "debugger",
+    "default", "delete", "do", "else", "enum", "eval", "export", "extends", "false", "finally", "for",
+    "function", "if", "implements", "import", "in", "instanceof", "interface", "let", "new", "null",
+    "package", "private", "protected", "public", "return", "static", "super", "switch", "this", "throw",
+    "true", "try", "typeof", "undefined", "value", "var", "void", "while", "with", "yield",
+];
+const PYTHON_RESERVED: &[&str] = &[
+    "_div", "_mod", "_pow", "abs", "and", "as", "assert", "async", "await", "break", "class", "continue", "def",
+    "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "int", "is",
+    "lambda", "nonlocal", "not", "or", "pass", "print", "raise", "return", "str", "try", "value", "while",
+    "with", "yield",
+];
+
+fn avoid(name: &str, reserved: &[&str]) -> String {
+    if reserved.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+// i64::MIN の絶対値は long long のリテラルに収まらないので式で書く。
+fn c_literal(value: i64) -> String {
+    if value == i64::MIN {
+        format!("({}LL - 1)", i64::MIN + 1)
+    } else {
+        format!("{}LL", value)
+    }
+}
+
+fn escape_rust_string(value: &str) -> String {
+    value.chars().flat_map(char::escape_default).collect()
+}
+
+// `"` `\` と改行・タブは共通の書き方で、それ以外の制御文字は `control` で書く。
+fn escape_string(value: &str, control: fn(char) -> String) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '"' => "\\\"".to_string(),
+            '\\' => "\\\\".to_string(),
+            '\n' => "\\n".to_string(),
+            '\t' => "\\t".to_string(),
+            c if c.is_control() && c.is_ascii() => control(c),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+fn unicode_escape(c: char) -> String {
+    format!("\\u{:04x}", u32::from(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::{Environment, EvalError, expression};
+    use crate::lexer::lex;
+    use crate::roles::{Arity, RoleDefinition, RoleRegistry, TerminalRole};
+    use crate::runner::{Job, RunLimits, RunOutcome};
+    use lr0_parser_rs::AstNode;
+    use lr0_parser_rs::grammar::parse_grammar_text;
+    use lr0_parser_rs::lr::compile;
+    use lr0_parser_rs::runtime::run;
+    use std::collections::HashMap;
+
+    const ARITHMETIC: &str = "E -> E+B\nE -> E-B\nE -> E*B\nE -> E/B\nE -> E%B\nE -> E^B\nE -> E&B\nE -> B\n\
+                              B -> (E)\nB -> -B\nB -> !B\nB -> n\nB -> i";
+
+    fn roles() -> HashMap<char, TerminalRole> {
+        let mut roles: HashMap<char, TerminalRole> =
+            "+-*/%()".chars().map(|symbol| (symbol, TerminalRole::default_for(symbol))).collect();
+        roles.insert('n', TerminalRole::Num);
+        roles.insert('i', TerminalRole::Ident);
+        roles.insert('^', TerminalRole::Custom("Pow".to_string()));
+        roles.insert('&', TerminalRole::Custom("And".to_string()));
+        roles.insert('!', TerminalRole::Custom("Not".to_string()));
+        roles
+    }
+
+    fn registry() -> RoleRegistry {
+        let mut registry = RoleRegistry::default();
+        for (name, evaluation, arity, operation) in [
+            ("Pow", "i64::pow({0}, {1} as u32)", Arity::Binary, Operation::Pow),
+            ("And", "i64::from({0} != 0 && {1} != 0)", Arity::Binary, Operation::And),
+            ("Not", "i64::from({0} == 0)", Arity::Unary, Operation::Not),
+        ] {
+            registry
+                .define(RoleDefinition {
+                    name: name.to_string(),
+                    source: String::new(),
+                    evaluation: evaluation.to_string(),
+                    arity,
+                    precedence: 3,
+                    operation,
+                })
+                .unwrap();
+        }
+        registry
+    }
+
+    fn parse(input: &str) -> AstNode {
+        let machine = compile(&parse_grammar_text(ARITHMETIC).unwrap()).unwrap();
+        let lexed = lex(input, &roles()).unwrap();
+        lexed.attach(&run(&machine, &lexed.symbols).unwrap().ast)
+    }
+
+    #[test]
+    fn operands_are_grouped_by_tree_shape_in_every_language() {
+        let ast = parse("-7/x^2");
+        let registry = registry();
+        let roles = roles();
+        let expr = expression(&ast, &registry, &|symbol| roles[&symbol].clone()).unwrap();
+
+        assert_eq!(CEmitter.expression(&expr), "ipow(((-7LL) / x), 2LL)");
+        assert_eq!(JavaScriptEmitter.expression(&expr), "((-7n) / x) ** 2n");
+        assert_eq!(PythonEmitter.expression(&expr), "_pow((_div((-7), x)), 2)");
+        assert_eq!(RustEmitter.variable("fn"), "fn_");
+        assert_eq!(PythonEmitter.variable("fn"), "fn");
+        assert_eq!(c_literal(i64::MIN), "(-9223372036854775807LL - 1)");
+        assert_eq!(escape_string("a\"b\\\n\u{1}", unicode_escape), "a\\\"b\\\\\\n\\u0001");
+    }
+
+    // 処理系があれば実際に走らせ、出力した値がその場での評価と一致するか確かめる。
+    #[test]
+    fn every_target_prints_the_in_process_value() {
+        let registry = registry();
+        let roles = roles();
+        let environment = Environment::from([
+            ("x".to_string(), 7),
+            ("y".to_string(), -3),
+            ("value".to_string(), 12),
+            ("print".to_string(), 2),
+        ]);
+        let inputs = [
+            "7-(2-1)*-2",
+            "-7%2",
+            "-7/2^2",
+            "2^7%7-1",
+            "!0&7-!(1&0)",
+            "x*10+y",
+            "value/y-print^x",
+        ];
+
+        for target in Target::ALL {
+            let emitter = target.emitter();
+            if !emitter.toolchain().available() {
+                eprintln!("{} not found; skipping the {} check", emitter.toolchain().program, target.name());
+                continue;
+            }
+            for input in inputs {
+                let ast = parse(input);
+                let expr = expression(&ast, &registry, &|symbol| roles[&symbol].clone()).unwrap();
+                let bindings: Vec<(&str, i64)> =
+                    expr.variables().into_iter().map(|name| (name, environment[name])).collect();
+                let source =
+                    emitter.program(&ast.to_string(), input, &bindings, Some(&emitter.expression(&expr)));
+                let outcome = Job::spawn(source, emitter.toolchain(), RunLimits::default()).wait();
+
+                let RunOutcome::Finished { success: true, stdout, .. } = &outcome else {
+                    panic!("{} {}: {:?}", target.name(), input, outcome);
+                };
+                assert!(stdout.text.contains(&format!("Source preview: {}\n", input)));
+                let printed = stdout
+                    .text
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Evaluated value: "))
+                    .and_then(|value| value.parse::<i64>().ok());
+                assert_eq!(printed, expr.value(&environment).ok(), "{} {}", target.name(), input);
+            }
+
+            // 評価器が値を出さない入力では、生成したプログラムも値を出さない。
+            let ast = parse("2^-1");
+            let expr = expression(&ast, &registry, &|symbol| roles[&symbol].clone()).unwrap();
+            assert!(matches!(expr.value(&environment), Err(EvalError::NegativeExponent { .. })));
+            let source = emitter.program(&ast.to_string(), "2^-1", &[], Some(&emitter.expression(&expr)));
+            let outcome = Job::spawn(source, emitter.toolchain(), RunLimits::default()).wait();
+            assert!(!outcome.to_string().contains("Evaluated value:"), "{}: {:?}", target.name(), outcome);
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use crate::roles::{Arity, Operation, RoleDefinition, RoleRegistry, TerminalRole};
+use lr0_parser_rs::ast::{AstNode, Span};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::Peekable;
+
+/// 変数名から値への対応。Ident の終端記号はここから値を引く。
+pub type Environment = BTreeMap<String, i64>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    /// 算術の役割が割り当てられていない、または登録されていない役割の終端記号。
+    UnknownRole { symbol: char, role: TerminalRole },
+    /// Num に割り当てられたが綴りが 64 bit 整数として読めない終端記号。
+    NotANumber { lexeme: String, span: Option<Span> },
+    /// 環境に値のない変数。
+    Unbound { name: String, span: Option<Span> },
+    DivisionByZero { operation: Operation, span: Option<Span> },
+    Overflow { operation: Operation, span: Option<Span> },
+    NegativeExponent { span: Option<Span> },
+    /// 部分木が「値」「単項演算子 値」「値 演算子 値 …」のどの形にもならない。
+    Malformed { span: Option<Span> },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let at = |span: &Option<Span>| match span {
+            Some(span) => format!(" at {}..{}", span.start, span.end),
+            None => String::new(),
+        };
+        match self {
+            Self::UnknownRole { symbol, role } => {
+                write!(f, "terminal '{}' has no arithmetic role (role: {})", symbol, role)
+            }
+            Self::NotANumber { lexeme, span } => {
+                write!(f, "'{}' is marked as Num but is not a 64-bit integer{}", lexeme, at(span))
+            }
+            Self::Unbound { name, span } => write!(f, "variable `{}` has no value{}", name, at(span)),
+            Self::DivisionByZero { operation, span } => {
+                write!(f, "division by zero in `{}`{}", operation.key(), at(span))
+            }
+            Self::Overflow { operation, span } => {
+                write!(f, "integer overflow in `{}`{}", operation.key(), at(span))
+            }
+            Self::NegativeExponent { span } => write!(f, "negative exponent{}", at(span)),
+            Self::Malformed { span } => {
+                write!(f, "subtree{} is not an arithmetic expression", at(span))
+            }
+        }
+    }
+}
+
+/// 役割つきの AST から組み立てた式。値の計算と Rust の評価式の両方をここから作る。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr<'r> {
+    Number(i64),
+    Variable { name: String, span: Option<Span> },
+    Unary { operation: Operation, evaluation: &'r str, operand: Box<Expr<'r>>, span: Option<Span> },
+    Binary {
+        operation: Operation,
+        evaluation: &'r str,
+        left: Box<Expr<'r>>,
+        right: Box<Expr<'r>>,
+        span: Option<Span>,
+    },
+}
+
+// Sub の役割が被演算子の位置に来たときの単項マイナス。
+const NEGATE: &str = "-{0}";
+
+impl Expr<'_> {
+    pub fn value(&self, environment: &Environment) -> Result<i64, EvalError> {
+        match self {
+            Self::Number(value) => Ok(*value),
+            Self::Variable { name, span } => environment
+                .get(name)
+                .copied()
+                .ok_or_else(|| EvalError::Unbound { name: name.clone(), span: *span }),
+            Self::Unary { operation, operand, span, .. } => {
+                apply_unary(*operation, operand.value(environment)?, *span)
+            }
+            Self::Binary { operation, left, right, span, .. } => {
+                apply(*operation, left.value(environment)?, right.value(environment)?, *span)
+            }
+        }
+    }
+
+    /// 式に現れる変数の名前。重複はなく、名前順に並ぶ。
+    pub fn variables(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            match expr {
+                Self::Number(_) => {}
+                Self::Variable { name, .. } => names.push(name.as_str()),
+                Self::Unary { operand, .. } => stack.push(operand),
+                Self::Binary { left, right, .. } => stack.extend([&**left, &**right]),
+            }
+        }
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// 役割ごとの `evaluation` の書き方で式を組む。数値でも変数でもない被演算子は必ず括弧で包むので、
+    /// Rust 側の優先順位に関係なく木の形どおりに評価される。変数名は `variable` で書き換える。
+    pub fn render(&self, variable: &dyn Fn(&str) -> String) -> String {
+        let grouped = |operand: &Self| match operand {
+            Self::Number(_) | Self::Variable { .. } => operand.render(variable),
+            _ => format!("({})", operand.render(variable)),
+        };
+        match self {
+            Self::Number(value) => value.to_string(),
+            Self::Variable { name, .. } => variable(name),
+            Self::Unary { evaluation, operand, .. } => fill(evaluation, &[grouped(operand)]),
+            Self::Binary { evaluation, left, right, .. } => fill(evaluation, &[grouped(left), grouped(right)]),
+        }
+    }
+}
+
+// `{0}` `{1}` を被演算子で置き換える。置き換えた文字列は読み直さない。
+fn fill(template: &str, operands: &[String]) -> String {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        rendered.push_str(&rest[..open]);
+        let operand = rest[open..]
+            .strip_prefix("{0}")
+            .map(|tail| (0, tail))
+            .or_else(|| rest[open..].strip_prefix("{1}").map(|tail| (1, tail)));
+        match operand {
+            Some((index, tail)) if index < operands.len() => {
+                rendered.push_str(&operands[index]);
+                rest = tail;
+            }
+            _ => {
+                rendered.push('{');
+                rest = &rest[open + 1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+enum Item<'r> {
+    Value(Expr<'r>),
+    Operator(&'r RoleDefinition, Option<Span>),
+}
+
+type Items<'r> = Peekable<std::vec::IntoIter<Item<'r>>>;
+
+/// 役割つきの AST を式にする。部分木ごとにまとめるので、結合の順序は文法が決める。
+/// 1 つの生成規則の右辺に演算子が並ぶ場合だけ、役割の優先順位（同じなら左結合）で結合する。
+pub fn expression<'r>(
+    ast: &AstNode,
+    registry: &'r RoleRegistry,
+    role_of: &dyn Fn(char) -> TerminalRole,
+) -> Result<Expr<'r>, EvalError> {
+    match node_expr(ast, registry, role_of)? {
+        Some(expr) => Ok(expr),
+        None => Err(EvalError::Malformed { span: ast.span() }),
+    }
+}
+
+// 括弧や Ignore だけの部分木は値を持たないので None。
+fn node_expr<'r>(
+    node: &AstNode,
+    registry: &'r RoleRegistry,
+    role_of: &dyn Fn(char) -> TerminalRole,
+) -> Result<Option<Expr<'r>>, EvalError> {
+    let mut items = Vec::new();
+    match node {
+        AstNode::Terminal(_) => push_item(&mut items, node, registry, role_of)?,
+        AstNode::NonTerminal(_, children) => {
+            for child in children {
+                push_item(&mut items, child, registry, role_of)?;
+            }
+        }
+    }
+    if items.is_empty() {
+        return Ok(None);
+    }
+
+    let mut items = items.into_iter().peekable();
+    let expr = binary(&mut items, 0, node.span())?;
+    if items.next().is_some() {
+        return Err(EvalError::Malformed { span: node.span() });
+    }
+    Ok(Some(expr))
+}
+
+fn push_item<'r>(
+    items: &mut Vec<Item<'r>>,
+    node: &AstNode,
+    registry: &'r RoleRegistry,
+    role_of: &dyn Fn(char) -> TerminalRole,
+) -> Result<(), EvalError> {
+    let AstNode::Terminal(token) = node else {
+        if let Some(expr) = node_expr(node, registry, role_of)? {
+            items.push(Item::Value(expr));
+        }
+        return Ok(());
+    };
+
+    let item = match role_of(token.symbol) {
+        TerminalRole::Num => match token.lexeme.parse() {
+            Ok(value) => Item::Value(Expr::Number(value)),
+            Err(_) => {
+                return Err(EvalError::NotANumber { lexeme: token.lexeme.clone(), span: token.span });
+            }
+        },
+        TerminalRole::Ident => Item::Value(Expr::Variable { name: token.lexeme.clone(), span: token.span }),
+        TerminalRole::LParen | TerminalRole::RParen | TerminalRole::Ignore => return Ok(()),
+        role => match registry.operator(&role) {
+            Some(definition) => Item::Operator(definition, token.span),
+            None => return Err(EvalError::UnknownRole { symbol: token.symbol, role }),
+        },
+    };
+    items.push(item);
+    Ok(())
+}
+
+// 優先順位が `min` 以上の二項演算子を左結合でまとめる。
+fn binary<'r>(items: &mut Items<'r>, min: u8, span: Option<Span>) -> Result<Expr<'r>, EvalError> {
+    let mut left = operand(items, span)?;
+    while let Some(&Item::Operator(definition, at)) = items.peek()
+        && definition.arity == Arity::Binary
+        && definition.precedence >= min
+    {
+        items.next();
+        let right = binary(items, definition.precedence.saturating_add(1), span)?;
+        left = Expr::Binary {
+            operation: definition.operation,
+            evaluation: &definition.evaluation,
+            left: Box::new(left),
+            right: Box::new(right),
+            span: at,
+        };
+    }
+    Ok(left)
+}
+
+fn operand<'r>(items: &mut Items<'r>, span: Option<Span>) -> Result<Expr<'r>, EvalError> {
+    let Some(item) = items.next() else {
+        return Err(EvalError::Malformed { span });
+    };
+    let (definition, at) = match item {
+        Item::Value(expr) => return Ok(expr),
+        Item::Operator(definition, at) => (definition, at),
+    };
+    match (definition.arity, definition.operation) {
+        // 単項演算子は自分の優先順位より弱い二項演算子の手前までを被演算子にする。
+        (Arity::Unary, operation) => Ok(Expr::Unary {
+            operation,
+            evaluation: &definition.evaluation,
+            operand: Box::new(binary(items, definition.precedence, span)?),
+            span: at,
+        }),
+        // 被演算子の位置の + と - は、何よりも強く結合する符号として読む。
+        (Arity::Binary, Operation::Add) => operand(items, span),
+        (Arity::Binary, Operation::Sub) => Ok(Expr::Unary {
+            operation: Operation::Neg,
+            evaluation: NEGATE,
+            operand: Box::new(operand(items, span)?),
+            span: at,
+        }),
+        (Arity::Binary, _) => Err(EvalError::Malformed { span }),
+    }
+}
+
+/// 単項演算を 1 つ適用する。`bytecode` の VM もこれを使うので、エラーは評価器と同じになる。
+pub fn apply_unary(operation: Operation, value: i64, span: Option<Span>) -> Result<i64, EvalError> {
+    match operation {
+        Operation::Neg => value.checked_neg().ok_or(EvalError::Overflow { operation, span }),
+        Operation::Not => Ok(i64::from(value == 0)),
+        _ => Err(EvalError::Malformed { span }),
+    }
+}
+
+pub fn apply(operation: Operation, left: i64, right: i64, span: Option<Span>) -> Result<i64, EvalError> {
+    if matches!(operation, Operation::Div | Operation::Mod) && right == 0 {
+        return Err(EvalError::DivisionByZero { operation, span });
+    }
+    let overflow = EvalError::Overflow { operation, span };
+    match operation {
+        Operation::Add => left.checked_add(right).ok_or(overflow),
+        Operation::Sub => left.checked_sub(right).ok_or(overflow),
+        Operation::Mul => left.checked_mul(right).ok_or(overflow),
+        Operation::Div => left.checked_div(right).ok_or(overflow),
+        Operation::Mod => left.checked_rem(right).ok_or(overflow),
+        Operation::Pow if right < 0 => Err(EvalError::NegativeExponent { span }),
+        Operation::Pow => u32::try_from(right)
+            .ok()
+            .and_then(|exponent| left.checked_pow(exponent))
+            .ok_or(overflow),
+        Operation::And => Ok(i64::from(left != 0 && right != 0)),
+        Operation::Or => Ok(i64::from(left != 0 || right != 0)),
+        Operation::Neg | Operation::Not => Err(EvalError::Malformed { span }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lr0_parser_rs::grammar::{parse_grammar_text, parse_input_text};
+    use lr0_parser_rs::lr::compile;
+    use lr0_parser_rs::runtime::run;
+
+    fn role(symbol: char) -> TerminalRole {
+        match symbol {
+            '^' => TerminalRole::Custom("Pow".to_string()),
+            '!' => TerminalRole::Custom("Not".to_string()),
+            symbol => TerminalRole::default_for(symbol),
+        }
+    }
+
+    fn registry() -> RoleRegistry {
+        let mut registry = RoleRegistry::default();
+        registry
+            .define(RoleDefinition {
+                name: "Pow".to_string(),
+                source: String::new(),
+                evaluation: "i64::pow({0}, {1} as u32)".to_string(),
+                arity: Arity::Binary,
+                precedence: 3,
+                operation: Operation::Pow,
+            })
+            .unwrap();
+        registry
+            .define(RoleDefinition {
+                name: "Not".to_string(),
+                source: String::new(),
+                evaluation: "i64::from({0} == 0)".to_string(),
+                arity: Arity::Unary,
+                precedence: 0,
+                operation: Operation::Not,
+            })
+            .unwrap();
+        registry
+    }
+
+    fn parse(grammar: &str, input: &str) -> AstNode {
+        let machine = compile(&parse_grammar_text(grammar).unwrap()).unwrap();
+        run(&machine, &parse_input_text(input).unwrap()).unwrap().ast
+    }
+
+    fn eval(grammar: &str, input: &str) -> Result<i64, EvalError> {
+        expression(&parse(grammar, input), &registry(), &role)?.value(&Environment::new())
+    }
+
+    const ARITHMETIC: &str = "E -> E+B\nE -> E-B\nE -> E*B\nE -> E/B\nE -> E%B\nE -> B\n\
+                              B -> (E)\nB -> -B\nB -> 0\nB -> 1\nB -> 2\nB -> 7\nB -> 9";
+
+    #[test]
+    fn values_follow_the_tree_shape() {
+        assert_eq!(eval(include_str!("../reducer"), "1+1*0"), Ok(0));
+        assert_eq!(eval(ARITHMETIC, "7-2*(1+2)"), Ok(15));
+        assert_eq!(eval(ARITHMETIC, "7%2+-9/2"), Ok(-4));
+        assert_eq!(eval(ARITHMETIC, "--7"), Ok(7));
+    }
+
+    #[test]
+    fn flat_right_hand_sides_use_the_role_precedence() {
+        assert_eq!(eval("S -> 1+2*7", "1+2*7"), Ok(15));
+        assert_eq!(eval("S -> 2*2^2^2", "2*2^2^2"), Ok(32));
+        assert_eq!(eval("S -> !1+1", "!1+1"), Ok(0));
+
+        let ast = parse("S -> 2*2^2^2", "2*2^2^2");
+        let registry = registry();
+        assert_eq!(
+            expression(&ast, &registry, &role).unwrap().render(&str::to_string),
+            "2*(i64::pow((i64::pow(2, 2 as u32)), 2 as u32))"
+        );
+    }
+
+    #[test]
+    fn arithmetic_errors_are_values() {
+        assert_eq!(
+            eval(ARITHMETIC, "7/(1-1)"),
+            Err(EvalError::DivisionByZero { operation: Operation::Div, span: Some(Span::new(1, 2)) })
+        );
+        assert!(matches!(eval(ARITHMETIC, "7%0"), Err(EvalError::DivisionByZero { .. })));
+
+        let nines = vec!["9"; 20].join("*");
+        assert!(matches!(
+            eval(ARITHMETIC, &nines),
+            Err(EvalError::Overflow { operation: Operation::Mul, .. })
+        ));
+    }
+
+    #[test]
+    fn unknown_roles_and_bad_shapes_are_reported() {
+        assert_eq!(
+            eval("S -> a+1", "a+1"),
+            Err(EvalError::UnknownRole { symbol: 'a', role: TerminalRole::Token })
+        );
+        assert!(matches!(eval("S -> 1*", "1*"), Err(EvalError::Malformed { .. })));
+        assert!(matches!(eval("S -> 12", "12"), Err(EvalError::Malformed { .. })));
+    }
+}
@@ -1,35 +1,65 @@
-use lr0_parser_rs::AstNode;
-use lr0_parser_rs::grammar::{parse_grammar_text, parse_input_text};
+use crate::bytecode::Program;
+use crate::emit::Target;
+use crate::evaluator::{Environment, EvalError, expression};
+use crate::lexer::lex;
+use crate::role_check::{RoleWarning, check_roles};
+use crate::roles::{RoleRegistry, TerminalRole};
+use crate::runner::RunOutcome;
+use lr0_parser_rs::{AstNode, Token};
+use lr0_parser_rs::attribute::{AttributeRules, decorate};
+use lr0_parser_rs::codegen::rust::{self as rust_codegen, RustOptions};
+use lr0_parser_rs::grammar::parse_grammar_text;
+use lr0_parser_rs::lower::{LoweringRules, lower};
 use lr0_parser_rs::lr::compile;
 use lr0_parser_rs::runtime::run;
-use std::collections::HashMap;
-use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
+use lr0_parser_rs::translate::{TranslationScheme, translate};
+use std::collections::{BTreeSet, HashMap};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GenerationOutput {
     pub ast_preview: String,
     pub ast: Option<AstNode>,
+    pub lowered_ast: Option<AstNode>,
+    pub rules: Vec<String>,
     pub source_preview: String,
+    /// 生成先の言語での評価式。
     pub evaluation_expression: Option<String>,
+    /// `evaluator::expression` によるその場での評価結果。評価式が作れないときは None。
+    pub evaluation: Option<Result<i64, EvalError>>,
+    /// 評価式をスタックマシンの命令列にしたもの。評価式と同じく作れないときは None。
+    pub bytecode: Option<Program>,
+    /// 文法に翻訳テンプレートがあればその翻訳、なければ評価式を表示する生成先の言語のプログラム。
     pub generated_code: String,
+    pub translated: bool,
+    /// 文法と役割の静的な検査で見つかった、評価式にならない形。
+    pub role_warnings: Vec<RoleWarning>,
+    /// 文法から生成した単体のパーサーモジュール。
+    pub parser_module: String,
     pub notes: Vec<String>,
 }
 
 pub struct GeneratorEngine {
-    pub terminal_types: HashMap<char, String>,
+    pub terminal_roles: HashMap<char, TerminalRole>,
+    pub registry: RoleRegistry,
+    /// 評価式とプログラムを書き出す言語。
+    pub target: Target,
+    /// Ident の終端記号の値。生成するプログラムでは式の前で宣言する。
+    pub variables: Environment,
 }
 
 impl GeneratorEngine {
     pub fn new() -> Self {
         Self {
-            terminal_types: HashMap::new(),
+            terminal_roles: HashMap::new(),
+            registry: RoleRegistry::default(),
+            target: Target::default(),
+            variables: Environment::new(),
         }
     }
 
-    pub fn set_terminal_types(&mut self, terminal_types: HashMap<char, String>) {
-        self.terminal_types = terminal_types;
+    pub fn set_roles(&mut self, terminal_roles: HashMap<char, TerminalRole>, registry: RoleRegistry) {
+        self.terminal_roles = terminal_roles;
+        self.registry = registry;
     }
 
     pub fn generate_output(
@@ -41,245 +71,382 @@ impl GeneratorEngine {
             .map_err(|err| format!("Failed to parse grammar: {err:?}"))?;
         let machine =
             compile(&grammar).map_err(|err| format!("Failed to compile parser: {err:?}"))?;
-        let input = parse_input_text(input_string)
+        // 数や識別子は役割を見て 1 つの終端記号にまとめ、入力上の綴りは木の葉に戻す。
+        let input = lex(input_string, &self.terminal_roles)
             .map_err(|err| format!("Failed to parse input: {err:?}"))?;
         let result =
-            run(&machine, &input).map_err(|err| format!("Failed to run parser: {err:?}"))?;
-
-        let ast = result.ast;
+            run(&machine, &input.symbols).map_err(|err| format!("Failed to run parser: {err:?}"))?;
+
+        let ast = input.attach(&result.ast);
+        // 属性は具象構文木の上で計算し、値を持たせたまま下ろしてプレビューに出す。
+        // collapse や root で親の代わりに残ったノードには、`lower` が親の値を引き継がせる。
+        let attribute_rules = AttributeRules::from_grammar(&grammar);
+        let (decorated, attribute_note) = if attribute_rules.is_empty() {
+            (None, None)
+        } else {
+            match decorate(&ast, &attribute_rules) {
+                Ok(decorated) => (Some(decorated), None),
+                Err(error) => (None, Some(format!("Attribute evaluation failed: {}", error))),
+            }
+        };
+        let lowered_ast = lower(decorated.as_ref().unwrap_or(&ast), &LoweringRules::from_grammar(&grammar));
         let ast_preview = ast.to_string();
         let source_preview = self.render_source_preview(&ast);
-        let evaluation_expression = self.render_evaluation_expression(&ast);
-
-        let mut notes = Vec::new();
+        let emitter = self.target.emitter();
+        let mut unbound = Vec::new();
+        let (evaluation_expression, evaluation, bytecode, bindings) =
+            match expression(&ast, &self.registry, &|symbol| self.terminal_role(symbol)) {
+                Ok(expr) => {
+                    let mut bindings = Vec::new();
+                    for name in expr.variables() {
+                        match self.variables.get(name) {
+                            Some(value) => bindings.push((name.to_string(), *value)),
+                            None => unbound.push(name.to_string()),
+                        }
+                    }
+                    (
+                        Some(emitter.expression(&expr)),
+                        Some(expr.value(&self.variables)),
+                        Some(Program::compile(&expr)),
+                        bindings,
+                    )
+                }
+                // Token のままの終端記号があるうちは評価しない
+                Err(EvalError::UnknownRole { role: TerminalRole::Token, .. }) => (None, None, None, Vec::new()),
+                Err(error) => (None, Some(Err(error)), None, Vec::new()),
+            };
+
+        let mut notes: Vec<String> = attribute_note.into_iter().collect();
         if source_preview.is_empty() {
             notes.push("The reconstructed source preview is empty.".to_string());
         }
-        if evaluation_expression.is_none() {
+        let role_warnings = check_roles(&grammar, &self.terminal_roles, &self.registry);
+        if evaluation.is_none() && role_warnings.is_empty() {
             notes.push(
-                "Assign arithmetic roles such as Num/Add/Mul/LParen/RParen to generate an evaluable Rust expression."
+                "Assign arithmetic roles such as Num/Add/Mul/LParen/RParen to generate an evaluable expression."
                     .to_string(),
             );
         }
-        if self.terminal_types.is_empty() {
+        if !unbound.is_empty() {
+            notes.push(format!(
+                "Give {} a value under Variables to evaluate and generate the expression.",
+                unbound.iter().map(|name| format!("`{}`", name)).collect::<Vec<_>>().join(", ")
+            ));
+        }
+        if self.terminal_roles.is_empty() {
             notes.push(
                 "No terminal role overrides are set yet, so raw terminal characters are used."
                     .to_string(),
             );
         }
 
-        let generated_code = self.build_rust_program(
-            &ast_preview,
-            &source_preview,
-            evaluation_expression.as_deref(),
-        );
+        let scheme = TranslationScheme::from_grammar(&grammar);
+        let translated = !scheme.is_empty();
+        let generated_code = if translated {
+            notes.push("Output comes from the translation templates (=> \"...\") in the grammar.".to_string());
+            translate(&ast, &scheme)
+        } else {
+            // 独自の役割の `evaluation` は Rust の式なので、ほかの言語は演算の種類から式を組む
+            if self.target != Target::Rust {
+                let custom: BTreeSet<String> = grammar
+                    .terminals()
+                    .into_iter()
+                    .filter_map(|terminal| match self.terminal_roles.get(&terminal.0) {
+                        Some(role @ TerminalRole::Custom(_)) => self.registry.operator(role),
+                        _ => None,
+                    })
+                    .map(|definition| format!("`{}` ({})", definition.name, definition.operation.key()))
+                    .collect();
+                if !custom.is_empty() {
+                    notes.push(format!(
+                        "Evaluation templates of custom roles are Rust-only; the {} program computes {} from the operation instead.",
+                        emitter.name(),
+                        custom.into_iter().collect::<Vec<_>>().join(", ")
+                    ));
+                }
+            }
+            let bindings: Vec<(&str, i64)> = bindings.iter().map(|(name, value)| (name.as_str(), *value)).collect();
+            // 値のない変数があるとプログラムが通らないので、評価値の行を出さない
+            let expression = evaluation_expression.as_deref().filter(|_| unbound.is_empty());
+            emitter.program(&ast_preview, &source_preview, &bindings, expression)
+        };
 
         Ok(GenerationOutput {
             ast_preview,
             ast: Some(ast),
+            lowered_ast: Some(lowered_ast),
+            rules: machine
+                .productions()
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
             source_preview,
             evaluation_expression,
+            evaluation,
+            bytecode,
             generated_code,
+            translated,
+            role_warnings,
+            parser_module: rust_codegen::generate(&machine, RustOptions::default()),
             notes,
         })
     }
 
     fn render_source_preview(&self, node: &AstNode) -> String {
-        let mut output = String::new();
-        self.push_rendered_terminals(node, &mut output, false);
-        output
-    }
-
-    fn render_evaluation_expression(&self, node: &AstNode) -> Option<String> {
-        let mut output = String::new();
-        self.push_rendered_terminals(node, &mut output, true)
-            .then_some(output)
+        node.leaves().map(|token| self.render_terminal_for_source(token)).collect()
     }
 
-    fn push_rendered_terminals(
-        &self,
-        node: &AstNode,
-        output: &mut String,
-        arithmetic_only: bool,
-    ) -> bool {
-        match node {
-            AstNode::Terminal(symbol) => {
-                let rendered = if arithmetic_only {
-                    self.render_terminal_for_evaluation(*symbol)
-                } else {
-                    Some(self.render_terminal_for_source(*symbol))
-                };
-
-                let Some(rendered) = rendered else {
-                    return false;
-                };
-                output.push_str(&rendered);
-                true
-            }
-            AstNode::NonTerminal(_, children) => children
-                .iter()
-                .all(|child| self.push_rendered_terminals(child, output, arithmetic_only)),
-        }
-    }
-
-    fn render_terminal_for_source(&self, symbol: char) -> String {
-        match self.terminal_role(symbol).as_str() {
-            "Ignore" => String::new(),
-            "LParen" | "L_paren" => "(".to_string(),
-            "RParen" | "R_paren" => ")".to_string(),
-            _ => symbol.to_string(),
+    fn render_terminal_for_source(&self, token: &Token) -> String {
+        match self.terminal_role(token.symbol) {
+            TerminalRole::Ignore => String::new(),
+            TerminalRole::LParen => "(".to_string(),
+            TerminalRole::RParen => ")".to_string(),
+            role => match self.registry.operator(&role) {
+                Some(definition) if !definition.source.is_empty() => definition.source.clone(),
+                _ => token.lexeme.clone(),
+            },
         }
     }
 
-    fn render_terminal_for_evaluation(&self, symbol: char) -> Option<String> {
-        let rendered = match self.terminal_role(symbol).as_str() {
-            "Num" => symbol.to_string(),
-            "Add" => "+".to_string(),
-            "Sub" => "-".to_string(),
-            "Mul" => "*".to_string(),
-            "Div" => "/".to_string(),
-            "Mod" => "%".to_string(),
-            "LParen" | "L_paren" => "(".to_string(),
-            "RParen" | "R_paren" => ")".to_string(),
-            "Ignore" => String::new(),
-            _ => return None,
-        };
-        Some(rendered)
-    }
-
-    fn terminal_role(&self, symbol: char) -> String {
-        self.terminal_types
+    fn terminal_role(&self, symbol: char) -> TerminalRole {
+        self.terminal_roles
             .get(&symbol)
             .cloned()
-            .unwrap_or_else(|| "Token".to_string())
+            .unwrap_or(TerminalRole::Token)
     }
+}
 
-    fn build_rust_program(
-        &self,
-        ast_preview: &str,
-        source_preview: &str,
-        evaluation_expression: Option<&str>,
-    ) -> String {
-        let escaped_ast = escape_rust_string(ast_preview);
-        let escaped_source = escape_rust_string(source_preview);
-
-        let mut generated = String::new();
-        generated.push_str("// Generated by the LR(0) Parser GUI\n");
-        generated.push_str("fn generated_ast() -> &'static str {\n");
-        generated.push_str(&format!("    \"{}\"\n", escaped_ast));
-        generated.push_str("}\n\n");
-        generated.push_str("fn generated_source() -> &'static str {\n");
-        generated.push_str(&format!("    \"{}\"\n", escaped_source));
-        generated.push_str("}\n\n");
-        generated.push_str("fn main() {\n");
-        generated.push_str("    println!(\"AST:\\n{}\", generated_ast());\n");
-        generated.push_str("    println!(\"Source preview: {}\", generated_source());\n");
-
-        if let Some(expression) = evaluation_expression {
-            generated.push_str(&format!("    let value = {};\n", expression));
-            generated.push_str("    println!(\"Evaluated value: {}\", value);\n");
-        } else {
-            generated.push_str(
-                "    println!(\"Evaluated value: <not available for current terminal mappings>\");\n",
-            );
+/// その場での評価結果を、生成したプログラムを `runner::Job` で走らせた結果と突き合わせる。
+/// `tool` は報告に出す処理系の名前。処理系がなければその旨を返すだけで、評価そのものには使わない。
+pub fn cross_check_report(
+    outcome: &RunOutcome,
+    evaluation: Option<&Result<i64, EvalError>>,
+    tool: &str,
+) -> String {
+    let printed = match outcome {
+        RunOutcome::Finished { success: true, stdout, .. } => stdout
+            .text
+            .lines()
+            .find_map(|line| line.strip_prefix("Evaluated value: "))
+            .and_then(|value| value.trim().parse::<i64>().ok()),
+        _ => None,
+    };
+
+    let verdict = match (evaluation, printed) {
+        (Some(Ok(expected)), Some(actual)) if *expected == actual => {
+            format!("{} agrees with the in-process value {}", tool, expected)
         }
+        (Some(Ok(expected)), Some(actual)) => {
+            format!("MISMATCH: in-process value {}, {} printed {}", expected, tool, actual)
+        }
+        (Some(Ok(expected)), None) => {
+            format!("{} did not print a value (in-process value {})", tool, expected)
+        }
+        (Some(Err(error)), _) => format!("in-process evaluation failed: {}", error),
+        (None, _) => "no in-process value to compare".to_string(),
+    };
+    format!("{}\n\n{}", verdict, outcome)
+}
 
-        generated.push_str("}\n");
-        generated
+impl Default for GeneratorEngine {
+    fn default() -> Self {
+        Self::new()
     }
-
 }
 
-pub fn run_generated_code(generated_code: &str) -> String {
-    if generated_code.trim().is_empty() {
-        return "No code to run. Please generate code first.".to_string();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roles::{Arity, Operation, RoleDefinition};
+    use crate::runner::{Job, RunLimits};
+
+    #[test]
+    fn generate_output_builds_source_preview() {
+        let mut engine = GeneratorEngine::new();
+        engine.terminal_roles.insert('+', TerminalRole::Add);
+        engine.terminal_roles.insert('*', TerminalRole::Mul);
+        engine.terminal_roles.insert('1', TerminalRole::Num);
+        engine.terminal_roles.insert('0', TerminalRole::Num);
+
+        let output = engine
+            .generate_output("E -> E*B\nE -> E+B\nE -> B\nB -> 0\nB -> 1", "1+0")
+            .unwrap();
+
+        assert!(output.source_preview.contains("1+0"));
+        assert_eq!(output.evaluation_expression.as_deref(), Some("1+0"));
     }
 
-    let build_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-        .join("target")
-        .join("generated");
+    #[test]
+    fn generate_output_lowers_the_tree_with_grammar_directives() {
+        let engine = GeneratorEngine::new();
+
+        let output = engine
+            .generate_output(
+                "E -> E+B :: root 1\nE -> B :: collapse\nB -> 0 :: collapse\nB -> 1 :: collapse",
+                "1+0",
+            )
+            .unwrap();
+
+        assert_eq!(output.lowered_ast.unwrap().to_string(), "+\n    1\n    0\n");
+        assert!(output.ast_preview.starts_with("E\n    E\n        B\n"));
+    }
 
-    if let Err(err) = fs::create_dir_all(&build_dir) {
-        return format!("Failed to create build directory: {err}");
+    #[test]
+    fn attribute_values_decorate_the_preview_tree() {
+        let grammar = "E -> E+B\n@ $$.val = $0.val + $2.val\nE -> B\n@ $$.val = $0.val\n\
+                       B -> 1\n@ $$.val = $0.val";
+        let output = GeneratorEngine::new().generate_output(grammar, "1+1").unwrap();
+        let Some(AstNode::NonTerminal(info, _)) = &output.lowered_ast else { panic!() };
+        assert_eq!(info.attributes.get("val").map(String::as_str), Some("2"));
+
+        let output = GeneratorEngine::new()
+            .generate_output("S -> A\n@ $$.val = $0.val\nA -> 1", "1")
+            .unwrap();
+        assert!(output.notes[0].starts_with("Attribute evaluation failed: A.val"), "{:?}", output.notes);
     }
 
-    let source_path = build_dir.join("generated_main.rs");
-    let binary_path = build_dir.join(format!("generated_program{}", std::env::consts::EXE_SUFFIX));
+    #[test]
+    fn translation_templates_replace_the_rust_program() {
+        let output = GeneratorEngine::new()
+            .generate_output("E -> E+B => \"(+ {0} {2})\"\nE -> B\nB -> 0\nB -> 1", "1+0+1")
+            .unwrap();
 
-    if let Err(err) = fs::write(&source_path, generated_code) {
-        return format!("Failed to write generated source: {err}");
+        assert!(output.translated);
+        assert_eq!(output.generated_code, "(+ (+ 1 0) 1)");
     }
 
-    let compile_output = match Command::new("rustc")
-        .arg("--edition=2024")
-        .arg(&source_path)
-        .arg("-o")
-        .arg(&binary_path)
-        .output()
-    {
-        Ok(output) => output,
-        Err(err) => return format!("Failed to invoke rustc: {err}"),
-    };
+    fn arithmetic_engine() -> GeneratorEngine {
+        let mut engine = GeneratorEngine::new();
+        for (symbol, role) in [
+            ('+', TerminalRole::Add),
+            ('-', TerminalRole::Sub),
+            ('*', TerminalRole::Mul),
+            ('/', TerminalRole::Div),
+            ('(', TerminalRole::LParen),
+            (')', TerminalRole::RParen),
+            (';', TerminalRole::Ignore),
+            ('0', TerminalRole::Num),
+            ('1', TerminalRole::Num),
+            ('2', TerminalRole::Num),
+        ] {
+            engine.terminal_roles.insert(symbol, role);
+        }
+        engine
+    }
 
-    if !compile_output.status.success() {
-        return format!(
-            "Rust compilation failed.\n{}",
-            String::from_utf8_lossy(&compile_output.stderr)
-        );
+    fn expression(grammar: &str, input: &str) -> String {
+        arithmetic_engine()
+            .generate_output(grammar, input)
+            .unwrap()
+            .evaluation_expression
+            .unwrap()
     }
 
-    let run_output = match Command::new(&binary_path).output() {
-        Ok(output) => output,
-        Err(err) => return format!("Failed to run generated binary: {err}"),
-    };
+    #[test]
+    fn evaluation_follows_the_grammar_precedence() {
+        // 既定文法では + と * が同じ優先順位の左結合なので、1+1*0 は (1+1)*0。
+        assert_eq!(expression(include_str!("../reducer"), "1+1*0"), "(1+1)*0");
+        assert_eq!(expression(include_str!("../reducer"), "1*0+1"), "(1*0)+1");
+    }
 
-    let stdout = String::from_utf8_lossy(&run_output.stdout);
-    let stderr = String::from_utf8_lossy(&run_output.stderr);
+    #[test]
+    fn evaluation_follows_the_grammar_associativity() {
+        // 右再帰の文法では 2-1-1 は 2-(1-1)、2/2/2 は 2/(2/2)。LR(0) に収めるため末尾を ';' で閉じる。
+        let grammar = "E -> B-E\nE -> B/E\nE -> B;\nB -> 1\nB -> 2";
+        assert_eq!(expression(grammar, "2-1-1;"), "2-(1-1)");
+        assert_eq!(expression(grammar, "2/2/2;"), "2/(2/2)");
+    }
 
-    if run_output.status.success() {
-        if stderr.trim().is_empty() {
-            stdout.trim_end().to_string()
-        } else {
-            format!("{}\n\nstderr:\n{}", stdout.trim_end(), stderr.trim_end())
-        }
-    } else if stdout.trim().is_empty() {
-        format!("Generated binary failed.\n{}", stderr.trim_end())
-    } else {
-        format!(
-            "Generated binary failed.\nstdout:\n{}\n\nstderr:\n{}",
-            stdout.trim_end(),
-            stderr.trim_end()
-        )
+    #[test]
+    fn grammar_parentheses_become_the_tree_grouping() {
+        let grammar = "E -> E*B\nE -> E+B\nE -> B\nB -> (E)\nB -> 1\nB -> 2";
+        assert_eq!(expression(grammar, "2*(1+1)"), "2*(1+1)");
+        assert_eq!(expression(grammar, "1+2*(1+1)"), "(1+2)*(1+1)");
+        assert_eq!(expression(grammar, "((2))"), "2");
     }
-}
 
-fn escape_rust_string(value: &str) -> String {
-    value.chars().flat_map(char::escape_default).collect()
-}
+    #[test]
+    fn generation_evaluates_in_process() {
+        let output = arithmetic_engine()
+            .generate_output(include_str!("../reducer"), "1+1*0")
+            .unwrap();
+        assert_eq!(output.evaluation, Some(Ok(0)));
 
-impl Default for GeneratorEngine {
-    fn default() -> Self {
-        Self::new()
+        let output = GeneratorEngine::new().generate_output(include_str!("../reducer"), "1").unwrap();
+        assert_eq!(output.evaluation, None);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn identifiers_and_numbers_use_their_lexemes_and_variables() {
+        let mut engine = arithmetic_engine();
+        engine.terminal_roles.insert('n', TerminalRole::Num);
+        engine.terminal_roles.insert('i', TerminalRole::Ident);
+        let grammar = "E -> E+B\nE -> E*B\nE -> B\nB -> n\nB -> i";
+
+        let output = engine.generate_output(grammar, "x*10+y").unwrap();
+        assert_eq!(output.source_preview, "x*10+y");
+        assert_eq!(output.evaluation_expression.as_deref(), Some("(x*10)+y"));
+        assert!(matches!(output.evaluation, Some(Err(EvalError::Unbound { .. }))));
+        assert!(output.notes.iter().any(|note| note.contains("`x`, `y`")), "{:?}", output.notes);
+        assert!(output.generated_code.contains("<not available"));
+
+        engine.variables = Environment::from([("x".to_string(), 4), ("y".to_string(), 2)]);
+        let output = engine.generate_output(grammar, "x*10+y").unwrap();
+        assert_eq!(output.evaluation, Some(Ok(42)));
+        assert!(output.generated_code.contains("    let x: i64 = 4;\n    let y: i64 = 2;\n"));
+    }
 
     #[test]
-    fn generate_output_builds_source_preview() {
-        let mut engine = GeneratorEngine::new();
-        engine.terminal_types.insert('+', "Add".to_string());
-        engine.terminal_types.insert('*', "Mul".to_string());
-        engine.terminal_types.insert('1', "Num".to_string());
-        engine.terminal_types.insert('0', "Num".to_string());
+    fn role_warnings_replace_the_generic_note() {
+        let output = arithmetic_engine().generate_output("E -> E+B\nE -> B\nB -> 12", "12").unwrap();
+
+        assert!(matches!(output.evaluation, Some(Err(EvalError::Malformed { .. }))));
+        // 文法にない '-' '*' などの役割は終端記号への警告として後ろに並ぶ
+        assert!(output.role_warnings[0].to_string().starts_with("r3 `B -> 12`"));
+        assert!(output.role_warnings[1..].iter().all(|warning| warning.to_string().starts_with("terminal ")));
+        assert!(!output.notes.iter().any(|note| note.starts_with("Assign arithmetic roles")));
+    }
 
-        let output = engine
-            .generate_output("E -> E*B\nE -> E+B\nE -> B\nB -> 0\nB -> 1", "1+0")
+    #[test]
+    fn custom_roles_outside_rust_are_noted() {
+        let mut engine = arithmetic_engine();
+        engine.terminal_roles.insert('^', TerminalRole::Custom("Pow".to_string()));
+        engine
+            .registry
+            .define(RoleDefinition {
+                name: "Pow".to_string(),
+                source: String::new(),
+                evaluation: "i64::pow({0}, {1} as u32)".to_string(),
+                arity: Arity::Binary,
+                precedence: 3,
+                operation: Operation::Pow,
+            })
             .unwrap();
+        let grammar = "E -> E^B\nE -> B\nB -> 2";
+        let rust_only = |output: &GenerationOutput| output.notes.iter().any(|note| note.contains("Rust-only"));
+
+        assert!(!rust_only(&engine.generate_output(grammar, "2^2").unwrap()));
+        engine.target = Target::Python;
+        let output = engine.generate_output(grammar, "2^2").unwrap();
+        assert!(rust_only(&output), "{:?}", output.notes);
+        assert!(output.notes.iter().any(|note| note.contains("the Python program computes `Pow` (pow)")));
+    }
 
-        assert!(output.source_preview.contains("1+0"));
-        assert_eq!(output.evaluation_expression.as_deref(), Some("1+0"));
+    #[test]
+    fn cross_check_agrees_with_the_evaluator_for_the_selected_target() {
+        for target in Target::ALL {
+            let toolchain = target.emitter().toolchain();
+            if !toolchain.available() {
+                continue;
+            }
+            let mut engine = arithmetic_engine();
+            engine.target = target;
+            let output = engine.generate_output(include_str!("../reducer"), "1+1*0").unwrap();
+
+            let outcome = Job::spawn(output.generated_code.clone(), toolchain, RunLimits::default()).wait();
+            let report = cross_check_report(&outcome, output.evaluation.as_ref(), toolchain.program);
+            let expected = format!("{} agrees with the in-process value 0", toolchain.program);
+            assert!(report.starts_with(&expected), "{}", report);
+        }
     }
 }
@@ -1,4 +1,7 @@
+use crate::attribute::{Equation, parse_equations};
 use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
 
 pub fn read_file(path: &str) -> Result<String, std::io::Error> {
     std::fs::read_to_string(path)
@@ -7,12 +10,37 @@ pub fn read_file(path: &str) -> Result<String, std::io::Error> {
 pub fn parse_grammar_text(input: &str) -> Result<Grammar, GrammarError> {
     let mut productions = Vec::new();
 
+    let mut directives = Vec::new();
+    let mut translations = Vec::new();
+    let mut attributes: Vec<Vec<Equation>> = Vec::new();
+
     for line in input.lines() {
         let line = line.trim();
         if line.is_empty() {
             continue;
         }
 
+        // `@` で始まる行は直前の生成規則の属性方程式。
+        if let Some(text) = line.strip_prefix('@') {
+            let (Some(production), Some(equations)) = (productions.last(), attributes.last_mut()) else {
+                return Err(GrammarError::InvalidAttribute(
+                    "attribute equations must follow a production".to_string(),
+                ));
+            };
+            let parsed = parse_equations(text, production).map_err(GrammarError::InvalidAttribute)?;
+            for equation in parsed {
+                if equations.iter().any(|existing: &Equation| existing.target == equation.target) {
+                    return Err(GrammarError::InvalidAttribute(format!("{} is defined twice", equation.target)));
+                }
+                equations.push(equation);
+            }
+            continue;
+        }
+
+        // テンプレートの中に `::` が書けるよう、先に `=> "..."` を切り離す。
+        let (line, translation_text) = split_translation(line);
+        let (line, directive_text) = split_directives(line);
+
         let mut parts = line.split("->");
         let left = parts
             .next()
@@ -45,6 +73,15 @@ pub fn parse_grammar_text(input: &str) -> Result<Grammar, GrammarError> {
             .map(Symbol::from_char)
             .collect::<Result<Vec<_>, _>>()?;
 
+        directives.push(match directive_text {
+            Some(text) => parse_directives(text, right.len())?,
+            None => Vec::new(),
+        });
+        translations.push(match translation_text {
+            Some(text) => Some(parse_translation(text, right.len())?),
+            None => None,
+        });
+        attributes.push(Vec::new());
         productions.push(Production {
             left: NonTerminal(left),
             right,
@@ -56,13 +93,174 @@ pub fn parse_grammar_text(input: &str) -> Result<Grammar, GrammarError> {
         .map(|production| production.left)
         .ok_or(GrammarError::EmptyGrammar)?;
 
-    Ok(Grammar { start, productions })
+    Ok(Grammar {
+        start,
+        productions,
+        directives,
+        translations,
+        attributes,
+    })
+}
+
+// 行末の `=> "..."` を切り出す。`=>` の後ろが `"` で始まるものだけをテンプレートとみなす。
+fn split_translation(line: &str) -> (&str, Option<&str>) {
+    line.match_indices("=>")
+        .find(|(at, _)| line[at + 2..].trim_start().starts_with('"'))
+        .map(|(at, _)| (&line[..at], Some(line[at + 2..].trim())))
+        .unwrap_or((line, None))
+}
+
+// 前後を空白で挟まれた `::` で切る。`S -> a::b` のように終端記号として並んだ `:` は切らない。
+fn split_directives(line: &str) -> (&str, Option<&str>) {
+    line.match_indices("::")
+        .find(|(at, _)| {
+            let (before, after) = (&line[..*at], &line[at + 2..]);
+            before.ends_with(char::is_whitespace) && (after.is_empty() || after.starts_with(char::is_whitespace))
+        })
+        .map(|(at, _)| (&line[..at], Some(&line[at + 2..])))
+        .unwrap_or((line, None))
+}
+
+/// `=>` 以降の翻訳テンプレートを読む。`"..."` の中では `\"` `\\` `\n` `\t` が使え、
+/// `{i}` は右辺 i 番目の子の翻訳、`{{` と `}}` は波括弧そのもの。
+fn parse_translation(text: &str, arity: usize) -> Result<Translation, GrammarError> {
+    let invalid = |message: String| GrammarError::InvalidTranslation(message);
+    let mut chars = text.chars();
+    if chars.next() != Some('"') {
+        return Err(invalid("translation must be a quoted string".to_string()));
+    }
+
+    let mut literal = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => break,
+            Some('\\') => match chars.next() {
+                Some('n') => literal.push('\n'),
+                Some('t') => literal.push('\t'),
+                Some(escaped @ ('"' | '\\')) => literal.push(escaped),
+                other => {
+                    return Err(invalid(format!("unknown escape '\\{}'", other.unwrap_or(' '))));
+                }
+            },
+            Some(c) => literal.push(c),
+            None => return Err(invalid("unterminated string".to_string())),
+        }
+    }
+    if !chars.as_str().trim().is_empty() {
+        return Err(invalid(format!("unexpected '{}' after the template", chars.as_str().trim())));
+    }
+
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = literal.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                text.push(c);
+            }
+            ('{', _) => {
+                let mut digits = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => digits.push(c),
+                        None => return Err(invalid(format!("'{{{}' is missing its closing '}}'", digits))),
+                    }
+                }
+                let index = digits
+                    .parse::<usize>()
+                    .map_err(|_| invalid(format!("'{{{}}}' is not a child index", digits)))?;
+                if index >= arity {
+                    return Err(invalid(format!(
+                        "{{{index}}}: production has only {arity} symbols"
+                    )));
+                }
+                if !text.is_empty() {
+                    pieces.push(TranslationPiece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(TranslationPiece::Child(index));
+            }
+            ('}', _) => return Err(invalid("unmatched '}' (write '}}')".to_string())),
+            _ => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(TranslationPiece::Text(text));
+    }
+    Ok(Translation { pieces })
+}
+
+/// `::` 以降の木整形ディレクティブを読む。
+/// `drop 0 2` は子を捨て、`root 1` は子を親に持ち上げ、`collapse` は子が1つなら自身を子で置き換える。
+fn parse_directives(text: &str, arity: usize) -> Result<Vec<TreeDirective>, GrammarError> {
+    let mut directives = Vec::new();
+    let mut words = text.split_whitespace().peekable();
+
+    while let Some(word) = words.next() {
+        match word {
+            "collapse" => directives.push(TreeDirective::Collapse),
+            "drop" | "root" => {
+                let mut indices = Vec::new();
+                while let Some(index) = words.peek().and_then(|next| next.parse::<usize>().ok()) {
+                    words.next();
+                    if index >= arity {
+                        return Err(GrammarError::InvalidDirective(format!(
+                            "{word} {index}: production has only {arity} symbols"
+                        )));
+                    }
+                    indices.push(index);
+                }
+                match (word, indices.as_slice()) {
+                    ("drop", []) | ("root", []) => {
+                        return Err(GrammarError::InvalidDirective(format!(
+                            "{word} needs a child index"
+                        )));
+                    }
+                    // 持ち上げられる子は 1 つだけ
+                    ("root", [_]) if directives.iter().any(|d| matches!(d, TreeDirective::Root(_))) => {
+                        return Err(GrammarError::InvalidDirective(
+                            "root appears more than once".to_string(),
+                        ));
+                    }
+                    ("root", [index]) => directives.push(TreeDirective::Root(*index)),
+                    ("root", _) => {
+                        return Err(GrammarError::InvalidDirective(
+                            "root takes exactly one child index".to_string(),
+                        ));
+                    }
+                    _ => directives.extend(indices.into_iter().map(TreeDirective::Drop)),
+                }
+            }
+            other => {
+                return Err(GrammarError::InvalidDirective(format!(
+                    "unknown directive '{other}'"
+                )));
+            }
+        }
+    }
+
+    for directive in &directives {
+        if let TreeDirective::Root(index) = directive
+            && directives.contains(&TreeDirective::Drop(*index))
+        {
+            return Err(GrammarError::InvalidDirective(format!("root {index}: child {index} is also dropped")));
+        }
+    }
+
+    Ok(directives)
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Grammar {
     pub start: NonTerminal,
     pub productions: Vec<Production>,
+    /// `productions` と同じ添字で並ぶ木整形ディレクティブ。
+    pub directives: Vec<Vec<TreeDirective>>,
+    /// `productions` と同じ添字で並ぶ翻訳テンプレート。
+    pub translations: Vec<Option<Translation>>,
+    /// `productions` と同じ添字で並ぶ属性方程式。
+    pub attributes: Vec<Vec<Equation>>,
 }
 
 impl Grammar {
@@ -100,6 +298,29 @@ pub enum GrammarError {
     MissingLeftHandSide,
     NonTerminalTooLong,
     InvalidSymbol(char),
+    InvalidDirective(String),
+    InvalidTranslation(String),
+    InvalidAttribute(String),
+}
+
+/// CST から AST へ下ろすときの生成規則ごとの指示。添字は右辺の位置（0 始まり）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeDirective {
+    Drop(usize),
+    Root(usize),
+    Collapse,
+}
+
+/// 生成規則に付けた翻訳テンプレート。`translate::translate` が子の翻訳を埋めて連結する。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translation {
+    pub pieces: Vec<TranslationPiece>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranslationPiece {
+    Text(String),
+    Child(usize),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -108,6 +329,39 @@ pub struct Production {
     pub right: Vec<Symbol>,
 }
 
+impl fmt::Display for Production {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -> ", self.left.0)?;
+        for symbol in &self.right {
+            write!(f, "{}", symbol.as_char())?;
+        }
+        Ok(())
+    }
+}
+
+/// `Display` と対になる `"E -> E*B"` 形式の読み取り。左辺は1文字であれば何でもよい（拡大規則用）。
+impl FromStr for Production {
+    type Err = GrammarError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut chars = value.chars();
+        let left = chars.next().ok_or(GrammarError::MissingLeftHandSide)?;
+        let right = chars
+            .as_str()
+            .strip_prefix(" ->")
+            .ok_or(GrammarError::InvalidProductionFormat)?;
+        let right = right
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(Symbol::from_char)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Production {
+            left: NonTerminal(left),
+            right,
+        })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Symbol {
     Terminal(Terminal),
@@ -115,6 +369,13 @@ pub enum Symbol {
 }
 
 impl Symbol {
+    pub fn as_char(&self) -> char {
+        match self {
+            Self::Terminal(terminal) => terminal.0,
+            Self::NonTerminal(non_terminal) => non_terminal.0,
+        }
+    }
+
     fn from_char(value: char) -> Result<Self, GrammarError> {
         if value.is_whitespace() {
             return Err(GrammarError::InvalidSymbol(value));
@@ -159,4 +420,79 @@ mod tests {
         assert_eq!(grammar.start, NonTerminal('E'));
         assert_eq!(grammar.productions.len(), 3);
     }
+
+    #[test]
+    fn parse_grammar_text_reads_tree_directives() {
+        let grammar = parse_grammar_text("E -> E+B :: root 1\nE -> B :: collapse\nB -> (E) :: drop 0 2 collapse").unwrap();
+
+        assert_eq!(grammar.productions[0].right.len(), 3);
+        assert_eq!(
+            grammar.directives,
+            vec![
+                vec![TreeDirective::Root(1)],
+                vec![TreeDirective::Collapse],
+                vec![TreeDirective::Drop(0), TreeDirective::Drop(2), TreeDirective::Collapse],
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_grammar_text_reads_translation_templates() {
+        let grammar = parse_grammar_text(
+            "E -> E+B :: root 1 => \"(+ {0} {2})\"\nE -> B\nB -> 1 => \"i64::from({{1}}) \\\"{0}\\\"\"",
+        )
+        .unwrap();
+
+        assert_eq!(grammar.directives[0], vec![TreeDirective::Root(1)]);
+        assert_eq!(
+            grammar.translations[0].as_ref().unwrap().pieces,
+            vec![
+                TranslationPiece::Text("(+ ".to_string()),
+                TranslationPiece::Child(0),
+                TranslationPiece::Text(" ".to_string()),
+                TranslationPiece::Child(2),
+                TranslationPiece::Text(")".to_string()),
+            ]
+        );
+        assert_eq!(grammar.translations[1], None);
+        assert_eq!(
+            grammar.translations[2].as_ref().unwrap().pieces,
+            vec![
+                TranslationPiece::Text("i64::from({1}) \"".to_string()),
+                TranslationPiece::Child(0),
+                TranslationPiece::Text("\"".to_string()),
+            ]
+        );
+
+        let unclosed = "E -> a => \"x{0\"";
+        for bad in ["E -> a => \"{1}\"", "E -> a => \"{x}\"", "E -> a => \"}\"", "E -> a => \"open", unclosed] {
+            assert!(matches!(parse_grammar_text(bad), Err(GrammarError::InvalidTranslation(_))), "{bad}");
+        }
+    }
+
+    #[test]
+    fn parse_grammar_text_rejects_out_of_range_directive() {
+        assert!(matches!(
+            parse_grammar_text("E -> B :: drop 1"),
+            Err(GrammarError::InvalidDirective(_))
+        ));
+        assert!(matches!(
+            parse_grammar_text("E -> (B) :: drop 0 1 root 1"),
+            Err(GrammarError::InvalidDirective(_))
+        ));
+        assert_eq!(
+            parse_grammar_text("E -> E+B :: root 1 root 0"),
+            Err(GrammarError::InvalidDirective("root appears more than once".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_grammar_text_keeps_adjacent_colons_as_terminals() {
+        let grammar = parse_grammar_text("S -> a::b\nS -> a:b :: drop 1").unwrap();
+
+        assert_eq!(grammar.productions[0].to_string(), "S -> a::b");
+        assert_eq!(grammar.directives[0], Vec::new());
+        assert_eq!(grammar.productions[1].to_string(), "S -> a:b");
+        assert_eq!(grammar.directives[1], vec![TreeDirective::Drop(1)]);
+    }
 }
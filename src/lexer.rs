@@ -0,0 +1,165 @@
+use crate::roles::TerminalRole;
+use lr0_parser_rs::AstNode;
+use lr0_parser_rs::grammar::{GrammarError, Symbol, Terminal};
+use std::collections::HashMap;
+
+/// 入力を切り分けた終端記号の列と、それぞれの入力上の綴り。`symbols` の末尾は `$`。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lexed {
+    pub symbols: Vec<Symbol>,
+    pub lexemes: Vec<String>,
+}
+
+impl Lexed {
+    /// `lex` した入力から作った木の葉に、前から順に綴りを戻す。
+    pub fn attach(&self, ast: &AstNode) -> AstNode {
+        let mut ast = ast.clone();
+        let mut lexemes = self.lexemes.iter();
+        ast.for_each_mut(|node| {
+            if let AstNode::Terminal(token) = node
+                && let Some(lexeme) = lexemes.next()
+            {
+                token.lexeme = lexeme.clone();
+            }
+        });
+        ast
+    }
+}
+
+/// `lex` が識別子として読む綴りか。英小文字か `_` で始まり、英小文字・数字・`_` が続く。
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|first| first.is_ascii_lowercase() || first == '_')
+        && chars.all(continues_identifier)
+}
+
+fn continues_identifier(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'
+}
+
+/// 役割を見ながら入力を終端記号に切り分ける。
+/// 数字でない終端記号に Num が割り当てられていれば数字の並びを、Ident が割り当てられていれば
+/// 英小文字か `_` で始まる英小文字・数字・`_` の並びを、それぞれ 1 つのその終端記号として読む。
+/// 該当する終端記号が複数あれば文字コードの小さいほうを使う。ほかの文字は 1 文字 1 記号のまま。
+/// 別の役割を割り当てた終端記号（`x` の Mul や Token のままのキーワードなど）の文字からは
+/// 数や識別子を始めず、その終端記号として読む。始まった数や識別子の続きには入ってよい。
+pub fn lex(input: &str, roles: &HashMap<char, TerminalRole>) -> Result<Lexed, GrammarError> {
+    let terminal_for = |wanted: TerminalRole, skip_digits: bool| {
+        roles
+            .iter()
+            .filter(|(symbol, role)| **role == wanted && !(skip_digits && symbol.is_ascii_digit()))
+            .map(|(symbol, _)| *symbol)
+            .min()
+    };
+    let number = terminal_for(TerminalRole::Num, true);
+    let identifier = terminal_for(TerminalRole::Ident, false);
+    let free = |c: char, own: &TerminalRole| roles.get(&c).is_none_or(|role| role == own);
+
+    let mut symbols = Vec::new();
+    let mut lexemes = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c.is_ascii_uppercase() {
+            return Err(GrammarError::InvalidSymbol(c));
+        }
+        let (symbol, continues): (char, fn(char) -> bool) = match (number, identifier) {
+            (Some(number), _) if c.is_ascii_digit() && free(c, &TerminalRole::Num) => {
+                (number, |next| next.is_ascii_digit())
+            }
+            (_, Some(identifier)) if (c.is_ascii_lowercase() || c == '_') && free(c, &TerminalRole::Ident) => {
+                (identifier, continues_identifier)
+            }
+            _ => (c, |_| false),
+        };
+        let mut lexeme = c.to_string();
+        while let Some(&next) = chars.peek()
+            && continues(next)
+        {
+            lexeme.push(next);
+            chars.next();
+        }
+        symbols.push(Symbol::Terminal(Terminal(symbol)));
+        lexemes.push(lexeme);
+    }
+
+    symbols.push(Symbol::Terminal(Terminal('$')));
+    Ok(Lexed { symbols, lexemes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lr0_parser_rs::grammar::parse_grammar_text;
+    use lr0_parser_rs::lr::compile;
+    use lr0_parser_rs::runtime::run;
+
+    fn terminals(lexed: &Lexed) -> String {
+        lexed
+            .symbols
+            .iter()
+            .map(|symbol| match symbol {
+                Symbol::Terminal(terminal) => terminal.0,
+                Symbol::NonTerminal(non_terminal) => non_terminal.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn numbers_and_identifiers_become_single_terminals() {
+        let roles = HashMap::from([('n', TerminalRole::Num), ('i', TerminalRole::Ident), ('+', TerminalRole::Add)]);
+        let lexed = lex("x1 * 10 + _y", &roles).unwrap();
+
+        assert_eq!(terminals(&lexed), "i*n+i$");
+        assert_eq!(lexed.lexemes, ["x1", "*", "10", "+", "_y"]);
+
+        let machine = compile(&parse_grammar_text("E -> E*B\nE -> E+B\nE -> B\nB -> n\nB -> i").unwrap()).unwrap();
+        let ast = lexed.attach(&run(&machine, &lexed.symbols).unwrap().ast);
+        assert_eq!(ast.yield_string(), "x1*10+_y");
+    }
+
+    #[test]
+    fn terminals_with_their_own_role_are_not_swallowed() {
+        let roles = HashMap::from([('i', TerminalRole::Ident), ('x', TerminalRole::Mul), ('f', TerminalRole::Token)]);
+        let lexed = lex("2x3", &roles).unwrap();
+        assert_eq!(terminals(&lexed), "2x3$");
+
+        let lexed = lex("f*fx i", &roles).unwrap();
+        assert_eq!(terminals(&lexed), "f*fxi$");
+
+        // 識別子の続きには入る
+        let lexed = lex("ax_f", &roles).unwrap();
+        assert_eq!(terminals(&lexed), "i$");
+        assert_eq!(lexed.lexemes, ["ax_f"]);
+    }
+
+    #[test]
+    fn digit_terminals_keep_one_character_per_symbol() {
+        let roles = HashMap::from([('1', TerminalRole::Num), ('0', TerminalRole::Num)]);
+        let lexed = lex("10+1", &roles).unwrap();
+
+        assert_eq!(terminals(&lexed), "10+1$");
+        assert!(matches!(lex("1+X", &roles), Err(GrammarError::InvalidSymbol('X'))));
+        assert!(is_identifier("_x1") && !is_identifier("1x") && !is_identifier("x-y") && !is_identifier(""));
+    }
+
+    #[test]
+    fn reducer_digits_with_default_roles_are_separate_terminals() {
+        let grammar = parse_grammar_text(include_str!("../reducer")).unwrap();
+        let roles: HashMap<char, TerminalRole> = grammar
+            .terminals()
+            .into_iter()
+            .map(|terminal| (terminal.0, TerminalRole::default_for(terminal.0)))
+            .collect();
+        let lexed = lex("10", &roles).unwrap();
+
+        // 数字の終端記号 `1` と `0` はどちらも既定で Num だが、数字の並びにはまとめない
+        assert_eq!(terminals(&lexed), "10$");
+        assert_eq!(lexed.lexemes, ["1", "0"]);
+        let machine = compile(&grammar).unwrap();
+        assert!(run(&machine, &lexed.symbols).is_err());
+        assert!(run(&machine, &lex("1+0", &roles).unwrap().symbols).is_ok());
+    }
+}
@@ -1,19 +1,43 @@
 pub mod ast;
+pub mod attribute;
+pub mod codegen;
+pub mod corpus;
+pub mod diagram;
 pub mod grammar;
+pub mod lower;
 pub mod lr;
+pub mod packed;
 pub mod runtime;
+pub mod serialize;
+pub mod table;
+pub mod translate;
 
-pub use ast::AstNode;
+pub use ast::{AstNode, Fold, NodeInfo, Span, Token, Visitor};
 pub use lr::{LrItem, StateInfo};
 pub use runtime::{ParseStep, StepAction, build_trace};
 
 #[cfg(test)]
 mod tests {
-    use crate::ast::AstNode;
+    use crate::ast::{AstNode, Span};
     use crate::grammar::{parse_grammar_text, parse_input_text};
     use crate::lr::compile;
     use crate::runtime::run;
 
+    fn t(symbol: char, at: usize) -> AstNode {
+        AstNode::terminal(symbol).with_span(Span::new(at, at + 1))
+    }
+
+    fn nt(
+        symbol: char,
+        production: usize,
+        (start, end): (usize, usize),
+        children: Vec<AstNode>,
+    ) -> AstNode {
+        AstNode::non_terminal(symbol, children)
+            .with_span(Span::new(start, end))
+            .with_production(production)
+    }
+
     #[test]
     fn grammar_compile_runtime_pipeline_parses_expression() {
         let grammar = parse_grammar_text(include_str!("../reducer")).unwrap();
@@ -24,22 +48,23 @@ mod tests {
 
         assert_eq!(
             result.ast,
-            AstNode::NonTerminal(
+            nt(
                 'E',
+                1,
+                (0, 5),
                 vec![
-                    AstNode::NonTerminal(
+                    nt(
                         'E',
+                        2,
+                        (0, 3),
                         vec![
-                            AstNode::NonTerminal(
-                                'E',
-                                vec![AstNode::NonTerminal('B', vec![AstNode::Terminal('1')])],
-                            ),
-                            AstNode::Terminal('+'),
-                            AstNode::NonTerminal('B', vec![AstNode::Terminal('1')]),
+                            nt('E', 3, (0, 1), vec![nt('B', 5, (0, 1), vec![t('1', 0)])]),
+                            t('+', 1),
+                            nt('B', 5, (2, 3), vec![t('1', 2)]),
                         ],
                     ),
-                    AstNode::Terminal('*'),
-                    AstNode::NonTerminal('B', vec![AstNode::Terminal('1')]),
+                    t('*', 3),
+                    nt('B', 5, (4, 5), vec![t('1', 4)]),
                 ],
             )
         );
@@ -0,0 +1,185 @@
+use crate::ast::{AstNode, Attributes, Fold, NodeInfo, Token};
+use crate::grammar::{Grammar, TreeDirective};
+
+/// 生成規則 id ごとの木整形ディレクティブ。
+/// id は `CompiledParser` と同じ番号付け（0 が拡大規則、文法の n 番目の規則が n + 1）。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoweringRules {
+    directives: Vec<Vec<TreeDirective>>,
+}
+
+impl LoweringRules {
+    pub fn from_grammar(grammar: &Grammar) -> Self {
+        let mut directives = vec![Vec::new()];
+        directives.extend(grammar.directives.iter().cloned());
+        Self { directives }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.directives.iter().all(Vec::is_empty)
+    }
+
+    pub fn directives(&self, production: usize) -> &[TreeDirective] {
+        self.directives
+            .get(production)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+}
+
+/// 具象構文木を `rules` に従って抽象構文木へ下ろす。
+/// ディレクティブを持たない規則で作られたノードはそのまま残る。
+/// root や collapse で親の代わりに置かれたノードは親の属性を引き継ぐ（同じ名前なら親の値）。
+pub fn lower(node: &AstNode, rules: &LoweringRules) -> AstNode {
+    node.fold(&mut Lowering { rules })
+}
+
+// 子を下ろし終えてから親のディレクティブを当てる。`fold` は再帰しないので深い木でも使える。
+struct Lowering<'r> {
+    rules: &'r LoweringRules,
+}
+
+impl Fold for Lowering<'_> {
+    type Output = AstNode;
+
+    fn fold_terminal(&mut self, token: &Token) -> AstNode {
+        AstNode::Terminal(token.clone())
+    }
+
+    fn fold_non_terminal(&mut self, info: &NodeInfo, children: Vec<AstNode>) -> AstNode {
+        let directives = info
+            .production
+            .map(|production| self.rules.directives(production))
+            .unwrap_or(&[]);
+
+        let mut kept: Vec<(usize, AstNode)> = Vec::with_capacity(children.len());
+        for (index, child) in children.into_iter().enumerate() {
+            if !directives.contains(&TreeDirective::Drop(index)) {
+                kept.push((index, child));
+            }
+        }
+
+        let root = directives.iter().find_map(|directive| match directive {
+            TreeDirective::Root(index) => kept.iter().position(|(kept_index, _)| kept_index == index),
+            _ => None,
+        });
+
+        let mut lowered = match root {
+            Some(position) => {
+                let (_, head) = kept.remove(position);
+                let rest = kept.into_iter().map(|(_, child)| child).collect();
+                promote(head, rest, info)
+            }
+            None => AstNode::NonTerminal(
+                info.clone(),
+                kept.into_iter().map(|(_, child)| child).collect(),
+            ),
+        };
+
+        if directives.contains(&TreeDirective::Collapse)
+            && let AstNode::NonTerminal(_, children) = &mut lowered
+            && children.len() == 1
+        {
+            let mut child = children.pop().expect("exactly one child");
+            inherit(child.attributes_mut(), &info.attributes);
+            return child;
+        }
+        lowered
+    }
+}
+
+// root に選ばれた子を親ノードにして、残りの子をその下にぶら下げる。
+// `AstNode` は Drop を持つので中身を取り出せない。終端記号は属性を移して作り直し、非終端はその場で書き換える。
+fn promote(mut head: AstNode, rest: Vec<AstNode>, parent: &NodeInfo) -> AstNode {
+    match &mut head {
+        AstNode::Terminal(token) => {
+            let mut attributes = std::mem::take(&mut token.attributes);
+            inherit(&mut attributes, &parent.attributes);
+            AstNode::NonTerminal(
+                NodeInfo {
+                    symbol: token.symbol,
+                    production: parent.production,
+                    span: parent.span,
+                    attributes,
+                },
+                rest,
+            )
+        }
+        AstNode::NonTerminal(info, children) => {
+            inherit(&mut info.attributes, &parent.attributes);
+            children.extend(rest);
+            head
+        }
+    }
+}
+
+fn inherit(attributes: &mut Attributes, parent: &Attributes) {
+    attributes.extend(parent.iter().map(|(name, value)| (name.clone(), value.clone())));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attribute::{AttributeRules, decorate};
+    use crate::grammar::{parse_grammar_text, parse_input_text};
+    use crate::lr::compile;
+    use crate::runtime::run;
+
+    fn lower_input(grammar_text: &str, input: &str) -> AstNode {
+        let grammar = parse_grammar_text(grammar_text).unwrap();
+        let machine = compile(&grammar).unwrap();
+        let cst = run(&machine, &parse_input_text(input).unwrap()).unwrap().ast;
+        lower(&cst, &LoweringRules::from_grammar(&grammar))
+    }
+
+    #[test]
+    fn operator_becomes_parent_and_unit_chains_collapse() {
+        let ast = lower_input(
+            "E -> E*B :: root 1\nE -> E+B :: root 1\nE -> B :: collapse\nB -> 0 :: collapse\nB -> 1 :: collapse",
+            "1+0",
+        );
+
+        assert_eq!(ast.to_string(), "+\n    1\n    0\n");
+        assert_eq!(ast.production(), Some(2));
+    }
+
+    #[test]
+    fn nodes_standing_in_for_their_parent_keep_its_attributes() {
+        let grammar = parse_grammar_text(
+            "E -> E+B :: root 1\n@ $$.val = $0.val + $2.val\nE -> B :: collapse\n@ $$.val = $0.val * 10\n\
+             B -> 1 :: collapse\n@ $$.val = $0.val; $$.digit = $0.val",
+        )
+        .unwrap();
+        let machine = compile(&grammar).unwrap();
+        let cst = run(&machine, &parse_input_text("1+1").unwrap()).unwrap().ast;
+        let decorated = decorate(&cst, &AttributeRules::from_grammar(&grammar)).unwrap();
+        let ast = lower(&decorated, &LoweringRules::from_grammar(&grammar));
+
+        // `+` は E -> E+B の値を、左の `1` は E -> B の値を持ち、B だけの属性も残る。
+        let AstNode::NonTerminal(root, children) = &ast else { panic!("{ast}") };
+        assert_eq!(root.attributes["val"], "11");
+        assert_eq!(children[0].attributes()["val"], "10");
+        assert_eq!(children[0].attributes()["digit"], "1");
+        assert_eq!(children[1].attributes()["val"], "1");
+    }
+
+    #[test]
+    fn dropped_punctuation_disappears_from_the_tree() {
+        let ast = lower_input("S -> SP\nS -> P :: collapse\nP -> <>\nP -> <S> :: drop 0 2", "<<>>");
+
+        assert_eq!(ast.to_string(), "P\n    P\n        <\n        >\n");
+    }
+
+    #[test]
+    fn grammar_without_directives_keeps_the_derivation_tree() {
+        let grammar_text = include_str!("../reducer");
+        let grammar = parse_grammar_text(grammar_text).unwrap();
+        let machine = compile(&grammar).unwrap();
+        let cst = run(&machine, &parse_input_text("1+0").unwrap()).unwrap().ast;
+
+        let rules = LoweringRules::from_grammar(&grammar);
+
+        assert!(rules.is_empty());
+        assert_eq!(lower(&cst, &rules), cst);
+    }
+}
@@ -1,8 +1,24 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
 
 use crate::grammar::{Grammar, NonTerminal, Production, Symbol, Terminal};
 
 pub fn compile(grammar: &Grammar) -> Result<CompiledParser, ParserError> {
+    let (machine, conflicts) = build(grammar)?;
+    if !conflicts.is_empty() {
+        return Err(ParserError::ConflictReducer);
+    }
+    Ok(machine)
+}
+
+/// LR(0) 表を作るときに衝突したすべての (状態, 終端記号) を返す。衝突がなければ空。
+pub fn conflicts(grammar: &Grammar) -> Result<Vec<Conflict>, ParserError> {
+    build(grammar).map(|(_, conflicts)| conflicts)
+}
+
+// 衝突があっても表を最後まで作り、先に入った action を残したまま衝突を集める。
+fn build(grammar: &Grammar) -> Result<(CompiledParser, Vec<Conflict>), ParserError> {
     let mut productions = grammar.productions.clone();
     let start_symbol = grammar.start;
     let augmented_start = NonTerminal(((start_symbol.0 as u8) + 1) as char);
@@ -15,227 +31,227 @@ pub fn compile(grammar: &Grammar) -> Result<CompiledParser, ParserError> {
         },
     );
 
-    let non_terminals = grammar.non_terminals();
     let terminals = grammar.terminals();
+    let index = ProductionIndex::new(&productions);
 
-    let mut item_sets: Vec<BTreeSet<Item>> = Vec::new();
-    let mut edges: BTreeMap<(InternalState, Symbol), InternalState> = BTreeMap::new();
-
-    let initial_item = Item {
-        production: productions[0].clone(),
-        dot_pos: 0,
-    };
-
-    let initial_closure = closure(
-        &vec![initial_item].into_iter().collect(),
-        &productions,
-        &non_terminals,
-    );
-    item_sets.push(initial_closure);
+    // 状態は核（初期項目か、ドットが先頭にない項目）で決まるので、核だけをハッシュして同じ状態を探す。
+    let initial_kernel = vec![Item { production: 0, dot_pos: 0 }];
+    let mut kernels: HashMap<Vec<Item>, InternalState> = HashMap::new();
+    kernels.insert(initial_kernel.clone(), 0);
+    let mut item_sets: Vec<Vec<Item>> = vec![index.closure(initial_kernel)];
+    let mut transitions: Vec<Vec<(Symbol, InternalState)>> = Vec::new();
 
     let mut state_id = 0;
     while state_id < item_sets.len() {
-        let current_set = item_sets[state_id].clone();
-        let mut all_symbols = Vec::new();
-        all_symbols.extend(terminals.iter().copied().map(Symbol::Terminal));
-        all_symbols.extend(non_terminals.iter().copied().map(Symbol::NonTerminal));
-
-        for symbol in all_symbols {
-            let goto_set = goto(&current_set, symbol.clone(), &productions, &non_terminals);
-            if goto_set.is_empty() {
-                continue;
+        // ドットの直後に現れる記号だけを、終端記号 → 非終端記号の順に調べる。
+        let mut successors: BTreeMap<Symbol, Vec<Item>> = BTreeMap::new();
+        for item in &item_sets[state_id] {
+            if let Some(symbol) = productions[item.production].right.get(item.dot_pos) {
+                successors.entry(symbol.clone()).or_default().push(Item {
+                    production: item.production,
+                    dot_pos: item.dot_pos + 1,
+                });
             }
+        }
 
-            let next_state =
-                if let Some(existing) = item_sets.iter().position(|set| *set == goto_set) {
-                    existing
-                } else {
+        let mut edges = Vec::with_capacity(successors.len());
+        for (symbol, mut kernel) in successors {
+            kernel.sort_unstable();
+            let next_state = match kernels.get(&kernel) {
+                Some(&existing) => existing,
+                None => {
                     let new_id = item_sets.len();
-                    item_sets.push(goto_set);
+                    item_sets.push(index.closure(kernel.clone()));
+                    kernels.insert(kernel, new_id);
                     new_id
-                };
-
-            edges.insert((state_id, symbol), next_state);
+                }
+            };
+            edges.push((symbol, next_state));
         }
+        transitions.push(edges);
 
         state_id += 1;
     }
 
+    // 表と StateInfo は生成規則の値の順に項目を並べる（衝突の existing/incoming もこの順で決まる）。
+    for items in &mut item_sets {
+        items.sort_unstable_by(|a, b| {
+            index.rank[a.production]
+                .cmp(&index.rank[b.production])
+                .then(a.dot_pos.cmp(&b.dot_pos))
+        });
+    }
+
     let mut action_table = BTreeMap::new();
     let mut goto_table = BTreeMap::new();
+    let mut conflicts = Vec::new();
 
     for (state_id, item_set) in item_sets.iter().enumerate() {
         for item in item_set {
-            if item.dot_pos < item.production.right.len() {
-                let next_symbol = item.production.right[item.dot_pos].clone();
-                let Some(&next_state) = edges.get(&(state_id, next_symbol.clone())) else {
+            let production = &productions[item.production];
+            if let Some(next_symbol) = production.right.get(item.dot_pos) {
+                let edges = &transitions[state_id];
+                let Ok(position) = edges.binary_search_by(|(symbol, _)| symbol.cmp(next_symbol))
+                else {
                     continue;
                 };
+                let next_state = edges[position].1;
 
                 match next_symbol {
                     Symbol::Terminal(terminal) => {
                         insert_action(
                             &mut action_table,
+                            &mut conflicts,
                             state_id,
-                            terminal,
+                            *terminal,
                             Action::Shift(next_state),
-                        )?;
+                        );
                     }
                     Symbol::NonTerminal(non_terminal) => {
-                        goto_table.insert((state_id, non_terminal), next_state);
+                        goto_table.insert((state_id, *non_terminal), next_state);
                     }
                 }
+            } else if item.production == 0 {
+                insert_action(
+                    &mut action_table,
+                    &mut conflicts,
+                    state_id,
+                    Terminal('$'),
+                    Action::Accept,
+                );
             } else {
-                let production_id = productions
-                    .iter()
-                    .position(|production| *production == item.production)
-                    .ok_or(ParserError::MissingProduction)?;
-
-                if production_id == 0 {
-                    insert_action(&mut action_table, state_id, Terminal('$'), Action::Accept)?;
-                } else {
-                    for terminal in &terminals {
-                        insert_action(
-                            &mut action_table,
-                            state_id,
-                            *terminal,
-                            Action::Reduce(production_id),
-                        )?;
-                    }
-
+                for terminal in terminals.iter().chain([&Terminal('$')]) {
                     insert_action(
                         &mut action_table,
+                        &mut conflicts,
                         state_id,
-                        Terminal('$'),
-                        Action::Reduce(production_id),
-                    )?;
+                        *terminal,
+                        Action::Reduce(item.production),
+                    );
                 }
             }
         }
     }
 
-    let state_infos: Vec<StateInfo> = item_sets.iter().enumerate().map(|(id, item_set)| {
-        let items = item_set.iter().map(|item| LrItem {
-            production: item.production.clone(),
-            dot_pos: item.dot_pos,
-        }).collect();
-        let mut transitions: Vec<(Symbol, usize)> = edges.iter()
-            .filter(|((state, _), _)| *state == id)
-            .map(|((_, sym), &next)| (sym.clone(), next))
-            .collect();
-        transitions.sort_by(|a, b| a.0.cmp(&b.0));
-        StateInfo { id, items, transitions }
-    }).collect();
-
-    Ok(CompiledParser {
+    let state_infos: Vec<StateInfo> = item_sets
+        .iter()
+        .zip(transitions)
+        .enumerate()
+        .map(|(id, (item_set, transitions))| StateInfo {
+            id,
+            items: item_set
+                .iter()
+                .map(|item| LrItem {
+                    production: productions[item.production].clone(),
+                    dot_pos: item.dot_pos,
+                })
+                .collect(),
+            transitions,
+        })
+        .collect();
+
+    let machine = CompiledParser {
         productions,
         action_table,
         goto_table,
         start_state: 0,
         state_count: item_sets.len(),
         state_infos,
-    })
+    };
+    Ok((machine, conflicts))
 }
 
 fn insert_action(
     table: &mut BTreeMap<(InternalState, Terminal), Action>,
+    conflicts: &mut Vec<Conflict>,
     state: InternalState,
     terminal: Terminal,
     action: Action,
-) -> Result<(), ParserError> {
+) {
     match table.entry((state, terminal)) {
         std::collections::btree_map::Entry::Vacant(entry) => {
             entry.insert(action);
         }
         std::collections::btree_map::Entry::Occupied(entry) => {
-            if *entry.get() != action {
-                return Err(ParserError::ConflictReducer);
+            let conflict = Conflict {
+                state,
+                terminal,
+                existing: *entry.get(),
+                incoming: action,
+            };
+            if *entry.get() != action && !conflicts.contains(&conflict) {
+                conflicts.push(conflict);
             }
         }
     }
-    Ok(())
 }
 
-fn closure(
-    items: &BTreeSet<Item>,
-    productions: &[Production],
-    non_terminals: &BTreeSet<NonTerminal>,
-) -> BTreeSet<Item> {
-    let mut closure_set = items.clone();
-    let mut new_items_to_add = closure_set.clone();
-
-    loop {
-        let mut added_this_round = false;
-        let current_items = new_items_to_add.clone();
-        new_items_to_add.clear();
-
-        for item in &current_items {
-            if item.dot_pos >= item.production.right.len() {
-                continue;
-            }
-
-            let next_symbol = item.production.right[item.dot_pos].clone();
-            let Symbol::NonTerminal(non_terminal) = next_symbol else {
-                continue;
-            };
-
-            if !non_terminals.contains(&non_terminal) {
-                continue;
-            }
-
-            for production in productions
-                .iter()
-                .filter(|production| production.left == non_terminal)
-            {
-                let next_item = Item {
-                    production: production.clone(),
-                    dot_pos: 0,
-                };
+/// 左辺ごとの生成規則 id と、生成規則を値で並べたときの順位。
+/// 同じ生成規則が2度書かれていれば最初の id だけを項目にする（後のものは還元に使われない）。
+struct ProductionIndex<'a> {
+    productions: &'a [Production],
+    by_left: HashMap<NonTerminal, Vec<ProductionId>>,
+    rank: Vec<usize>,
+}
 
-                if closure_set.insert(next_item.clone()) {
-                    new_items_to_add.insert(next_item);
-                    added_this_round = true;
-                }
+impl<'a> ProductionIndex<'a> {
+    fn new(productions: &'a [Production]) -> Self {
+        let mut by_left: HashMap<NonTerminal, Vec<ProductionId>> = HashMap::new();
+        let mut seen: HashSet<&Production> = HashSet::new();
+        for (id, production) in productions.iter().enumerate() {
+            if seen.insert(production) {
+                by_left.entry(production.left).or_default().push(id);
             }
         }
 
-        if !added_this_round {
-            break;
+        let mut order: Vec<ProductionId> = (0..productions.len()).collect();
+        order.sort_by(|a, b| productions[*a].cmp(&productions[*b]));
+        let mut rank = vec![0; productions.len()];
+        for (position, id) in order.into_iter().enumerate() {
+            rank[id] = position;
         }
+
+        Self { productions, by_left, rank }
     }
 
-    closure_set
-}
+    /// 核に、ドットの直後の非終端記号を左辺に持つ規則の初期項目を推移的に足す。
+    fn closure(&self, kernel: Vec<Item>) -> Vec<Item> {
+        let mut items = kernel;
+        let mut expanded: HashSet<NonTerminal> = HashSet::new();
+        let mut cursor = 0;
+
+        while cursor < items.len() {
+            let item = items[cursor];
+            cursor += 1;
 
-fn goto(
-    items: &BTreeSet<Item>,
-    symbol: Symbol,
-    productions: &[Production],
-    non_terminals: &BTreeSet<NonTerminal>,
-) -> BTreeSet<Item> {
-    let mut next_items = BTreeSet::new();
-
-    for item in items {
-        if item.dot_pos < item.production.right.len()
-            && item.production.right[item.dot_pos] == symbol
-        {
-            let mut next_item = item.clone();
-            next_item.dot_pos += 1;
-            next_items.insert(next_item);
+            let Some(Symbol::NonTerminal(non_terminal)) =
+                self.productions[item.production].right.get(item.dot_pos)
+            else {
+                continue;
+            };
+            if !expanded.insert(*non_terminal) {
+                continue;
+            }
+            if let Some(ids) = self.by_left.get(non_terminal) {
+                items.extend(ids.iter().map(|&production| Item { production, dot_pos: 0 }));
+            }
         }
-    }
 
-    closure(&next_items, productions, non_terminals)
+        // 初期項目が核にも含まれる場合（開始状態）に備えて重複を落とす。
+        items.sort_unstable();
+        items.dedup();
+        items
+    }
 }
 
-#[derive(PartialEq, Eq, Hash, Clone, Debug, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, PartialOrd, Ord)]
 struct Item {
-    production: Production,
+    production: ProductionId,
     dot_pos: usize,
 }
 
 pub type InternalState = usize;
-type ProductionId = usize;
+pub type ProductionId = usize;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct LrItem {
@@ -243,13 +259,42 @@ pub struct LrItem {
     pub dot_pos: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StateInfo {
     pub id: usize,
     pub items: Vec<LrItem>,
     pub transitions: Vec<(Symbol, usize)>,
 }
 
+/// 駆動ループが表に問い合わせる操作。`CompiledParser` のほか `packed` の圧縮表も実装する。
+pub trait LrTable {
+    fn start_state(&self) -> InternalState;
+
+    fn action(&self, state: InternalState, terminal: Terminal) -> Option<Action>;
+
+    fn goto(&self, state: InternalState, non_terminal: NonTerminal) -> Option<InternalState>;
+
+    fn production(&self, id: ProductionId) -> Option<&Production>;
+}
+
+impl LrTable for CompiledParser {
+    fn start_state(&self) -> InternalState {
+        CompiledParser::start_state(self)
+    }
+
+    fn action(&self, state: InternalState, terminal: Terminal) -> Option<Action> {
+        CompiledParser::action(self, state, terminal)
+    }
+
+    fn goto(&self, state: InternalState, non_terminal: NonTerminal) -> Option<InternalState> {
+        CompiledParser::goto(self, state, non_terminal)
+    }
+
+    fn production(&self, id: ProductionId) -> Option<&Production> {
+        CompiledParser::production(self, id)
+    }
+}
+
 pub struct CompiledParser {
     productions: Vec<Production>,
     action_table: BTreeMap<(InternalState, Terminal), Action>,
@@ -280,6 +325,11 @@ impl CompiledParser {
         self.productions.get(id)
     }
 
+    /// 拡大文法の生成規則。添字が `Action::Reduce` の生成規則 id に対応する。
+    pub fn productions(&self) -> &[Production] {
+        &self.productions
+    }
+
     pub fn state_count(&self) -> usize {
         self.state_count
     }
@@ -287,6 +337,33 @@ impl CompiledParser {
     pub fn state_infos(&self) -> &[StateInfo] {
         &self.state_infos
     }
+
+    /// action/goto 表を状態・記号順に並べた平坦な表現。
+    pub fn tables(&self) -> ParseTables {
+        ParseTables {
+            state_count: self.state_count,
+            productions: self.productions.clone(),
+            actions: self
+                .action_table
+                .iter()
+                .map(|(&(state, terminal), &action)| (state, terminal, action))
+                .collect(),
+            gotos: self
+                .goto_table
+                .iter()
+                .map(|(&(state, non_terminal), &next)| (state, non_terminal, next))
+                .collect(),
+        }
+    }
+}
+
+/// `CompiledParser` から切り出した action/goto 表。スナップショットや外部ツールへの受け渡しに使う。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTables {
+    pub state_count: usize,
+    pub productions: Vec<Production>,
+    pub actions: Vec<(InternalState, Terminal, Action)>,
+    pub gotos: Vec<(InternalState, NonTerminal, InternalState)>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -296,10 +373,71 @@ pub enum Action {
     Accept,
 }
 
+// 表示は GUI の parse table と同じ `s<n>` / `r<n>` / `acc`。
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Shift(state) => write!(f, "s{state}"),
+            Self::Reduce(production) => write!(f, "r{production}"),
+            Self::Accept => write!(f, "acc"),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid action '{value}'");
+        if value == "acc" {
+            return Ok(Self::Accept);
+        }
+        if let Some(state) = value.strip_prefix('s') {
+            return state.parse().map(Self::Shift).map_err(|_| invalid());
+        }
+        if let Some(production) = value.strip_prefix('r') {
+            return production.parse().map(Self::Reduce).map_err(|_| invalid());
+        }
+        Err(invalid())
+    }
+}
+
+/// action 表の同じ枠に入ろうとした2つの action。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflict {
+    pub state: InternalState,
+    pub terminal: Terminal,
+    pub existing: Action,
+    pub incoming: Action,
+}
+
+impl Conflict {
+    pub fn kind(&self) -> &'static str {
+        match (self.existing, self.incoming) {
+            (Action::Reduce(_), Action::Reduce(_)) => "reduce/reduce",
+            (Action::Shift(_), _) | (_, Action::Shift(_)) => "shift/reduce",
+            _ => "accept/reduce",
+        }
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} conflict in state {} on '{}': {} vs {}",
+            self.kind(),
+            self.state,
+            self.terminal.0,
+            self.existing,
+            self.incoming
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ParserError {
     ConflictReducer,
-    MissingProduction,
 }
 
 #[cfg(test)]
@@ -314,4 +452,46 @@ mod tests {
 
         assert_eq!(machine.start_state(), 0);
     }
+
+    #[test]
+    fn repeated_productions_share_one_item() {
+        let grammar = parse_grammar_text("S -> a\nS -> b\nS -> a").unwrap();
+        let machine = compile(&grammar).unwrap();
+
+        let after_a = match machine.action(0, Terminal('a')) {
+            Some(Action::Shift(state)) => state,
+            other => panic!("expected a shift, got {other:?}"),
+        };
+        assert_eq!(machine.action(after_a, Terminal('$')), Some(Action::Reduce(1)));
+    }
+
+    #[test]
+    fn conflicts_lists_every_clashing_cell() {
+        let grammar = parse_grammar_text("E -> <>\nE -> <E>\nE -> EE").unwrap();
+
+        let conflicts = conflicts(&grammar).unwrap();
+
+        assert!(!conflicts.is_empty());
+        assert!(conflicts.iter().all(|conflict| conflict.kind() == "shift/reduce"));
+        assert!(conflicts.iter().any(|conflict| conflict.terminal == Terminal('<')));
+    }
+
+    #[test]
+    fn states_with_the_same_kernel_are_shared() {
+        // B の右辺は a..e の3文字の全組み合わせ（125 規則）。状態は接頭辞の木と同じ形になる。
+        let mut text = String::from("S -> SB\nS -> B\n");
+        for a in "abcde".chars() {
+            for b in "abcde".chars() {
+                for c in "abcde".chars() {
+                    text.push_str(&format!("B -> {a}{b}{c}\n"));
+                }
+            }
+        }
+        let machine = compile(&parse_grammar_text(&text).unwrap()).unwrap();
+
+        // 開始, S, B, S の後の B, 接頭辞 5 + 25 + 125（S の後からも同じ状態に合流する）
+        assert_eq!(machine.state_count(), 4 + 5 + 25 + 125);
+        let after_s = machine.goto(0, NonTerminal('S')).unwrap();
+        assert_eq!(machine.action(0, Terminal('a')), machine.action(after_s, Terminal('a')));
+    }
 }
@@ -1,7 +1,15 @@
 mod app;
+mod bytecode;
+mod emit;
+mod evaluator;
 mod generator_engine;
+mod lexer;
 mod pages;
+mod role_check;
+mod roles;
+mod runner;
 mod validation;
+mod workspace;
 
 use app::ParserApp;
 
@@ -15,6 +23,6 @@ fn main() -> eframe::Result {
     eframe::run_native(
         "LR(0) Parser GUI",
         options,
-        Box::new(|_cc| Ok(Box::new(ParserApp::default()))),
+        Box::new(|cc| Ok(Box::new(ParserApp::new(cc)))),
     )
 }
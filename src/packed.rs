@@ -0,0 +1,524 @@
+//! `CompiledParser` の `BTreeMap` 表を、生成コードや速い駆動ループ向けの詰めた配列に置き換える。
+//!
+//! どの表現も `LrTable` を実装しているので、`runtime::run` / `runtime::step` をそのまま走らせられる。
+
+use std::fmt;
+use std::mem::size_of;
+
+use crate::grammar::{NonTerminal, Production, Symbol, Terminal};
+use crate::lr::{Action, CompiledParser, InternalState, LrTable, ProductionId};
+
+/// 記号から列番号への対応。終端記号は文字コード順に並べて末尾に `$` を置き、
+/// 非終端記号は拡大規則の左辺（goto 表に現れない）を除いて文字コード順に並べる。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolIndex {
+    pub terminals: Vec<char>,
+    pub non_terminals: Vec<char>,
+}
+
+impl SymbolIndex {
+    pub fn from_parser(machine: &CompiledParser) -> Self {
+        let mut terminals: Vec<char> = machine
+            .productions()
+            .iter()
+            .flat_map(|production| production.right.iter())
+            .filter_map(|symbol| match symbol {
+                Symbol::Terminal(terminal) if terminal.0 != '$' => Some(terminal.0),
+                _ => None,
+            })
+            .collect();
+        terminals.sort_unstable();
+        terminals.dedup();
+        terminals.push('$');
+
+        let mut non_terminals: Vec<char> = machine
+            .productions()
+            .iter()
+            .skip(1)
+            .map(|production| production.left.0)
+            .collect();
+        non_terminals.sort_unstable();
+        non_terminals.dedup();
+
+        Self { terminals, non_terminals }
+    }
+
+    pub fn terminal(&self, terminal: Terminal) -> Option<usize> {
+        let (end, sorted) = self.terminals.split_last()?;
+        if terminal.0 == *end {
+            return Some(sorted.len());
+        }
+        sorted.binary_search(&terminal.0).ok()
+    }
+
+    pub fn non_terminal(&self, non_terminal: NonTerminal) -> Option<usize> {
+        self.non_terminals.binary_search(&non_terminal.0).ok()
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        (self.terminals.len() + self.non_terminals.len()) * size_of::<char>()
+    }
+}
+
+// action は 1 セル 32 bit に詰める。下位 2 bit が種類（0 がエラー）、残りが状態番号か生成規則 id。
+const ERROR: u32 = 0;
+const SHIFT: u32 = 1;
+const REDUCE: u32 = 2;
+const ACCEPT: u32 = 3;
+
+fn encode_action(action: Option<Action>) -> u32 {
+    match action {
+        None => ERROR,
+        Some(Action::Shift(state)) => ((state as u32) << 2) | SHIFT,
+        Some(Action::Reduce(production)) => ((production as u32) << 2) | REDUCE,
+        Some(Action::Accept) => ACCEPT,
+    }
+}
+
+fn decode_action(cell: u32) -> Option<Action> {
+    let value = (cell >> 2) as usize;
+    match cell & 0b11 {
+        SHIFT => Some(Action::Shift(value)),
+        REDUCE => Some(Action::Reduce(value)),
+        ACCEPT => Some(Action::Accept),
+        _ => None,
+    }
+}
+
+// goto は 0 を「遷移なし」にして状態番号を 1 ずらす。
+fn encode_goto(goto: Option<InternalState>) -> u32 {
+    goto.map_or(0, |state| state as u32 + 1)
+}
+
+fn decode_goto(cell: u32) -> Option<InternalState> {
+    cell.checked_sub(1).map(|state| state as usize)
+}
+
+fn dense_rows<T>(
+    machine: &CompiledParser,
+    columns: &[char],
+    cell: impl Fn(InternalState, char) -> T,
+) -> Vec<Vec<T>> {
+    (0..machine.state_count())
+        .map(|state| columns.iter().map(|&symbol| cell(state, symbol)).collect())
+        .collect()
+}
+
+/// 状態 × 記号 id の密な配列。引くのは添字計算だけで、大きさは状態数 × 記号数に比例する。
+/// `codegen` の生成器も、この並びの表をそのまま各言語のリテラルに書き下す。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DenseTable {
+    symbols: SymbolIndex,
+    productions: Vec<Production>,
+    start_state: InternalState,
+    state_count: usize,
+    actions: Vec<u32>,
+    gotos: Vec<u32>,
+}
+
+impl DenseTable {
+    pub fn from_parser(machine: &CompiledParser) -> Self {
+        let symbols = SymbolIndex::from_parser(machine);
+        let actions = dense_rows(machine, &symbols.terminals, |state, symbol| {
+            encode_action(machine.action(state, Terminal(symbol)))
+        });
+        let gotos = dense_rows(machine, &symbols.non_terminals, |state, symbol| {
+            encode_goto(machine.goto(state, NonTerminal(symbol)))
+        });
+
+        Self {
+            productions: machine.productions().to_vec(),
+            start_state: machine.start_state(),
+            state_count: machine.state_count(),
+            actions: actions.concat(),
+            gotos: gotos.concat(),
+            symbols,
+        }
+    }
+
+    pub fn symbols(&self) -> &SymbolIndex {
+        &self.symbols
+    }
+
+    /// 拡大文法の生成規則。添字が `Action::Reduce` の生成規則 id に対応する。
+    pub fn productions(&self) -> &[Production] {
+        &self.productions
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.state_count
+    }
+
+    /// 状態 `state` の action を `symbols().terminals` の列順に並べたもの。
+    pub fn action_row(&self, state: InternalState) -> Vec<Option<Action>> {
+        let width = self.symbols.terminals.len();
+        self.actions[state * width..(state + 1) * width].iter().map(|&cell| decode_action(cell)).collect()
+    }
+
+    /// 状態 `state` の goto を `symbols().non_terminals` の列順に並べたもの。
+    pub fn goto_row(&self, state: InternalState) -> Vec<Option<InternalState>> {
+        let width = self.symbols.non_terminals.len();
+        self.gotos[state * width..(state + 1) * width].iter().map(|&cell| decode_goto(cell)).collect()
+    }
+}
+
+impl LrTable for DenseTable {
+    fn start_state(&self) -> InternalState {
+        self.start_state
+    }
+
+    fn action(&self, state: InternalState, terminal: Terminal) -> Option<Action> {
+        let column = self.symbols.terminal(terminal)?;
+        let cell = self.actions.get(state * self.symbols.terminals.len() + column)?;
+        decode_action(*cell)
+    }
+
+    fn goto(&self, state: InternalState, non_terminal: NonTerminal) -> Option<InternalState> {
+        let column = self.symbols.non_terminal(non_terminal)?;
+        let cell = self.gotos.get(state * self.symbols.non_terminals.len() + column)?;
+        decode_goto(*cell)
+    }
+
+    fn production(&self, id: ProductionId) -> Option<&Production> {
+        self.productions.get(id)
+    }
+}
+
+/// 行ずらし（comb）で詰めた疎な表。各行の空でないセルが、共有の `next` 配列の
+/// `base[row] + column` に重ならないように置かれ、`check` に持ち主の行を記録する。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CombVector {
+    base: Vec<u32>,
+    next: Vec<u32>,
+    check: Vec<u32>,
+}
+
+impl CombVector {
+    /// `rows` の 0 のセルを空きとして詰める。各行は先頭から空きを探して最初に収まる位置に置く。
+    pub fn pack(rows: &[Vec<u32>]) -> Self {
+        let mut base = vec![0; rows.len()];
+        let mut next: Vec<u32> = Vec::new();
+        let mut check: Vec<u32> = Vec::new();
+
+        // 詰まっている行から置くと隙間が埋まりやすい。
+        let mut order: Vec<usize> = (0..rows.len()).collect();
+        order.sort_by_key(|&row| std::cmp::Reverse(rows[row].iter().filter(|&&cell| cell != 0).count()));
+
+        for row in order {
+            let cells: Vec<(usize, u32)> = rows[row]
+                .iter()
+                .enumerate()
+                .filter(|(_, cell)| **cell != 0)
+                .map(|(column, cell)| (column, *cell))
+                .collect();
+            let fits = |offset: usize| {
+                cells
+                    .iter()
+                    .all(|(column, _)| check.get(offset + column).is_none_or(|&owner| owner == u32::MAX))
+            };
+            let offset = (0..).find(|&offset| fits(offset)).unwrap_or(0);
+
+            base[row] = offset as u32;
+            for (column, cell) in cells {
+                let index = offset + column;
+                if index >= next.len() {
+                    next.resize(index + 1, 0);
+                    check.resize(index + 1, u32::MAX);
+                }
+                next[index] = cell;
+                check[index] = row as u32;
+            }
+        }
+
+        Self { base, next, check }
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> u32 {
+        let Some(&base) = self.base.get(row) else {
+            return 0;
+        };
+        let index = base as usize + column;
+        match self.check.get(index) {
+            Some(&owner) if owner == row as u32 => self.next[index],
+            _ => 0,
+        }
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        (self.base.len() + self.next.len() + self.check.len()) * size_of::<u32>()
+    }
+}
+
+/// action 表と goto 表をそれぞれ comb で詰めた表現。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CombTable {
+    symbols: SymbolIndex,
+    productions: Vec<Production>,
+    start_state: InternalState,
+    actions: CombVector,
+    gotos: CombVector,
+}
+
+impl CombTable {
+    pub fn from_parser(machine: &CompiledParser) -> Self {
+        let symbols = SymbolIndex::from_parser(machine);
+        let actions = dense_rows(machine, &symbols.terminals, |state, symbol| {
+            encode_action(machine.action(state, Terminal(symbol)))
+        });
+        let gotos = dense_rows(machine, &symbols.non_terminals, |state, symbol| {
+            encode_goto(machine.goto(state, NonTerminal(symbol)))
+        });
+
+        Self {
+            productions: machine.productions().to_vec(),
+            start_state: machine.start_state(),
+            actions: CombVector::pack(&actions),
+            gotos: CombVector::pack(&gotos),
+            symbols,
+        }
+    }
+}
+
+impl LrTable for CombTable {
+    fn start_state(&self) -> InternalState {
+        self.start_state
+    }
+
+    fn action(&self, state: InternalState, terminal: Terminal) -> Option<Action> {
+        decode_action(self.actions.get(state, self.symbols.terminal(terminal)?))
+    }
+
+    fn goto(&self, state: InternalState, non_terminal: NonTerminal) -> Option<InternalState> {
+        decode_goto(self.gotos.get(state, self.symbols.non_terminal(non_terminal)?))
+    }
+
+    fn production(&self, id: ProductionId) -> Option<&Production> {
+        self.productions.get(id)
+    }
+}
+
+/// 状態ごとの既定の還元と、残りの action だけを comb で詰めた表現。
+/// LR(0) の還元状態は先読みによらず同じ規則で還元するので、その行は表から丸ごと消える。
+/// 既定の還元を持つ状態では、本来エラーになる先読みでも還元してから次の状態でエラーになる。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefaultReductionTable {
+    symbols: SymbolIndex,
+    productions: Vec<Production>,
+    start_state: InternalState,
+    defaults: Vec<Option<ProductionId>>,
+    actions: CombVector,
+    gotos: CombVector,
+}
+
+impl DefaultReductionTable {
+    pub fn from_parser(machine: &CompiledParser) -> Self {
+        let symbols = SymbolIndex::from_parser(machine);
+        let mut actions = dense_rows(machine, &symbols.terminals, |state, symbol| {
+            machine.action(state, Terminal(symbol))
+        });
+
+        let defaults: Vec<Option<ProductionId>> = actions
+            .iter()
+            .map(|row| {
+                let mut reductions = row.iter().filter_map(|action| match action {
+                    Some(Action::Reduce(production)) => Some(*production),
+                    _ => None,
+                });
+                let first = reductions.next()?;
+                reductions.all(|production| production == first).then_some(first)
+            })
+            .collect();
+        for (row, default) in actions.iter_mut().zip(&defaults) {
+            if let Some(production) = default {
+                row.iter_mut()
+                    .filter(|action| **action == Some(Action::Reduce(*production)))
+                    .for_each(|action| *action = None);
+            }
+        }
+
+        let actions: Vec<Vec<u32>> = actions
+            .into_iter()
+            .map(|row| row.into_iter().map(encode_action).collect())
+            .collect();
+        let gotos = dense_rows(machine, &symbols.non_terminals, |state, symbol| {
+            encode_goto(machine.goto(state, NonTerminal(symbol)))
+        });
+
+        Self {
+            productions: machine.productions().to_vec(),
+            start_state: machine.start_state(),
+            defaults,
+            actions: CombVector::pack(&actions),
+            gotos: CombVector::pack(&gotos),
+            symbols,
+        }
+    }
+}
+
+impl LrTable for DefaultReductionTable {
+    fn start_state(&self) -> InternalState {
+        self.start_state
+    }
+
+    fn action(&self, state: InternalState, terminal: Terminal) -> Option<Action> {
+        let explicit = self
+            .symbols
+            .terminal(terminal)
+            .and_then(|column| decode_action(self.actions.get(state, column)));
+        explicit.or_else(|| self.defaults.get(state).copied().flatten().map(Action::Reduce))
+    }
+
+    fn goto(&self, state: InternalState, non_terminal: NonTerminal) -> Option<InternalState> {
+        decode_goto(self.gotos.get(state, self.symbols.non_terminal(non_terminal)?))
+    }
+
+    fn production(&self, id: ProductionId) -> Option<&Production> {
+        self.productions.get(id)
+    }
+}
+
+/// 表現ごとの大きさ。生成規則は全表現で同じなので数えない。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodingSize {
+    pub name: &'static str,
+    /// 表が使うおおよそのバイト数（記号の対応表を含む）。
+    pub bytes: usize,
+    /// 格納しているセル数。空セルも場所を取る表現ではそれも数える。
+    pub cells: usize,
+}
+
+impl fmt::Display for EncodingSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<18} {:>8} bytes {:>8} cells", self.name, self.bytes, self.cells)
+    }
+}
+
+/// `machine` をそれぞれの表現にしたときの大きさ。`BTreeMap` は要素だけを数えた下限の見積もり。
+pub fn encoding_sizes(machine: &CompiledParser) -> Vec<EncodingSize> {
+    let tables = machine.tables();
+    let btree_cells = tables.actions.len() + tables.gotos.len();
+    let btree_bytes = tables.actions.len() * size_of::<((InternalState, Terminal), Action)>()
+        + tables.gotos.len() * size_of::<((InternalState, NonTerminal), InternalState)>();
+
+    let dense = DenseTable::from_parser(machine);
+    let comb = CombTable::from_parser(machine);
+    let defaults = DefaultReductionTable::from_parser(machine);
+
+    vec![
+        EncodingSize { name: "btree-map", bytes: btree_bytes, cells: btree_cells },
+        EncodingSize {
+            name: "dense",
+            bytes: dense.symbols.size_in_bytes()
+                + (dense.actions.len() + dense.gotos.len()) * size_of::<u32>(),
+            cells: dense.actions.len() + dense.gotos.len(),
+        },
+        EncodingSize {
+            name: "comb",
+            bytes: comb.symbols.size_in_bytes()
+                + comb.actions.size_in_bytes()
+                + comb.gotos.size_in_bytes(),
+            cells: comb.actions.next.len() + comb.gotos.next.len(),
+        },
+        EncodingSize {
+            name: "default-reduction",
+            bytes: defaults.symbols.size_in_bytes()
+                + defaults.defaults.len() * size_of::<u32>()
+                + defaults.actions.size_in_bytes()
+                + defaults.gotos.size_in_bytes(),
+            cells: defaults.defaults.len() + defaults.actions.next.len() + defaults.gotos.next.len(),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{parse_grammar_text, parse_input_text};
+    use crate::lr::compile;
+    use crate::runtime::{build_trace, run};
+
+    fn machine(text: &str) -> CompiledParser {
+        compile(&parse_grammar_text(text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn comb_vector_keeps_every_cell() {
+        let rows = vec![vec![1, 0, 2, 0], vec![0, 3, 0, 4], vec![0, 0, 0, 0], vec![5, 0, 0, 6]];
+        let packed = CombVector::pack(&rows);
+
+        for (row, cells) in rows.iter().enumerate() {
+            for (column, cell) in cells.iter().enumerate() {
+                assert_eq!(packed.get(row, column), *cell, "({row}, {column})");
+            }
+        }
+        assert!(packed.next.len() < 16);
+    }
+
+    #[test]
+    fn dense_and_comb_tables_answer_like_the_compiled_parser() {
+        let machine = machine("S -> SP\nS -> P\nP -> <>\nP -> <S>\nP -> x");
+        let dense = DenseTable::from_parser(&machine);
+        let comb = CombTable::from_parser(&machine);
+
+        for state in 0..machine.state_count() {
+            for symbol in ['<', '>', 'x', '$', '?'] {
+                let expected = machine.action(state, Terminal(symbol));
+                assert_eq!(dense.action(state, Terminal(symbol)), expected);
+                assert_eq!(comb.action(state, Terminal(symbol)), expected);
+            }
+            for symbol in ['S', 'P', 'Q'] {
+                let expected = machine.goto(state, NonTerminal(symbol));
+                assert_eq!(dense.goto(state, NonTerminal(symbol)), expected);
+                assert_eq!(comb.goto(state, NonTerminal(symbol)), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn end_marker_is_the_last_terminal_column() {
+        let machine = machine(include_str!("../reducer"));
+        let dense = DenseTable::from_parser(&machine);
+
+        assert_eq!(dense.symbols().terminals, ['*', '+', '0', '1', '$']);
+        assert_eq!(dense.symbols().non_terminals, ['B', 'E']);
+        assert_eq!(dense.symbols().terminal(Terminal('$')), Some(4));
+        assert_eq!(dense.symbols().terminal(Terminal('+')), Some(1));
+        assert_eq!(dense.symbols().terminal(Terminal('x')), None);
+        for state in 0..dense.state_count() {
+            assert_eq!(dense.action_row(state)[4], machine.action(state, Terminal('$')));
+        }
+    }
+
+    #[test]
+    fn runtime_runs_from_every_encoding() {
+        let machine = machine(include_str!("../reducer"));
+        let input = parse_input_text("1+1*0").unwrap();
+        let expected = build_trace(&machine, &input).unwrap();
+
+        assert_eq!(build_trace(&DenseTable::from_parser(&machine), &input).unwrap(), expected);
+        assert_eq!(build_trace(&CombTable::from_parser(&machine), &input).unwrap(), expected);
+        assert_eq!(
+            build_trace(&DefaultReductionTable::from_parser(&machine), &input).unwrap(),
+            expected
+        );
+
+        let rejected = parse_input_text("1+").unwrap();
+        assert!(run(&DefaultReductionTable::from_parser(&machine), &rejected).is_err());
+    }
+
+    #[test]
+    fn default_reductions_remove_reduce_rows() {
+        let machine = machine(include_str!("../reducer"));
+        let sizes = encoding_sizes(&machine);
+        let cells = |name: &str| sizes.iter().find(|size| size.name == name).unwrap().cells;
+
+        // 9 状態 × (5 終端 + 2 非終端)
+        assert_eq!(cells("dense"), 63);
+        // 空でないセルは 38 個で、comb はそれを隙間なく詰める
+        assert_eq!(cells("btree-map"), 38);
+        assert_eq!(cells("comb"), 38);
+        // 状態ごとの既定の還元 9 個と、還元だけの行を抜いた残りの 13 セル
+        assert_eq!(cells("default-reduction"), 22);
+        assert!(cells("default-reduction") <= cells("comb"));
+    }
+}
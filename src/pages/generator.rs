@@ -1,6 +1,9 @@
 use crate::app::ParserApp;
+use crate::emit::Target;
+use crate::lexer::is_identifier;
+use crate::roles::{Arity, Operation, TerminalRole};
 use eframe::egui;
-use super::tree::{draw_tree, layout_ast, tree_pixel_height, NODE_R};
+use super::tree::{draw_tree, layout_ast, show_rule_tooltip, tree_pixel_height, NODE_R};
 
 impl ParserApp {
     pub fn show_generator_page(&mut self, ui: &mut egui::Ui) {
@@ -56,11 +59,38 @@ impl ParserApp {
                             }
                         }
 
+                        ui.add_space(16.0);
+                        self.show_custom_roles(ui);
+
+                        ui.add_space(16.0);
+                        self.show_variables(ui);
+
                         ui.add_space(16.0);
                         ui.label(egui::RichText::new("Target String").size(18.0).strong());
                         ui.add_space(8.0);
                         ui.text_edit_singleline(&mut self.workspace.input_string);
 
+                        ui.add_space(16.0);
+                        ui.label(egui::RichText::new("Target Language").size(18.0).strong());
+                        ui.add_space(8.0);
+                        // 言語の選択は `GeneratorEngine::target` だけが持つ
+                        let previous = self.services.generator_engine.target;
+                        egui::ComboBox::from_id_salt("generator_target")
+                            .selected_text(previous.name())
+                            .show_ui(ui, |ui| {
+                                for target in Target::ALL {
+                                    let selected = &mut self.services.generator_engine.target;
+                                    ui.selectable_value(selected, target, target.name());
+                                }
+                            });
+                        // 走っている突き合わせは前の言語のものなので止め、生成済みならすぐ書き直す
+                        if self.services.generator_engine.target != previous {
+                            self.generator.job = None;
+                            if !self.generator.generate_result.is_empty() {
+                                self.generate_code();
+                            }
+                        }
+
                         ui.add_space(16.0);
                         if ui
                             .button(egui::RichText::new("Generate Code").size(17.0))
@@ -70,11 +100,42 @@ impl ParserApp {
                         }
 
                         ui.add_space(8.0);
-                        if ui
-                            .button(egui::RichText::new("Run Generated Rust").size(17.0))
-                            .clicked()
-                        {
-                            self.run_rust_code();
+                        if let Some(job) = &self.generator.job {
+                            let status = format!(
+                                "{}… {:.1}s",
+                                job.phase().label(),
+                                job.elapsed().as_secs_f64()
+                            );
+                            ui.horizontal(|ui| {
+                                ui.spinner();
+                                ui.label(egui::RichText::new(status).size(14.0));
+                                if ui.button("Cancel").clicked() {
+                                    self.cancel_cross_check();
+                                }
+                            });
+                        } else {
+                            let target = self.services.generator_engine.target;
+                            let tool = target.emitter().toolchain().program;
+                            // 翻訳テンプレートの出力はプログラムではないので走らせない
+                            let button = egui::Button::new(
+                                egui::RichText::new(format!("Cross-check with {}", tool)).size(17.0),
+                            );
+                            if ui
+                                .add_enabled(!self.generator.translated, button)
+                                .on_hover_text(format!(
+                                    "Run the generated {} program in the background \
+                                     (needs {} on PATH; stopped after a timeout)",
+                                    target.name(),
+                                    tool
+                                ))
+                                .on_disabled_hover_text(
+                                    "The output comes from the grammar's translation templates, \
+                                     not from a generated program",
+                                )
+                                .clicked()
+                            {
+                                self.cross_check();
+                            }
                         }
 
                         ui.add_space(16.0);
@@ -97,6 +158,26 @@ impl ParserApp {
                                 );
                             }
                         }
+
+                        if !self.generator.role_warnings.is_empty() {
+                            ui.add_space(16.0);
+                            ui.label(egui::RichText::new("Role Check").size(18.0).strong());
+                            ui.label(
+                                egui::RichText::new(
+                                    "Productions and terminals whose roles cannot form an expression.",
+                                )
+                                .size(12.0)
+                                .color(egui::Color32::GRAY),
+                            );
+                            ui.add_space(8.0);
+                            for warning in &self.generator.role_warnings {
+                                ui.label(
+                                    egui::RichText::new(format!("- {}", warning))
+                                        .size(13.0)
+                                        .color(egui::Color32::from_rgb(240, 180, 60)),
+                                );
+                            }
+                        }
                     });
 
                     ui.add_space(18.0);
@@ -126,15 +207,18 @@ impl ParserApp {
                                     .max_height(tree_h)
                                     .show(ui, |ui| {
                                         let size = egui::Vec2::new(tree_w, tree_h);
-                                        let (rect, _) =
+                                        let (rect, response) =
                                             ui.allocate_exact_size(size, egui::Sense::hover());
+                                        let origin = rect.min + egui::Vec2::new(0.0, NODE_R);
                                         if ui.is_rect_visible(rect) {
-                                            draw_tree(
-                                                ui.painter(),
-                                                rect.min + egui::Vec2::new(0.0, NODE_R),
-                                                &layout,
-                                            );
+                                            draw_tree(ui.painter(), origin, &layout);
                                         }
+                                        show_rule_tooltip(
+                                            response,
+                                            origin,
+                                            &layout,
+                                            &self.generator.rules,
+                                        );
                                     });
                             });
                         } else {
@@ -162,14 +246,32 @@ impl ParserApp {
                         );
 
                         ui.add_space(14.0);
-                        ui.label(egui::RichText::new("Generated Rust Code").size(18.0).strong());
+                        let (title, label) = if self.generator.translated {
+                            ("Translation".to_string(), "Output")
+                        } else {
+                            let target = self.services.generator_engine.target;
+                            (format!("Generated {} Code", target.name()), "Code")
+                        };
+                        ui.label(egui::RichText::new(title).size(18.0).strong());
+                        ui.add_space(8.0);
+                        self.show_preview_card(ui, label, &self.generator.generate_result.clone());
+
+                        ui.add_space(14.0);
+                        ui.label(egui::RichText::new("Bytecode").size(18.0).strong());
                         ui.add_space(8.0);
-                        self.show_preview_card(ui, "Code", &self.generator.generate_result.clone());
+                        self.show_preview_card(ui, "Disassembly", &self.generator.disassembly.clone());
+                        ui.add_space(10.0);
+                        self.show_preview_card(ui, "Stack VM", &self.generator.vm_result.clone());
+
+                        ui.add_space(14.0);
+                        ui.label(egui::RichText::new("Generated Parser Module").size(18.0).strong());
+                        ui.add_space(8.0);
+                        self.show_preview_card(ui, "Module", &self.generator.parser_module.clone());
 
                         ui.add_space(14.0);
                         ui.label(egui::RichText::new("Execution Result").size(18.0).strong());
                         ui.add_space(8.0);
-                        self.show_preview_card(ui, "Run", &self.generator.run_result.clone());
+                        self.show_preview_card(ui, "Value", &self.generator.run_result.clone());
                     });
                 });
             });
@@ -208,28 +310,180 @@ impl ParserApp {
     }
 
     fn show_terminal_dropdown(&mut self, ui: &mut egui::Ui, symbol: char) {
-        let current_selection = self
-            .workspace.terminal_types
-            .get(&symbol)
-            .unwrap_or(&"Token".to_string())
-            .clone();
+        let roles = self.workspace.role_registry.roles();
+        let current = self
+            .workspace
+            .terminal_roles
+            .entry(symbol)
+            .or_insert(TerminalRole::Token);
 
         egui::ComboBox::from_id_salt(format!("combo_{}", symbol))
-            .selected_text(&current_selection)
+            .selected_text(current.name())
             .show_ui(ui, |ui| {
-                let options = [
-                    "Token", "Num", "Add", "Sub", "Mul", "Div", "Mod", "LParen", "RParen",
-                    "Ignore",
-                ];
-                for option in &options {
-                    ui.selectable_value(
-                        self.workspace.terminal_types
-                            .entry(symbol)
-                            .or_insert_with(|| "Token".to_string()),
-                        option.to_string(),
-                        *option,
-                    );
+                for role in roles {
+                    let label = role.name().to_string();
+                    ui.selectable_value(current, role, label);
+                }
+            });
+    }
+
+    fn show_custom_roles(&mut self, ui: &mut egui::Ui) {
+        ui.label(egui::RichText::new("Custom Roles").size(18.0).strong());
+        ui.label(
+            egui::RichText::new("Operators beyond Add..Mod. {0} and {1} are the operands in the evaluation template (Rust).")
+                .size(12.0)
+                .color(egui::Color32::GRAY),
+        );
+        // 独自の役割でできることの限りを、説明の奥ではなく編集欄のそばに出す
+        ui.label(
+            egui::RichText::new(format!(
+                "A custom role only re-labels one of the {} operations below; it cannot add a new one. \
+                 The evaluation template is used for Rust output only, other targets compute the operation.",
+                Operation::ALL.len()
+            ))
+            .size(12.0)
+            .color(egui::Color32::from_rgb(240, 180, 60)),
+        );
+        let target = self.services.generator_engine.target;
+        if target != Target::Rust && !self.workspace.role_registry.custom().is_empty() {
+            ui.label(
+                egui::RichText::new(format!(
+                    "The {} output ignores the evaluation templates below.",
+                    target.name()
+                ))
+                .size(12.0)
+                .color(egui::Color32::from_rgb(240, 180, 60)),
+            );
+        }
+        ui.add_space(8.0);
+
+        let mut removed = None;
+        for definition in self.workspace.role_registry.custom() {
+            ui.horizontal(|ui| {
+                ui.label(
+                    egui::RichText::new(format!(
+                        "{}  {} {}  prec {}  {}",
+                        definition.name,
+                        definition.arity.key(),
+                        definition.operation.key(),
+                        definition.precedence,
+                        definition.evaluation
+                    ))
+                    .monospace()
+                    .size(13.0),
+                );
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(definition.name.clone());
+                }
+            });
+        }
+        if let Some(name) = removed {
+            self.workspace.role_registry.remove(&name);
+        }
+
+        let draft = &mut self.generator.role_draft;
+        egui::Grid::new("role_draft_grid").num_columns(2).show(ui, |ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut draft.name);
+            ui.end_row();
+
+            ui.label("Operation");
+            egui::ComboBox::from_id_salt("role_draft_operation")
+                .selected_text(draft.operation.key())
+                .show_ui(ui, |ui| {
+                    for operation in Operation::ALL {
+                        ui.selectable_value(&mut draft.operation, operation, operation.key());
+                    }
+                })
+                .response
+                .on_hover_text("What the role computes in every target; only these operations exist");
+            ui.end_row();
+
+            ui.label("Arity");
+            ui.horizontal(|ui| {
+                for arity in [Arity::Unary, Arity::Binary] {
+                    ui.radio_value(&mut draft.arity, arity, arity.key());
                 }
             });
+            ui.end_row();
+
+            ui.label("Precedence");
+            ui.add(egui::DragValue::new(&mut draft.precedence).range(0..=9));
+            ui.end_row();
+
+            ui.label("Source");
+            ui.text_edit_singleline(&mut draft.source)
+                .on_hover_text("Shown in the reconstructed source; empty keeps the terminal itself");
+            ui.end_row();
+
+            ui.label("Evaluation (Rust)");
+            ui.text_edit_singleline(&mut draft.evaluation)
+                .on_hover_text("Rust expression used only in Rust output; other targets compute the operation");
+            ui.end_row();
+        });
+
+        if ui.button("Add role").clicked() {
+            let definition = self.generator.role_draft.clone();
+            self.generator.role_message = match self.workspace.role_registry.define(definition) {
+                Ok(()) => format!("Added role {}", self.generator.role_draft.name.trim()),
+                Err(error) => error.to_string(),
+            };
+        }
+        if !self.generator.role_message.is_empty() {
+            ui.label(
+                egui::RichText::new(&self.generator.role_message)
+                    .size(13.0)
+                    .color(egui::Color32::GRAY),
+            );
+        }
+    }
+
+    fn show_variables(&mut self, ui: &mut egui::Ui) {
+        ui.label(egui::RichText::new("Variables").size(18.0).strong());
+        ui.label(
+            egui::RichText::new(
+                "Values for identifiers read by an Ident terminal. Digits are read as one number \
+                 when Num is assigned to a non-digit terminal.",
+            )
+            .size(12.0)
+            .color(egui::Color32::GRAY),
+        );
+        ui.add_space(8.0);
+
+        let mut removed = None;
+        for (name, value) in &mut self.workspace.variables {
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(format!("{} =", name)).monospace().size(13.0));
+                ui.add(egui::DragValue::new(value));
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(name.clone());
+                }
+            });
+        }
+        if let Some(name) = removed {
+            self.workspace.variables.remove(&name);
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.generator.variable_name).desired_width(80.0));
+            ui.label("=");
+            ui.add(egui::DragValue::new(&mut self.generator.variable_value));
+            if ui.button("Set").clicked() {
+                let name = self.generator.variable_name.trim().to_string();
+                self.generator.variable_message = if is_identifier(&name) {
+                    self.workspace.variables.insert(name.clone(), self.generator.variable_value);
+                    format!("Set {} = {}", name, self.generator.variable_value)
+                } else {
+                    format!("`{}` is not an identifier (a-z, 0-9 and _, not starting with a digit)", name)
+                };
+            }
+        });
+        if !self.generator.variable_message.is_empty() {
+            ui.label(
+                egui::RichText::new(&self.generator.variable_message)
+                    .size(13.0)
+                    .color(egui::Color32::GRAY),
+            );
+        }
     }
 }
@@ -4,12 +4,12 @@ use lr0_parser_rs::lr::{CompiledParser, ParserError, compile};
 use lr0_parser_rs::runtime::{RuntimeError, run};
 use lr0_parser_rs::{StateInfo, StepAction};
 use std::fmt;
-use super::tree::{draw_tree, layout_ast, tree_pixel_height, H_GAP, NODE_R};
+use super::tree::{draw_tree, layout_ast, show_rule_tooltip, tree_pixel_height, H_GAP, NODE_R};
 
 use crate::app::{
     ParseArtifacts, ParseTableAction, ParserApp, ParserKind, ParserStatus,
-    SmHighlightView, TraceCursorView,
-    build_animation_trace, build_parse_table, terminals_from_grammar,
+    SmHighlightView, TableFormat, TraceCursorView,
+    build_animation_trace, build_parse_table, rule_labels, terminals_from_grammar,
 };
 use crate::validation::Validation;
 
@@ -38,12 +38,18 @@ impl fmt::Display for UiError {
             UiError::Grammar(GrammarError::InvalidSymbol(c)) => {
                 write!(f, "Invalid symbol '{c}'. Non-terminals must be uppercase ASCII.")
             }
+            UiError::Grammar(GrammarError::InvalidDirective(message)) => {
+                write!(f, "Invalid tree directive: {message}. Use: :: drop <i>... | root <i> | collapse")
+            }
+            UiError::Grammar(GrammarError::InvalidAttribute(message)) => {
+                write!(f, "Invalid attribute equation: {message}. Use: @ $$.name = $0.name + 1")
+            }
+            UiError::Grammar(GrammarError::InvalidTranslation(message)) => {
+                write!(f, "Invalid translation template: {message}. Use: => \"... {{0}} ...\"")
+            }
             UiError::Compile(ParserError::ConflictReducer) => {
                 write!(f, "LR conflict: grammar is not LR(0). Check for ambiguous productions.")
             }
-            UiError::Compile(ParserError::MissingProduction) => {
-                write!(f, "Internal error: production not found during compile.")
-            }
             UiError::Runtime(RuntimeError::InvalidAction) => {
                 write!(f, "Parse error: unexpected token in input. Check that the input matches the grammar.")
             }
@@ -160,7 +166,7 @@ impl ParserApp {
         ui.add_space(10.0);
         ui.horizontal(|ui| {
             ui.label(egui::RichText::new("Algorithm:").size(14.0));
-            for kind in [ParserKind::Lr0, ParserKind::Slr, ParserKind::Lalr, ParserKind::Lr1] {
+            for kind in ParserKind::ALL {
                 if ui.selectable_label(self.parser.selected_kind == kind, kind.label()).clicked() {
                     self.parser.selected_kind = kind;
                 }
@@ -332,6 +338,10 @@ impl ParserApp {
 
     fn show_ast_formation_section(&self, ui: &mut egui::Ui, view: &TraceCursorView) {
         let Some(step) = &view.step else { return; };
+        let rules: &[String] = match &self.parser.status {
+            ParserStatus::Ready(artifacts) => &artifacts.rules,
+            ParserStatus::Empty => &[],
+        };
 
         ui.horizontal(|ui| {
             ui.label(egui::RichText::new("AST Formation:").size(16.0));
@@ -390,14 +400,13 @@ impl ParserApp {
                                 let tree_h = bottom_y + NODE_R * 2.0 + 6.0;
                                 let size = egui::Vec2::new(tree_w, tree_h);
 
-                                let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+                                let (rect, response) =
+                                    ui.allocate_exact_size(size, egui::Sense::hover());
+                                let origin = rect.min + egui::Vec2::new(0.0, NODE_R);
                                 if ui.is_rect_visible(rect) {
-                                    draw_tree(
-                                        ui.painter(),
-                                        rect.min + egui::Vec2::new(0.0, NODE_R),
-                                        &layout,
-                                    );
+                                    draw_tree(ui.painter(), origin, &layout);
                                 }
+                                show_rule_tooltip(response, origin, &layout, rules);
                             });
 
                         ui.add_space(8.0);
@@ -406,8 +415,21 @@ impl ParserApp {
             });
     }
 
-    fn show_parse_table_panel(&self, ui: &mut egui::Ui, table_h: f32, view: &TraceCursorView) {
-        ui.label(egui::RichText::new("Parse Table:").size(16.0));
+    fn show_parse_table_panel(&mut self, ui: &mut egui::Ui, table_h: f32, view: &TraceCursorView) {
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("Parse Table:").size(16.0));
+            let exportable = matches!(self.parser.status, ParserStatus::Ready(_));
+            ui.add_enabled_ui(exportable, |ui| {
+                ui.menu_button("Export", |ui| {
+                    for format in TableFormat::ALL {
+                        if ui.button(format.name()).clicked() {
+                            self.export_parse_table(format);
+                            ui.close();
+                        }
+                    }
+                });
+            });
+        });
         ui.add_space(5.0);
 
         ui.horizontal(|ui| {
@@ -442,8 +464,19 @@ impl ParserApp {
         }
     }
 
-    fn show_state_machine_panel(&self, ui: &mut egui::Ui, view: &TraceCursorView) {
-        ui.label(egui::RichText::new("State Machine:").size(16.0));
+    fn show_state_machine_panel(&mut self, ui: &mut egui::Ui, view: &TraceCursorView) {
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("State Machine:").size(16.0));
+            let exportable = matches!(
+                &self.parser.status,
+                ParserStatus::Ready(artifacts) if !artifacts.state_infos.is_empty()
+            );
+            for (label, extension) in [("Export DOT", "dot"), ("Export SVG", "svg")] {
+                if ui.add_enabled(exportable, egui::Button::new(label)).clicked() {
+                    self.export_state_machine(extension);
+                }
+            }
+        });
         ui.add_space(5.0);
 
         // ── Trace Debug panel ─────────────────────────────────────────────
@@ -456,8 +489,8 @@ impl ParserApp {
                     let action_str = match &step.action {
                         StepAction::Shift { terminal, to_state } =>
                             format!("SHIFT '{}' -> {}", terminal, to_state),
-                        StepAction::Reduce { rule, pop_count } =>
-                            format!("REDUCE {} (pop {})", rule, pop_count),
+                        StepAction::Reduce { production, rule, pop_count } =>
+                            format!("REDUCE r{} {} (pop {})", production, rule, pop_count),
                         StepAction::Accept =>
                             "ACCEPT".to_string(),
                     };
@@ -713,7 +746,7 @@ impl ParserApp {
         // ── フェーズ2: compile 成功後のテーブル構築 ────────────────────────────
         let (symbols, table) = build_parse_table(&request.grammar, &request.machine);
         self.workspace.terminals = terminals_from_grammar(&request.grammar);
-        self.apply_default_terminal_types();
+        self.apply_default_terminal_roles();
 
         // ── フェーズ3: run（RunRequest への依存的な逐次処理） ──────────────────
         match run(&request.machine, &request.input_symbols).map_err(UiError::Runtime) {
@@ -729,21 +762,20 @@ impl ParserApp {
                     table,
                     state_infos: vec![],
                     accept_states: vec![],
+                    rules: rule_labels(&request.machine),
                 });
                 return;
             }
         }
 
         let state_infos = request.machine.state_infos().to_vec();
-        let accept_states: Vec<usize> = table.iter().enumerate()
-            .filter(|(_, row)| row.iter().any(|a| matches!(a, ParseTableAction::Accept)))
-            .map(|(state, _)| state)
-            .collect();
+        let accept_states = lr0_parser_rs::diagram::accept_states(&request.machine);
         self.parser.status = ParserStatus::Ready(ParseArtifacts {
             symbols,
             table,
             state_infos,
             accept_states,
+            rules: rule_labels(&request.machine),
         });
 
         self.parser.parse_trace = build_animation_trace(&request.machine, &request.input_symbols).unwrap_or_default();
@@ -771,25 +803,7 @@ fn layout_sm(state_infos: &[StateInfo]) -> (Vec<SmNodePos>, f32, f32) {
     if state_infos.is_empty() {
         return (vec![], 0.0, 0.0);
     }
-    let n = state_infos.len();
-    let mut layer = vec![usize::MAX; n];
-    let mut queue = std::collections::VecDeque::new();
-    layer[0] = 0;
-    queue.push_back(0_usize);
-    while let Some(s) = queue.pop_front() {
-        for (_, next) in &state_infos[s].transitions {
-            if *next < n && layer[*next] == usize::MAX {
-                layer[*next] = layer[s] + 1;
-                queue.push_back(*next);
-            }
-        }
-    }
-    let max_reached = layer.iter().filter(|&&l| l != usize::MAX).max().copied().unwrap_or(0);
-    for l in layer.iter_mut() {
-        if *l == usize::MAX {
-            *l = max_reached + 1;
-        }
-    }
+    let layer = lr0_parser_rs::diagram::layers(state_infos);
     let num_layers = layer.iter().max().copied().unwrap_or(0) + 1;
     let mut by_layer: Vec<Vec<usize>> = vec![vec![]; num_layers];
     for (s, &l) in layer.iter().enumerate() {
@@ -1004,7 +1018,7 @@ fn draw_sm(
         painter.text(
             pos,
             egui::Align2::CENTER_CENTER,
-            &node.state_id.to_string(),
+            node.state_id.to_string(),
             egui::FontId::monospace(13.0),
             egui::Color32::WHITE,
         );
@@ -1053,8 +1067,8 @@ fn render_action_label(action: &StepAction) -> (String, egui::Color32) {
             format!("SHIFT '{terminal}'  →  State {to_state}"),
             egui::Color32::from_rgb(80, 200, 80),
         ),
-        StepAction::Reduce { rule, pop_count } => (
-            format!("REDUCE  {rule}  (pop {pop_count})"),
+        StepAction::Reduce { production, rule, pop_count } => (
+            format!("REDUCE  r{production}: {rule}  (pop {pop_count})"),
             egui::Color32::from_rgb(100, 140, 255),
         ),
         StepAction::Accept => (
@@ -1,5 +1,6 @@
 use eframe::egui;
-use lr0_parser_rs::AstNode;
+use lr0_parser_rs::ast::Attributes;
+use lr0_parser_rs::{AstNode, Fold, NodeInfo, Token};
 
 pub(super) const NODE_R: f32 = 14.0;
 pub(super) const H_GAP: f32 = 48.0;
@@ -8,6 +9,9 @@ pub(super) const V_GAP: f32 = 52.0;
 pub(super) struct LayoutNode {
     pub label: String,
     pub is_terminal: bool,
+    pub production: Option<usize>,
+    /// ノードの属性（`name=value`）。属性文法の値などをノードの下に出す。
+    pub attributes: Vec<String>,
     pub x: f32,
     pub y: f32,
     pub subtree_width: f32,
@@ -15,56 +19,69 @@ pub(super) struct LayoutNode {
 }
 
 pub(super) fn layout_ast(node: &AstNode) -> LayoutNode {
-    layout_rec(node, 0)
+    node.fold(&mut TreeLayout)
 }
 
-fn layout_rec(node: &AstNode, depth: usize) -> LayoutNode {
-    let y = depth as f32 * V_GAP;
-    match node {
-        AstNode::Terminal(c) => LayoutNode {
-            label: c.to_string(),
+// 子のレイアウトを横に並べて1段下げ、その中央に親を置く。
+struct TreeLayout;
+
+impl Fold for TreeLayout {
+    type Output = LayoutNode;
+
+    fn fold_terminal(&mut self, token: &Token) -> LayoutNode {
+        LayoutNode {
+            label: token.lexeme.clone(),
             is_terminal: true,
+            production: None,
+            attributes: attribute_labels(&token.attributes),
             x: H_GAP / 2.0,
-            y,
+            y: 0.0,
             subtree_width: H_GAP,
             children: vec![],
-        },
-        AstNode::NonTerminal(name, children) => {
-            if children.is_empty() {
-                return LayoutNode {
-                    label: name.to_string(),
-                    is_terminal: false,
-                    x: H_GAP / 2.0,
-                    y,
-                    subtree_width: H_GAP,
-                    children: vec![],
-                };
-            }
-            let mut child_layouts: Vec<LayoutNode> =
-                children.iter().map(|c| layout_rec(c, depth + 1)).collect();
-            let mut x_cursor = 0.0;
-            for child in &mut child_layouts {
-                shift_x(child, x_cursor);
-                x_cursor += child.subtree_width;
-            }
-            let total_width = x_cursor;
-            let center_x = total_width / 2.0;
-            LayoutNode {
-                label: name.to_string(),
+        }
+    }
+
+    fn fold_non_terminal(&mut self, info: &NodeInfo, mut children: Vec<LayoutNode>) -> LayoutNode {
+        if children.is_empty() {
+            return LayoutNode {
+                label: info.symbol.to_string(),
                 is_terminal: false,
-                x: center_x,
-                y,
-                subtree_width: total_width,
-                children: child_layouts,
-            }
+                production: info.production,
+                attributes: attribute_labels(&info.attributes),
+                x: H_GAP / 2.0,
+                y: 0.0,
+                subtree_width: H_GAP,
+                children: vec![],
+            };
+        }
+        let mut x_cursor = 0.0;
+        for child in &mut children {
+            shift(child, x_cursor, V_GAP);
+            x_cursor += child.subtree_width;
+        }
+        let total_width = x_cursor;
+        LayoutNode {
+            label: info.symbol.to_string(),
+            is_terminal: false,
+            production: info.production,
+            attributes: attribute_labels(&info.attributes),
+            x: total_width / 2.0,
+            y: 0.0,
+            subtree_width: total_width,
+            children,
         }
     }
 }
 
-pub(super) fn shift_x(node: &mut LayoutNode, dx: f32) {
+fn attribute_labels(attributes: &Attributes) -> Vec<String> {
+    attributes.iter().map(|(name, value)| format!("{name}={value}")).collect()
+}
+
+fn shift(node: &mut LayoutNode, dx: f32, dy: f32) {
     node.x += dx;
+    node.y += dy;
     for child in &mut node.children {
-        shift_x(child, dx);
+        shift(child, dx, dy);
     }
 }
 
@@ -79,6 +96,37 @@ pub(super) fn tree_pixel_height(node: &LayoutNode) -> f32 {
     }
 }
 
+fn node_at(node: &LayoutNode, origin: egui::Pos2, pointer: egui::Pos2) -> Option<&LayoutNode> {
+    let center = origin + egui::Vec2::new(node.x, node.y);
+    if center.distance(pointer) <= NODE_R {
+        return Some(node);
+    }
+    node.children
+        .iter()
+        .find_map(|child| node_at(child, origin, pointer))
+}
+
+/// ポインタ下の非終端ノードを還元した生成規則と、ノードの属性をツールチップで表示する。
+/// `rules` は生成規則 id で引けるラベル列（`rules[id]`）。
+pub(super) fn show_rule_tooltip(
+    response: egui::Response,
+    origin: egui::Pos2,
+    node: &LayoutNode,
+    rules: &[String],
+) {
+    let Some(pointer) = response.hover_pos() else { return; };
+    let Some(hit) = node_at(node, origin, pointer) else { return; };
+    let mut lines: Vec<String> = hit
+        .production
+        .and_then(|id| rules.get(id).map(|rule| format!("r{id}: {rule}")))
+        .into_iter()
+        .collect();
+    lines.extend(hit.attributes.iter().cloned());
+    if !lines.is_empty() {
+        response.on_hover_text_at_pointer(lines.join("\n"));
+    }
+}
+
 pub(super) fn draw_tree(painter: &egui::Painter, origin: egui::Pos2, node: &LayoutNode) {
     let center = origin + egui::Vec2::new(node.x, node.y);
 
@@ -110,4 +158,13 @@ pub(super) fn draw_tree(painter: &egui::Painter, origin: egui::Pos2, node: &Layo
         egui::FontId::monospace(11.0),
         rim,
     );
+    if !node.attributes.is_empty() {
+        painter.text(
+            center + egui::Vec2::new(0.0, NODE_R + 1.0),
+            egui::Align2::CENTER_TOP,
+            node.attributes.join(" "),
+            egui::FontId::monospace(9.5),
+            egui::Color32::from_rgb(170, 190, 240),
+        );
+    }
 }
@@ -0,0 +1,303 @@
+use crate::roles::{Arity, Operation, RoleRegistry, TerminalRole};
+use lr0_parser_rs::grammar::{Grammar, Symbol, Terminal};
+use std::collections::HashMap;
+use std::fmt;
+
+/// 警告の出どころ。生成規則は `CompiledParser` と同じ id（文法の n 番目が n + 1）で指す。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Production { id: usize, rule: String },
+    Terminal(char),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// 被演算子が演算子を挟まずに並ぶ。
+    AdjacentOperands { left: char, right: char },
+    /// 二項演算子の左に被演算子がない（符号として読める Add/Sub は除く）。
+    MissingLeftOperand { operator: char, role: TerminalRole },
+    MissingRightOperand { operator: char, role: TerminalRole },
+    /// 右辺に被演算子が 1 つもなく、演算子だけがある。
+    NoOperands { operator: char, role: TerminalRole },
+    /// 前置の単項演算子が被演算子の後ろに来る。
+    UnaryAfterOperand { operator: char, role: TerminalRole },
+    UnmatchedParen { symbol: char, role: TerminalRole },
+    /// 文法に現れない終端記号に役割が割り当てられている。
+    UnusedTerminal { role: TerminalRole },
+    /// 登録されていない独自の役割が割り当てられている。
+    UndefinedRole { role: TerminalRole },
+}
+
+/// 文法と役割の組み合わせから、評価式にならない形を実行前に見つけた警告。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoleWarning {
+    pub location: Location,
+    pub problem: Problem,
+}
+
+impl fmt::Display for RoleWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.location {
+            Location::Production { id, rule } => write!(f, "r{} `{}`: ", id, rule)?,
+            Location::Terminal(symbol) => write!(f, "terminal '{}': ", symbol)?,
+        }
+        match &self.problem {
+            Problem::AdjacentOperands { left, right } => {
+                write!(f, "operands '{}' and '{}' sit side by side with no operator between them", left, right)
+            }
+            Problem::MissingLeftOperand { operator, role } => {
+                write!(f, "'{}' ({}) has no left operand", operator, role)
+            }
+            Problem::MissingRightOperand { operator, role } => {
+                write!(f, "'{}' ({}) has no right operand", operator, role)
+            }
+            Problem::NoOperands { operator, role } => write!(f, "'{}' ({}) has no operands", operator, role),
+            Problem::UnaryAfterOperand { operator, role } => {
+                write!(f, "prefix operator '{}' ({}) follows an operand", operator, role)
+            }
+            Problem::UnmatchedParen { symbol, role } => {
+                let other = if *role == TerminalRole::LParen { "RParen" } else { "LParen" };
+                write!(f, "'{}' ({}) has no matching {} in the same production", symbol, role, other)
+            }
+            Problem::UnusedTerminal { role } => write!(f, "has role {} but the grammar never uses it", role),
+            Problem::UndefinedRole { role } => write!(f, "role {} is not defined under Custom Roles", role),
+        }
+    }
+}
+
+enum Item<'r> {
+    Operand(char),
+    Operator(char, &'r TerminalRole, Arity, Operation),
+    /// 未定義の独自の役割。被演算子か演算子か分からないので、前後の形は問わない。
+    Unknown,
+}
+
+/// 各生成規則の右辺を役割で読み、`evaluator::expression` が式にできない形を探す。
+/// 非終端記号は値を持つものとみなす。Token のままの終端記号を含む規則は、まだ役割が決まっていないので見ない。
+pub fn check_roles(
+    grammar: &Grammar,
+    roles: &HashMap<char, TerminalRole>,
+    registry: &RoleRegistry,
+) -> Vec<RoleWarning> {
+    let mut warnings = Vec::new();
+    for (index, production) in grammar.productions.iter().enumerate() {
+        let location = Location::Production { id: index + 1, rule: production.to_string() };
+        for problem in check_production(&production.right, roles, registry) {
+            warnings.push(RoleWarning { location: location.clone(), problem });
+        }
+    }
+
+    let used = grammar.terminals();
+    let mut assigned: Vec<(&char, &TerminalRole)> = roles.iter().collect();
+    assigned.sort();
+    for (&symbol, role) in assigned {
+        let problem = if !used.contains(&Terminal(symbol)) {
+            if *role == TerminalRole::Token {
+                continue;
+            }
+            Problem::UnusedTerminal { role: role.clone() }
+        } else if matches!(role, TerminalRole::Custom(_)) && registry.operator(role).is_none() {
+            Problem::UndefinedRole { role: role.clone() }
+        } else {
+            continue;
+        };
+        warnings.push(RoleWarning { location: Location::Terminal(symbol), problem });
+    }
+    warnings
+}
+
+fn check_production(
+    right: &[Symbol],
+    roles: &HashMap<char, TerminalRole>,
+    registry: &RoleRegistry,
+) -> Vec<Problem> {
+    const TOKEN: TerminalRole = TerminalRole::Token;
+    let mut problems = Vec::new();
+    let mut items = Vec::new();
+    let mut open: Vec<(char, &TerminalRole)> = Vec::new();
+    for symbol in right {
+        let terminal = match symbol {
+            Symbol::NonTerminal(non_terminal) => {
+                items.push(Item::Operand(non_terminal.0));
+                continue;
+            }
+            Symbol::Terminal(terminal) => terminal.0,
+        };
+        let role = roles.get(&terminal).unwrap_or(&TOKEN);
+        match role {
+            TerminalRole::Token => return Vec::new(),
+            TerminalRole::Num | TerminalRole::Ident => items.push(Item::Operand(terminal)),
+            TerminalRole::Ignore => {}
+            TerminalRole::LParen => open.push((terminal, role)),
+            TerminalRole::RParen => {
+                if open.pop().is_none() {
+                    problems.push(Problem::UnmatchedParen { symbol: terminal, role: role.clone() });
+                }
+            }
+            role => match registry.operator(role) {
+                Some(definition) => {
+                    items.push(Item::Operator(terminal, role, definition.arity, definition.operation))
+                }
+                None => {
+                    problems.push(Problem::UndefinedRole { role: role.clone() });
+                    items.push(Item::Unknown);
+                }
+            },
+        }
+    }
+    for (symbol, role) in open {
+        problems.push(Problem::UnmatchedParen { symbol, role: role.clone() });
+    }
+    problems.extend(shape(&items));
+    problems
+}
+
+// `evaluator::operand` と `binary` の読み方をなぞる。最初に崩れたところだけを返す。
+fn shape(items: &[Item]) -> Option<Problem> {
+    let mut expect_operand = true;
+    let mut previous = None;
+    let mut operands = 0;
+    let mut last_operator = None;
+    // 直前が `Item::Unknown` なら、次の要素はどちらの位置にも置けたものとして読む
+    let mut after_unknown = false;
+    for item in items {
+        if std::mem::take(&mut after_unknown) {
+            expect_operand = !matches!(item, Item::Operator(_, _, Arity::Binary, _));
+        }
+        match *item {
+            Item::Unknown => {
+                previous = None;
+                last_operator = None;
+                after_unknown = true;
+            }
+            Item::Operand(symbol) => {
+                if !expect_operand {
+                    return Some(Problem::AdjacentOperands { left: previous?, right: symbol });
+                }
+                previous = Some(symbol);
+                operands += 1;
+                expect_operand = false;
+            }
+            Item::Operator(operator, role, arity, operation) => {
+                let role = role.clone();
+                match (expect_operand, arity, operation) {
+                    // 被演算子の位置の Add/Sub は符号
+                    (true, Arity::Unary, _) | (true, Arity::Binary, Operation::Add | Operation::Sub) => {}
+                    (true, Arity::Binary, _) if operands == 0 && items.len() == 1 => {
+                        return Some(Problem::NoOperands { operator, role });
+                    }
+                    (true, Arity::Binary, _) => return Some(Problem::MissingLeftOperand { operator, role }),
+                    (false, Arity::Binary, _) => expect_operand = true,
+                    (false, Arity::Unary, _) => return Some(Problem::UnaryAfterOperand { operator, role }),
+                }
+                last_operator = Some((operator, role));
+            }
+        }
+    }
+    match last_operator {
+        Some((operator, role)) if expect_operand && operands == 0 => Some(Problem::NoOperands { operator, role }),
+        Some((operator, role)) if expect_operand => Some(Problem::MissingRightOperand { operator, role }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roles::RoleDefinition;
+    use lr0_parser_rs::grammar::parse_grammar_text;
+
+    fn warnings(grammar: &str, extra: &[(char, TerminalRole)]) -> Vec<String> {
+        let grammar = parse_grammar_text(grammar).unwrap();
+        let mut roles: HashMap<char, TerminalRole> = grammar
+            .terminals()
+            .into_iter()
+            .map(|terminal| (terminal.0, TerminalRole::default_for(terminal.0)))
+            .collect();
+        roles.extend(extra.iter().cloned());
+        let mut registry = RoleRegistry::default();
+        registry
+            .define(RoleDefinition {
+                name: "Not".to_string(),
+                source: String::new(),
+                evaluation: "i64::from({0} == 0)".to_string(),
+                arity: Arity::Unary,
+                precedence: 3,
+                operation: Operation::Not,
+            })
+            .unwrap();
+        check_roles(&grammar, &roles, &registry).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn evaluable_grammars_have_no_warnings() {
+        assert!(warnings(include_str!("../reducer"), &[]).is_empty());
+        let grammar = "E -> E+B\nE -> E*B\nE -> B\nB -> (E)\nB -> -B\nB -> !B\nB -> 1";
+        assert!(warnings(grammar, &[('!', TerminalRole::Custom("Not".to_string()))]).is_empty());
+        // 役割の決まっていない規則は見ない
+        assert!(warnings("S -> ab", &[]).is_empty());
+    }
+
+    #[test]
+    fn bad_shapes_point_at_their_production() {
+        assert_eq!(
+            warnings("E -> E+B\nE -> B\nB -> 12", &[]),
+            ["r3 `B -> 12`: operands '1' and '2' sit side by side with no operator between them"]
+        );
+        assert_eq!(warnings("E -> +", &[]), ["r1 `E -> +`: '+' (Add) has no operands"]);
+        assert_eq!(
+            warnings("E -> E*B\nE -> *B\nE -> B\nB -> 1", &[]),
+            ["r2 `E -> *B`: '*' (Mul) has no left operand"]
+        );
+        assert_eq!(
+            warnings("E -> E-\nE -> 1", &[]),
+            ["r1 `E -> E-`: '-' (Sub) has no right operand"]
+        );
+        assert_eq!(
+            warnings("E -> (E\nE -> 1)", &[]),
+            [
+                "r1 `E -> (E`: '(' (LParen) has no matching RParen in the same production",
+                "r2 `E -> 1)`: ')' (RParen) has no matching LParen in the same production",
+            ]
+        );
+    }
+
+    #[test]
+    fn undefined_roles_do_not_hide_other_problems_in_their_production() {
+        let extra = [('^', TerminalRole::Custom("Pow".to_string()))];
+        assert_eq!(
+            warnings("E -> (E^B\nE -> E^B\nE -> B\nB -> 1", &extra),
+            [
+                "r1 `E -> (E^B`: role Pow is not defined under Custom Roles",
+                "r1 `E -> (E^B`: '(' (LParen) has no matching RParen in the same production",
+                "r2 `E -> E^B`: role Pow is not defined under Custom Roles",
+                "terminal '^': role Pow is not defined under Custom Roles",
+            ]
+        );
+        assert_eq!(
+            warnings("E -> E^BB\nE -> B\nB -> 1", &extra),
+            [
+                "r1 `E -> E^BB`: role Pow is not defined under Custom Roles",
+                "r1 `E -> E^BB`: operands 'B' and 'B' sit side by side with no operator between them",
+                "terminal '^': role Pow is not defined under Custom Roles",
+            ]
+        );
+    }
+
+    #[test]
+    fn stray_and_undefined_roles_point_at_their_terminal() {
+        let extra = [
+            ('x', TerminalRole::Mul),
+            ('y', TerminalRole::Token),
+            ('^', TerminalRole::Custom("Pow".to_string())),
+        ];
+        assert_eq!(
+            warnings("E -> E^B\nE -> B\nB -> 1", &extra),
+            [
+                "r1 `E -> E^B`: role Pow is not defined under Custom Roles",
+                "terminal '^': role Pow is not defined under Custom Roles",
+                "terminal 'x': has role Mul but the grammar never uses it",
+            ]
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use std::fmt;
+
+/// 終端記号に割り当てる役割。演算子の中身（表記・項数・優先順位・意味）は `RoleRegistry` が持つ。
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TerminalRole {
+    /// 算術の意味を持たない終端記号。評価式は作れない。
+    Token,
+    /// 整数。値は入力上の綴り（lexeme）から読む。
+    Num,
+    /// 変数。値は Generator ページの Variables から引く。
+    Ident,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    LParen,
+    RParen,
+    Ignore,
+    /// `RoleRegistry::define` で登録した役割の名前。
+    Custom(String),
+}
+
+impl TerminalRole {
+    pub const BUILTIN: [TerminalRole; 11] = [
+        Self::Token,
+        Self::Num,
+        Self::Ident,
+        Self::Add,
+        Self::Sub,
+        Self::Mul,
+        Self::Div,
+        Self::Mod,
+        Self::LParen,
+        Self::RParen,
+        Self::Ignore,
+    ];
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Token => "Token",
+            Self::Num => "Num",
+            Self::Ident => "Ident",
+            Self::Add => "Add",
+            Self::Sub => "Sub",
+            Self::Mul => "Mul",
+            Self::Div => "Div",
+            Self::Mod => "Mod",
+            Self::LParen => "LParen",
+            Self::RParen => "RParen",
+            Self::Ignore => "Ignore",
+            Self::Custom(name) => name,
+        }
+    }
+
+    /// 名前から役割を引く。組み込みにない名前は `Custom` になる。
+    /// 古いワークスペースの `L_paren` / `R_paren` はここでだけ読み替える。
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "L_paren" => return Self::LParen,
+            "R_paren" => return Self::RParen,
+            _ => {}
+        }
+        Self::BUILTIN
+            .into_iter()
+            .find(|role| role.name() == name)
+            .unwrap_or_else(|| Self::Custom(name.to_string()))
+    }
+
+    /// 新しく現れた終端記号に最初に当てる役割。
+    /// 数字の終端記号は Num になるが、`lexer::lex` は数字の並びを 1 つにまとめず 1 文字ずつその終端記号として読む。
+    pub fn default_for(symbol: char) -> Self {
+        match symbol {
+            '+' => Self::Add,
+            '-' => Self::Sub,
+            '*' => Self::Mul,
+            '/' => Self::Div,
+            '%' => Self::Mod,
+            '(' | '<' | '[' | '{' => Self::LParen,
+            ')' | '>' | ']' | '}' => Self::RParen,
+            '0'..='9' => Self::Num,
+            _ => Self::Token,
+        }
+    }
+}
+
+impl fmt::Display for TerminalRole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Unary,
+    Binary,
+}
+
+impl Arity {
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Unary => "unary",
+            Self::Binary => "binary",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        [Self::Unary, Self::Binary].into_iter().find(|arity| arity.key() == key)
+    }
+}
+
+/// その場での評価に使う i64 上の演算。役割を増やすときはここから選ぶ。
+/// 独自の役割が選べるのはこの 10 個だけで、ほかの役割を組み合わせて定義することはできない。
+/// Xor や Shl のような新しい意味を足すには、ここと評価器・VM・各言語の書き出しに手を入れる。
+/// And / Or / Not は 0 を偽、それ以外を真として 0 か 1 を返す。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    And,
+    Or,
+    Neg,
+    Not,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 10] = [
+        Self::Add,
+        Self::Sub,
+        Self::Mul,
+        Self::Div,
+        Self::Mod,
+        Self::Pow,
+        Self::And,
+        Self::Or,
+        Self::Neg,
+        Self::Not,
+    ];
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Mul => "mul",
+            Self::Div => "div",
+            Self::Mod => "mod",
+            Self::Pow => "pow",
+            Self::And => "and",
+            Self::Or => "or",
+            Self::Neg => "neg",
+            Self::Not => "not",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|operation| operation.key() == key)
+    }
+
+    pub fn arity(self) -> Arity {
+        match self {
+            Self::Neg | Self::Not => Arity::Unary,
+            _ => Arity::Binary,
+        }
+    }
+}
+
+/// 演算子の役割の定義。`evaluation` は評価式での書き方で、`{0}` と `{1}` に被演算子が入る。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoleDefinition {
+    pub name: String,
+    /// 再構成したソースでの表記。空なら終端記号そのもの。
+    pub source: String,
+    /// 生成するプログラムの評価式での書き方。いまは Rust の式として書く。
+    pub evaluation: String,
+    pub arity: Arity,
+    /// 大きいほど強く結合する。組み込みは + - が 1、* / % が 2。
+    pub precedence: u8,
+    pub operation: Operation,
+}
+
+impl RoleDefinition {
+    fn builtin(name: &str, symbol: char, precedence: u8, operation: Operation) -> Self {
+        Self {
+            name: name.to_string(),
+            source: String::new(),
+            evaluation: format!("{{0}}{}{{1}}", symbol),
+            arity: Arity::Binary,
+            precedence,
+            operation,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoleError {
+    /// 組み込みの役割名、または登録済みの名前。
+    NameTaken(String),
+    EmptyName,
+    /// 宣言した項数と演算の項数が合わない。
+    ArityMismatch { name: String, arity: Arity, operation: Operation },
+    /// `evaluation` の書き方に必要な `{0}` / `{1}` がない、または余分にある。
+    BadTemplate { name: String, template: String },
+}
+
+impl fmt::Display for RoleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NameTaken(name) => write!(f, "role `{}` already exists", name),
+            Self::EmptyName => write!(f, "role name must not be empty"),
+            Self::ArityMismatch { name, arity, operation } => write!(
+                f,
+                "role `{}` is declared {} but `{}` is {}",
+                name,
+                arity.key(),
+                operation.key(),
+                operation.arity().key()
+            ),
+            Self::BadTemplate { name, template } => write!(
+                f,
+                "role `{}`: evaluation template `{}` must use {{0}} (and {{1}} for binary roles only)",
+                name, template
+            ),
+        }
+    }
+}
+
+/// 演算子の役割の一覧。組み込みの Add〜Mod に、ユーザーが定義した役割を足していく。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoleRegistry {
+    builtin: Vec<RoleDefinition>,
+    custom: Vec<RoleDefinition>,
+}
+
+impl Default for RoleRegistry {
+    fn default() -> Self {
+        Self {
+            builtin: vec![
+                RoleDefinition::builtin("Add", '+', 1, Operation::Add),
+                RoleDefinition::builtin("Sub", '-', 1, Operation::Sub),
+                RoleDefinition::builtin("Mul", '*', 2, Operation::Mul),
+                RoleDefinition::builtin("Div", '/', 2, Operation::Div),
+                RoleDefinition::builtin("Mod", '%', 2, Operation::Mod),
+            ],
+            custom: Vec::new(),
+        }
+    }
+}
+
+impl RoleRegistry {
+    /// 演算子として振る舞う役割の定義。Num や括弧、未登録の名前では None。
+    pub fn operator(&self, role: &TerminalRole) -> Option<&RoleDefinition> {
+        match role {
+            TerminalRole::Custom(name) => self.custom.iter().find(|definition| &definition.name == name),
+            role => self.builtin.iter().find(|definition| definition.name == role.name()),
+        }
+    }
+
+    pub fn custom(&self) -> &[RoleDefinition] {
+        &self.custom
+    }
+
+    /// ドロップダウンに並べる役割。組み込み、登録順の順。
+    pub fn roles(&self) -> Vec<TerminalRole> {
+        TerminalRole::BUILTIN
+            .into_iter()
+            .chain(self.custom.iter().map(|definition| TerminalRole::Custom(definition.name.clone())))
+            .collect()
+    }
+
+    pub fn define(&mut self, definition: RoleDefinition) -> Result<(), RoleError> {
+        let name = definition.name.trim();
+        if name.is_empty() {
+            return Err(RoleError::EmptyName);
+        }
+        if !matches!(TerminalRole::from_name(name), TerminalRole::Custom(_))
+            || self.custom.iter().any(|existing| existing.name == name)
+        {
+            return Err(RoleError::NameTaken(name.to_string()));
+        }
+        if definition.arity != definition.operation.arity() {
+            return Err(RoleError::ArityMismatch {
+                name: name.to_string(),
+                arity: definition.arity,
+                operation: definition.operation,
+            });
+        }
+        let binary = definition.arity == Arity::Binary;
+        if !definition.evaluation.contains("{0}") || definition.evaluation.contains("{1}") != binary {
+            return Err(RoleError::BadTemplate {
+                name: name.to_string(),
+                template: definition.evaluation.clone(),
+            });
+        }
+        self.custom.push(RoleDefinition { name: name.to_string(), ..definition });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.custom.retain(|definition| definition.name != name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pow() -> RoleDefinition {
+        RoleDefinition {
+            name: "Pow".to_string(),
+            source: String::new(),
+            evaluation: "i64::pow({0}, {1} as u32)".to_string(),
+            arity: Arity::Binary,
+            precedence: 3,
+            operation: Operation::Pow,
+        }
+    }
+
+    #[test]
+    fn names_round_trip_and_legacy_aliases_are_read() {
+        for role in TerminalRole::BUILTIN {
+            assert_eq!(TerminalRole::from_name(role.name()), role);
+        }
+        assert_eq!(TerminalRole::from_name("L_paren"), TerminalRole::LParen);
+        assert_eq!(TerminalRole::from_name("R_paren"), TerminalRole::RParen);
+        assert_eq!(TerminalRole::from_name("Pow"), TerminalRole::Custom("Pow".to_string()));
+    }
+
+    #[test]
+    fn custom_roles_are_checked_before_they_are_registered() {
+        let mut registry = RoleRegistry::default();
+        registry.define(pow()).unwrap();
+        let role = TerminalRole::Custom("Pow".to_string());
+
+        assert_eq!(registry.operator(&role).map(|definition| definition.precedence), Some(3));
+        assert_eq!(registry.operator(&TerminalRole::Mul).unwrap().evaluation, "{0}*{1}");
+        assert!(registry.operator(&TerminalRole::Num).is_none());
+        assert!(registry.roles().contains(&role));
+
+        assert_eq!(registry.define(pow()), Err(RoleError::NameTaken("Pow".to_string())));
+        assert_eq!(
+            registry.define(RoleDefinition { name: "Mul".to_string(), ..pow() }),
+            Err(RoleError::NameTaken("Mul".to_string()))
+        );
+        assert!(matches!(
+            registry.define(RoleDefinition { name: "Neg".to_string(), operation: Operation::Neg, ..pow() }),
+            Err(RoleError::ArityMismatch { .. })
+        ));
+        assert!(matches!(
+            registry.define(RoleDefinition { name: "Or".to_string(), evaluation: "{0} || x".to_string(), ..pow() }),
+            Err(RoleError::BadTemplate { .. })
+        ));
+
+        registry.remove("Pow");
+        assert!(registry.operator(&role).is_none());
+    }
+}
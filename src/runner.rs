@@ -0,0 +1,476 @@
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// 生成プログラムを走らせるときの上限。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunLimits {
+    pub compile_timeout: Duration,
+    pub run_timeout: Duration,
+    /// stdout / stderr それぞれに残すバイト数。超えた分は読み捨てる。
+    pub output_cap: usize,
+}
+
+impl Default for RunLimits {
+    fn default() -> Self {
+        Self {
+            compile_timeout: Duration::from_secs(30),
+            run_timeout: Duration::from_secs(5),
+            output_cap: 64 * 1024,
+        }
+    }
+}
+
+/// 生成プログラムを走らせる処理系。コマンドは PATH から探す。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Toolchain {
+    pub program: &'static str,
+    /// ソースファイルより前に渡す引数。
+    pub args: &'static [&'static str],
+    /// ソースファイルの拡張子（`.` なし）。
+    pub extension: &'static str,
+    /// true なら `-o` でバイナリを作ってからそれを走らせ、false ならソースを直接渡して走らせる。
+    pub compiled: bool,
+}
+
+impl Toolchain {
+    pub const RUSTC: Self = Self { program: "rustc", args: &["--edition=2024"], extension: "rs", compiled: true };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Compiling,
+    Running,
+}
+
+impl Phase {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Compiling => "Compiling",
+            Self::Running => "Running",
+        }
+    }
+}
+
+/// 上限つきで読んだ出力。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Captured {
+    pub text: String,
+    pub truncated: bool,
+}
+
+impl fmt::Display for Captured {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text.trim_end())?;
+        if self.truncated {
+            write!(f, "\n<output truncated>")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// プログラムが終了した。`success` は終了コードが 0 かどうか。
+    Finished { success: bool, stdout: Captured, stderr: Captured },
+    CompileFailed { stderr: Captured },
+    TimedOut { phase: Phase, limit: Duration },
+    Cancelled,
+    /// 一時ディレクトリを作れない、処理系が見つからないなど。
+    Failed(String),
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Finished { success: true, stdout, stderr } => {
+                write!(f, "{}", stdout)?;
+                if !stderr.text.trim().is_empty() {
+                    write!(f, "\n\nstderr:\n{}", stderr)?;
+                }
+                Ok(())
+            }
+            Self::Finished { success: false, stdout, stderr } => {
+                write!(f, "Generated program failed.")?;
+                if !stdout.text.trim().is_empty() {
+                    write!(f, "\nstdout:\n{}\n", stdout)?;
+                }
+                write!(f, "\nstderr:\n{}", stderr)
+            }
+            Self::CompileFailed { stderr } => write!(f, "Compilation failed.\n{}", stderr),
+            Self::TimedOut { phase, limit } => {
+                write!(f, "{} timed out after {:.1}s and was stopped.", phase.label(), limit.as_secs_f64())
+            }
+            Self::Cancelled => write!(f, "Cancelled."),
+            Self::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+enum Event {
+    Phase(Phase),
+    Done(RunOutcome),
+}
+
+/// 生成したプログラムを別スレッドで `Toolchain` にかけて走らせるジョブ。
+/// 実行ごとに専用の一時ディレクトリを使い、終わったら消す。
+pub struct Job {
+    events: Receiver<Event>,
+    cancel: Arc<AtomicBool>,
+    phase: Phase,
+    started: Instant,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Job {
+    pub fn spawn(source: String, toolchain: Toolchain, limits: RunLimits) -> Self {
+        let (sender, events) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&cancel);
+        let worker = thread::spawn(move || {
+            let outcome = compile_and_run(&source, toolchain, limits, &flag, &sender);
+            let _ = sender.send(Event::Done(outcome));
+        });
+        Self {
+            events,
+            cancel,
+            phase: if toolchain.compiled { Phase::Compiling } else { Phase::Running },
+            started: Instant::now(),
+            worker: Some(worker),
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// 子プロセスを止めるよう頼む。結果は `poll` に `Cancelled` として届く。
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// 溜まった通知を読み、終わっていれば結果を返す。UI スレッドから毎フレーム呼んでよい。
+    pub fn poll(&mut self) -> Option<RunOutcome> {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Event::Phase(phase) => self.phase = phase,
+                Event::Done(outcome) => {
+                    if let Some(worker) = self.worker.take() {
+                        let _ = worker.join();
+                    }
+                    return Some(outcome);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        // 結果を待たずに捨てられたジョブも子プロセスを残さない。
+        self.cancel();
+    }
+}
+
+// 実行ごとの一時ディレクトリ。スコープを抜けると中身ごと消す。
+struct RunDir(PathBuf);
+
+impl RunDir {
+    fn create() -> std::io::Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "lr0-parser-run-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self(path))
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for RunDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+enum Stop {
+    TimedOut,
+    Cancelled,
+}
+
+fn compile_and_run(
+    source: &str,
+    toolchain: Toolchain,
+    limits: RunLimits,
+    cancel: &AtomicBool,
+    events: &Sender<Event>,
+) -> RunOutcome {
+    let dir = match RunDir::create() {
+        Ok(dir) => dir,
+        Err(error) => return RunOutcome::Failed(format!("Failed to create a build directory: {}", error)),
+    };
+    let source_path = dir.path().join(format!("generated_main.{}", toolchain.extension));
+    if let Err(error) = std::fs::write(&source_path, source) {
+        return RunOutcome::Failed(format!("Failed to write generated source: {}", error));
+    }
+
+    let mut program = if toolchain.compiled {
+        let binary_path = dir
+            .path()
+            .join(format!("generated_program{}", std::env::consts::EXE_SUFFIX));
+        let mut compiler = Command::new(toolchain.program);
+        compiler.args(toolchain.args).arg(&source_path).arg("-o").arg(&binary_path);
+        let (status, _, stderr) = match supervise(compiler, limits.compile_timeout, limits.output_cap, cancel) {
+            Ok(Ok(result)) => result,
+            Ok(Err(Stop::TimedOut)) => {
+                return RunOutcome::TimedOut { phase: Phase::Compiling, limit: limits.compile_timeout };
+            }
+            Ok(Err(Stop::Cancelled)) => return RunOutcome::Cancelled,
+            Err(error) => {
+                return RunOutcome::Failed(format!("{} is not available: {}", toolchain.program, error));
+            }
+        };
+        if !status.success() {
+            return RunOutcome::CompileFailed { stderr };
+        }
+        let _ = events.send(Event::Phase(Phase::Running));
+        Command::new(&binary_path)
+    } else {
+        let mut interpreter = Command::new(toolchain.program);
+        interpreter.args(toolchain.args).arg(&source_path);
+        interpreter
+    };
+
+    program.current_dir(dir.path());
+    match supervise(program, limits.run_timeout, limits.output_cap, cancel) {
+        Ok(Ok((status, stdout, stderr))) => {
+            RunOutcome::Finished { success: status.success(), stdout, stderr }
+        }
+        Ok(Err(Stop::TimedOut)) => {
+            RunOutcome::TimedOut { phase: Phase::Running, limit: limits.run_timeout }
+        }
+        Ok(Err(Stop::Cancelled)) => RunOutcome::Cancelled,
+        Err(error) => RunOutcome::Failed(format!("Failed to run the generated program: {}", error)),
+    }
+}
+
+// 出力を読むスレッドを子の終了後に待つ上限。子が起動したプロセスがパイプを握ったままでも戻れるようにする。
+const READER_GRACE: Duration = Duration::from_secs(1);
+
+// 子プロセスを起動し、終了・期限切れ・取り消しのいずれかまで見張る。
+// 子は自分のプロセスグループで走らせ、止めたときも終わったときもグループごと kill して、孫プロセスを残さない。
+fn supervise(
+    mut command: Command,
+    timeout: Duration,
+    cap: usize,
+    cancel: &AtomicBool,
+) -> std::io::Result<Result<(ExitStatus, Captured, Captured), Stop>> {
+    command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command.spawn()?;
+    let stdout = capture(child.stdout.take(), cap);
+    let stderr = capture(child.stderr.take(), cap);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        let stop = if cancel.load(Ordering::Relaxed) {
+            Some(Stop::Cancelled)
+        } else if Instant::now() >= deadline {
+            Some(Stop::TimedOut)
+        } else {
+            None
+        };
+        if let Some(stop) = stop {
+            kill(&mut child);
+            return Ok(Err(stop));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    kill_group(&child);
+    let grace = Instant::now() + READER_GRACE;
+    let collect = |reader: Receiver<Captured>| {
+        // 期限までに読み終わらなければ、読めた分は諦めて打ち切りとして扱う。
+        let remaining = grace.saturating_duration_since(Instant::now());
+        reader.recv_timeout(remaining).unwrap_or(Captured { text: String::new(), truncated: true })
+    };
+    Ok(Ok((status, collect(stdout), collect(stderr))))
+}
+
+fn kill(child: &mut Child) {
+    kill_group(child);
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+// `child` のプロセスグループ全体に SIGKILL を送る。libc に頼らず `kill` コマンドを使う。
+#[cfg(unix)]
+fn kill_group(child: &Child) {
+    let _ = Command::new("kill")
+        .args(["-KILL", "--", &format!("-{}", child.id())])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+#[cfg(not(unix))]
+fn kill_group(_child: &Child) {}
+
+// パイプを最後まで読み、先頭の `cap` バイトだけを残す。読み続けないと子が書き込みで詰まる。
+fn capture(pipe: Option<impl Read + Send + 'static>, cap: usize) -> Receiver<Captured> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let Some(mut pipe) = pipe else {
+            let _ = sender.send(Captured::default());
+            return;
+        };
+        let mut kept = Vec::new();
+        let mut truncated = false;
+        let mut buffer = [0u8; 8192];
+        while let Ok(read) = pipe.read(&mut buffer) {
+            if read == 0 {
+                break;
+            }
+            let room = cap.saturating_sub(kept.len());
+            kept.extend_from_slice(&buffer[..read.min(room)]);
+            truncated |= read > room;
+        }
+        let _ = sender.send(Captured { text: String::from_utf8_lossy(&kept).into_owned(), truncated });
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Toolchain {
+        const SH: Self = Self { program: "sh", args: &[], extension: "sh", compiled: false };
+
+        // 処理系がなければテストを飛ばすのに使う。
+        pub(crate) fn available(&self) -> bool {
+            Command::new(self.program).arg("--version").output().is_ok()
+        }
+    }
+
+    impl Job {
+        pub(crate) fn wait(mut self) -> RunOutcome {
+            loop {
+                match self.events.recv() {
+                    Ok(Event::Phase(phase)) => self.phase = phase,
+                    Ok(Event::Done(outcome)) => return outcome,
+                    Err(_) => return RunOutcome::Failed("runner thread stopped unexpectedly".to_string()),
+                }
+            }
+        }
+    }
+
+    fn limits(run_timeout: Duration) -> RunLimits {
+        RunLimits { run_timeout, output_cap: 1024, ..RunLimits::default() }
+    }
+
+    #[test]
+    fn programs_run_in_their_own_directory() {
+        if !Toolchain::RUSTC.available() {
+            return;
+        }
+        let source = "fn main() { println!(\"{}\", std::env::current_dir().unwrap().display()); }";
+        let outcome = Job::spawn(source.to_string(), Toolchain::RUSTC, limits(Duration::from_secs(5))).wait();
+
+        let RunOutcome::Finished { success: true, stdout, .. } = outcome else {
+            panic!("{:?}", outcome);
+        };
+        let dir = PathBuf::from(stdout.text.trim());
+        assert!(dir.file_name().unwrap().to_string_lossy().starts_with("lr0-parser-run-"));
+        assert!(!dir.exists(), "the run directory is removed afterwards");
+    }
+
+    #[test]
+    fn infinite_loops_time_out_and_output_is_capped() {
+        if !Toolchain::RUSTC.available() {
+            return;
+        }
+        let source = "fn main() { loop { println!(\"spinning\"); } }";
+        let outcome = Job::spawn(source.to_string(), Toolchain::RUSTC, limits(Duration::from_millis(300))).wait();
+        assert_eq!(
+            outcome,
+            RunOutcome::TimedOut { phase: Phase::Running, limit: Duration::from_millis(300) }
+        );
+
+        let source = "fn main() { for _ in 0..10_000 { println!(\"line\"); } }";
+        let outcome = Job::spawn(source.to_string(), Toolchain::RUSTC, limits(Duration::from_secs(5))).wait();
+        let RunOutcome::Finished { stdout, .. } = outcome else {
+            panic!("{:?}", outcome);
+        };
+        assert_eq!(stdout.text.len(), 1024);
+        assert!(stdout.truncated);
+    }
+
+    #[test]
+    fn cancelled_jobs_stop_their_process() {
+        if !Toolchain::RUSTC.available() {
+            return;
+        }
+        let source = "fn main() { loop {} }".to_string();
+        let mut job = Job::spawn(source, Toolchain::RUSTC, limits(Duration::from_secs(60)));
+        while job.poll().is_none() && job.phase() != Phase::Running {
+            thread::sleep(Duration::from_millis(10));
+        }
+        job.cancel();
+        assert_eq!(job.wait(), RunOutcome::Cancelled);
+    }
+
+    #[test]
+    fn background_processes_do_not_outlive_the_run() {
+        if !Toolchain::SH.available() {
+            return;
+        }
+        // 孫プロセスが stdout を握ったままでも、終了後すぐに結果が返る。
+        let started = Instant::now();
+        let source = "(sleep 5; echo late) &\necho early\n".to_string();
+        let outcome = Job::spawn(source, Toolchain::SH, limits(Duration::from_secs(5))).wait();
+        let RunOutcome::Finished { success: true, stdout, .. } = outcome else {
+            panic!("{:?}", outcome);
+        };
+        assert_eq!(stdout.text.trim(), "early");
+        assert!(started.elapsed() < Duration::from_secs(3), "{:?}", started.elapsed());
+
+        // 期限切れで止めたとき、孫プロセスもグループごと止まる。
+        let marker = std::env::temp_dir().join(format!("lr0-parser-orphan-{}", std::process::id()));
+        let source = format!("(sleep 1; touch '{}') &\nwait\n", marker.display());
+        let outcome = Job::spawn(source, Toolchain::SH, limits(Duration::from_millis(200))).wait();
+        assert!(matches!(outcome, RunOutcome::TimedOut { .. }), "{:?}", outcome);
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!marker.exists(), "the background process was left running");
+    }
+
+    #[test]
+    fn compile_errors_are_reported() {
+        if !Toolchain::RUSTC.available() {
+            return;
+        }
+        let source = "fn main() { let }".to_string();
+        let outcome = Job::spawn(source, Toolchain::RUSTC, RunLimits::default()).wait();
+        assert!(matches!(outcome, RunOutcome::CompileFailed { .. }), "{:?}", outcome);
+    }
+}
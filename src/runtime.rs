@@ -1,15 +1,15 @@
-use crate::ast::AstNode;
+use crate::ast::{AstNode, Span};
 use crate::grammar::Symbol;
-use crate::lr::{Action, CompiledParser, InternalState};
+use crate::lr::{Action, InternalState, LrTable};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum StepAction {
     Shift { terminal: char, to_state: usize },
-    Reduce { rule: String, pop_count: usize },
+    Reduce { production: usize, rule: String, pop_count: usize },
     Accept,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ParseStep {
     pub action: StepAction,
     pub from_state: usize,
@@ -26,7 +26,7 @@ pub struct ParserResult {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum StepResult {
-    Continue(ParserState),
+    Continue,
     Accept(ParserResult),
 }
 
@@ -40,11 +40,13 @@ pub enum RuntimeError {
     MissingAst,
 }
 
+/// 駆動ループの状態。入力は前から削らず、`position` を読み位置のカーソルにする。
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ParserState {
     pub state_stack: Vec<InternalState>,
     pub ast_stack: Vec<AstNode>,
-    pub remaining_input: Vec<Symbol>,
+    input: Vec<Symbol>,
+    pub position: usize,
 }
 
 impl ParserState {
@@ -52,196 +54,331 @@ impl ParserState {
         Self {
             state_stack: vec![start_state],
             ast_stack: Vec::new(),
-            remaining_input: input,
+            input,
+            position: 0,
         }
     }
 
-    fn current_state(&self) -> Result<InternalState, RuntimeError> {
+    /// まだ読んでいない入力（先頭が先読み記号）。
+    pub fn remaining_input(&self) -> &[Symbol] {
+        self.input.get(self.position..).unwrap_or(&[])
+    }
+
+    pub fn current_state(&self) -> Result<InternalState, RuntimeError> {
         self.state_stack
             .last()
             .copied()
             .ok_or(RuntimeError::EmptyStateStack)
     }
+
+    fn lookahead(&self) -> Result<char, RuntimeError> {
+        match self.remaining_input().first() {
+            Some(Symbol::Terminal(terminal)) => Ok(terminal.0),
+            _ => Err(RuntimeError::ExpectedTerminalInput),
+        }
+    }
 }
 
-pub fn step(
-    machine: &CompiledParser,
-    state: ParserState,
+/// 1 手進める。AST のノード以外は確保せず、エラーのときは `state` を書き換えない。
+pub fn step<T: LrTable + ?Sized>(
+    machine: &T,
+    state: &mut ParserState,
 ) -> Result<StepResult, RuntimeError> {
     let current_state = state.current_state()?;
-    let next_symbol = state
-        .remaining_input
-        .first()
-        .cloned()
-        .ok_or(RuntimeError::ExpectedTerminalInput)?;
-
-    let Symbol::Terminal(terminal) = next_symbol.clone() else {
-        return Err(RuntimeError::ExpectedTerminalInput);
-    };
-
+    let lookahead = state.lookahead()?;
     let action = machine
-        .action(current_state, terminal)
+        .action(current_state, crate::grammar::Terminal(lookahead))
         .ok_or(RuntimeError::InvalidAction)?;
 
     match action {
         Action::Shift(next_state) => {
-            let mut next = state;
-            next.state_stack.push(next_state);
-            next.ast_stack.push(AstNode::Terminal(terminal.0));
-            next.remaining_input.remove(0);
-            Ok(StepResult::Continue(next))
+            state.state_stack.push(next_state);
+            state.ast_stack.push(
+                AstNode::terminal(lookahead)
+                    .with_span(Span::new(state.position, state.position + 1)),
+            );
+            state.position += 1;
+            Ok(StepResult::Continue)
         }
         Action::Reduce(production_id) => {
             let production = machine
                 .production(production_id)
-                .ok_or(RuntimeError::InvalidReduce)?
-                .clone();
+                .ok_or(RuntimeError::InvalidReduce)?;
 
             let pop_count = production.right.len();
-            if state.state_stack.len() < pop_count {
+            if state.state_stack.len() <= pop_count {
                 return Err(RuntimeError::InvalidReduce);
             }
             if state.ast_stack.len() < pop_count {
                 return Err(RuntimeError::MissingAst);
             }
 
-            let mut next = state;
-            let children = next
-                .ast_stack
-                .drain(next.ast_stack.len() - pop_count..)
-                .collect();
-            next.state_stack
-                .truncate(next.state_stack.len() - pop_count);
-
-            let goto_from = next.current_state()?;
+            let goto_from = state.state_stack[state.state_stack.len() - pop_count - 1];
             let goto_state = machine
                 .goto(goto_from, production.left)
                 .ok_or(RuntimeError::MissingGoto)?;
 
-            next.ast_stack
-                .push(AstNode::NonTerminal(production.left.0, children));
-            next.state_stack.push(goto_state);
-
-            Ok(StepResult::Continue(next))
+            let children: Vec<AstNode> = state
+                .ast_stack
+                .drain(state.ast_stack.len() - pop_count..)
+                .collect();
+            let span = covering_span(&children)
+                .unwrap_or(Span::new(state.position, state.position));
+            state
+                .state_stack
+                .truncate(state.state_stack.len() - pop_count);
+
+            state.ast_stack.push(
+                AstNode::non_terminal(production.left.0, children)
+                    .with_span(span)
+                    .with_production(production_id),
+            );
+            state.state_stack.push(goto_state);
+
+            Ok(StepResult::Continue)
         }
         Action::Accept => {
-            let ast = state
-                .ast_stack
-                .last()
-                .cloned()
-                .ok_or(RuntimeError::MissingAst)?;
+            let ast = state.ast_stack.pop().ok_or(RuntimeError::MissingAst)?;
             Ok(StepResult::Accept(ParserResult { ast }))
         }
     }
 }
 
-pub fn run(
-    machine: &CompiledParser,
+fn covering_span(children: &[AstNode]) -> Option<Span> {
+    let start = children.first()?.span()?.start;
+    let end = children.last()?.span()?.end;
+    Some(Span::new(start, end))
+}
+
+pub fn run<T: LrTable + ?Sized>(
+    machine: &T,
     input: &[Symbol],
 ) -> Result<ParserResult, RuntimeError> {
     let mut state = ParserState::new(input.to_vec(), machine.start_state());
+    run_state(machine, &mut state)
+}
 
+/// `state` から受理かエラーまで進める。エラーのとき `state` は止まった時点のまま残る。
+pub fn run_state<T: LrTable + ?Sized>(
+    machine: &T,
+    state: &mut ParserState,
+) -> Result<ParserResult, RuntimeError> {
     loop {
-        match step(machine, state)? {
-            StepResult::Continue(next_state) => state = next_state,
-            StepResult::Accept(result) => return Ok(result),
+        if let StepResult::Accept(result) = step(machine, state)? {
+            return Ok(result);
         }
     }
 }
 
-pub fn build_trace(
-    machine: &CompiledParser,
-    input: &[Symbol],
-) -> Result<Vec<ParseStep>, RuntimeError> {
-    let mut steps = Vec::new();
-    let mut state = ParserState::new(input.to_vec(), machine.start_state());
+/// 1 手ぶんの記録。スタックの写しは持たず、`Trace::steps` で必要になったときに再生して作る。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEvent {
+    pub from_state: InternalState,
+    pub lookahead: char,
+    pub action: Action,
+}
 
-    loop {
-        let from_state = state.current_state()?;
-        let next_sym = state
-            .remaining_input
-            .first()
-            .cloned()
-            .ok_or(RuntimeError::ExpectedTerminalInput)?;
-        let Symbol::Terminal(terminal) = next_sym else {
-            return Err(RuntimeError::ExpectedTerminalInput);
-        };
-        let lookahead = terminal.0;
+/// 受理までの手順の記録。記録は 1 手あたり定数の大きさで、`ParseStep` の写しは遅延して作る。
+pub struct Trace<'m, T: LrTable + ?Sized> {
+    machine: &'m T,
+    input: Vec<Symbol>,
+    events: Vec<TraceEvent>,
+}
 
-        let action = machine
-            .action(from_state, terminal)
-            .ok_or(RuntimeError::InvalidAction)?;
+impl<'m, T: LrTable + ?Sized> Trace<'m, T> {
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
 
-        let step_action = match &action {
-            Action::Shift(to) => StepAction::Shift { terminal: lookahead, to_state: *to },
-            Action::Reduce(prod_id) => {
-                let prod = machine
-                    .production(*prod_id)
-                    .ok_or(RuntimeError::InvalidReduce)?;
-                let rhs: String = prod
-                    .right
-                    .iter()
-                    .map(|s| match s {
-                        Symbol::Terminal(t) => t.0.to_string(),
-                        Symbol::NonTerminal(nt) => nt.0.to_string(),
-                    })
-                    .collect();
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    /// 先頭から再生しながら各手の `ParseStep` を作る。写しの費用は取り出した分だけかかる。
+    pub fn steps(&self) -> TraceSteps<'_, 'm, T> {
+        self.replay(true)
+    }
+
+    /// `ast_stack` を空にした `steps`。木の写しを作らないので、`format_step` のように
+    /// 木を見ない用途では 1 手あたりの費用がスタックと残り入力の長さだけになる。
+    pub fn steps_without_trees(&self) -> TraceSteps<'_, 'm, T> {
+        self.replay(false)
+    }
+
+    fn replay(&self, with_trees: bool) -> TraceSteps<'_, 'm, T> {
+        TraceSteps {
+            trace: self,
+            state: ParserState::new(self.input.clone(), self.machine.start_state()),
+            next: 0,
+            with_trees,
+        }
+    }
+}
+
+pub struct TraceSteps<'t, 'm, T: LrTable + ?Sized> {
+    trace: &'t Trace<'m, T>,
+    state: ParserState,
+    next: usize,
+    with_trees: bool,
+}
+
+impl<T: LrTable + ?Sized> Iterator for TraceSteps<'_, '_, T> {
+    type Item = ParseStep;
+
+    fn next(&mut self) -> Option<ParseStep> {
+        let event = *self.trace.events.get(self.next)?;
+        self.next += 1;
+
+        let action = match event.action {
+            Action::Shift(to_state) => StepAction::Shift { terminal: event.lookahead, to_state },
+            Action::Reduce(production) => {
+                // 記録できた手なので生成規則は必ずある。
+                let rule = self.trace.machine.production(production)?;
                 StepAction::Reduce {
-                    rule: format!("{} -> {}", prod.left.0, rhs),
-                    pop_count: prod.right.len(),
+                    production,
+                    rule: rule.to_string(),
+                    pop_count: rule.right.len(),
                 }
             }
             Action::Accept => StepAction::Accept,
         };
 
-        let step_result = step(machine, state)?;
-
-        match step_result {
-            StepResult::Continue(next) => {
-                let remaining: Vec<char> = next
-                    .remaining_input
+        let step_result = step(self.trace.machine, &mut self.state).ok()?;
+        let snapshot = match step_result {
+            StepResult::Continue => ParseStep {
+                action,
+                from_state: event.from_state,
+                lookahead: event.lookahead,
+                state_stack: self.state.state_stack.clone(),
+                remaining_input: self
+                    .state
+                    .remaining_input()
                     .iter()
-                    .filter_map(|s| {
-                        if let Symbol::Terminal(t) = s {
-                            Some(t.0)
-                        } else {
-                            None
-                        }
+                    .filter_map(|symbol| match symbol {
+                        Symbol::Terminal(terminal) => Some(terminal.0),
+                        Symbol::NonTerminal(_) => None,
                     })
-                    .collect();
-                steps.push(ParseStep {
-                    action: step_action,
-                    from_state,
-                    lookahead,
-                    state_stack: next.state_stack.clone(),
-                    remaining_input: remaining,
-                    ast_stack: next.ast_stack.clone(),
-                });
-                state = next;
-            }
-            StepResult::Accept(result) => {
-                steps.push(ParseStep {
-                    action: step_action,
-                    from_state,
-                    lookahead,
-                    state_stack: vec![],
-                    remaining_input: vec![],
-                    ast_stack: vec![result.ast.clone()],
-                });
-                break;
-            }
+                    .collect(),
+                ast_stack: if self.with_trees { self.state.ast_stack.clone() } else { Vec::new() },
+            },
+            StepResult::Accept(result) => ParseStep {
+                action,
+                from_state: event.from_state,
+                lookahead: event.lookahead,
+                state_stack: vec![],
+                remaining_input: vec![],
+                ast_stack: if self.with_trees { vec![result.ast] } else { Vec::new() },
+            },
+        };
+        Some(snapshot)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.trace.events.len() - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+/// 入力を受理まで走らせ、各手を記録する。スタックの写しはまだ作らない。
+pub fn trace<'m, T: LrTable + ?Sized>(
+    machine: &'m T,
+    input: &[Symbol],
+) -> Result<Trace<'m, T>, RuntimeError> {
+    let mut state = ParserState::new(input.to_vec(), machine.start_state());
+    let mut events = Vec::new();
+
+    loop {
+        let from_state = state.current_state()?;
+        let lookahead = state.lookahead()?;
+        let action = machine
+            .action(from_state, crate::grammar::Terminal(lookahead))
+            .ok_or(RuntimeError::InvalidAction)?;
+
+        let step_result = step(machine, &mut state)?;
+        events.push(TraceEvent { from_state, lookahead, action });
+        if let StepResult::Accept(_) = step_result {
+            break;
         }
     }
 
-    Ok(steps)
+    Ok(Trace { machine, input: input.to_vec(), events })
+}
+
+/// すべての手の `ParseStep` を作る。GUI のように全手を行き来する用途向け。
+pub fn build_trace<T: LrTable + ?Sized>(
+    machine: &T,
+    input: &[Symbol],
+) -> Result<Vec<ParseStep>, RuntimeError> {
+    Ok(trace(machine, input)?.steps().collect())
+}
+
+/// トレースを1ステップ3行（判断・適用後・状態機械の辺）のテキストに整形する。
+pub fn format_trace(trace: &[ParseStep]) -> String {
+    trace
+        .iter()
+        .enumerate()
+        .map(|(i, step)| format_step(i + 1, step))
+        .collect()
+}
+
+/// `number` 手目の `step` を `format_trace` と同じ3行と空行にする。
+/// `Trace::steps()` から1手ずつ取り出して書き出せば、全手の写しを溜めずに済む。
+pub fn format_step(number: usize, step: &ParseStep) -> String {
+    let action_str = match &step.action {
+        StepAction::Shift { terminal, to_state } =>
+            format!("SHIFT '{}' -> state {}", terminal, to_state),
+        StepAction::Reduce { production, rule, pop_count } =>
+            format!("REDUCE r{} {} (pop {})", production, rule, pop_count),
+        StepAction::Accept => "ACCEPT".to_string(),
+    };
+
+    let sm_edge = match &step.action {
+        StepAction::Shift { terminal, to_state } =>
+            format!("sm_edge: {} --'{}'-> {}", step.from_state, terminal, to_state),
+        StepAction::Reduce { rule, .. } =>
+            format!("sm_edge: <none; reduce {}>", rule),
+        StepAction::Accept =>
+            format!("sm_edge: <none; accept at {}>", step.from_state),
+    };
+
+    let stack: String = step.state_stack.iter()
+        .map(|s| s.to_string()).collect::<Vec<_>>().join(",");
+    let remaining: String = step.remaining_input.iter().collect();
+
+    let mut output = String::new();
+    output.push_str(&format!("step {:>2}: [pre]  from_state={}  lookahead='{}'  action={}\n",
+        number, step.from_state, step.lookahead, action_str));
+    output.push_str(&format!("         [post] stack=[{}]  remaining='{}'\n", stack, remaining));
+    output.push_str(&format!("         [sm]   {}\n", sm_edge));
+    output.push('\n');
+    output
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::grammar::{Symbol, Terminal, parse_grammar_text};
+    use crate::grammar::{Symbol, Terminal, parse_grammar_text, parse_input_text};
     use crate::lr::compile;
 
+    fn leaf(symbol: char, at: usize) -> AstNode {
+        AstNode::terminal(symbol).with_span(Span::new(at, at + 1))
+    }
+
+    fn node(
+        symbol: char,
+        production: usize,
+        (start, end): (usize, usize),
+        children: Vec<AstNode>,
+    ) -> AstNode {
+        AstNode::non_terminal(symbol, children)
+            .with_span(Span::new(start, end))
+            .with_production(production)
+    }
+
     #[test]
     fn dump_trace_for_debug() {
         let grammar = parse_grammar_text("E -> E*B\nE -> E+B\nE -> B\nB -> 0\nB -> 1").unwrap();
@@ -263,34 +400,7 @@ mod tests {
         println!("steps:   {}", trace.len());
         println!();
 
-        for (i, step) in trace.iter().enumerate() {
-            let action_str = match &step.action {
-                StepAction::Shift { terminal, to_state } =>
-                    format!("SHIFT '{}' -> state {}", terminal, to_state),
-                StepAction::Reduce { rule, pop_count } =>
-                    format!("REDUCE {} (pop {})", rule, pop_count),
-                StepAction::Accept => "ACCEPT".to_string(),
-            };
-
-            let sm_edge = match &step.action {
-                StepAction::Shift { terminal, to_state } =>
-                    format!("sm_edge: {} --'{}'-> {}", step.from_state, terminal, to_state),
-                StepAction::Reduce { rule, .. } =>
-                    format!("sm_edge: <none; reduce {}>", rule),
-                StepAction::Accept =>
-                    format!("sm_edge: <none; accept at {}>", step.from_state),
-            };
-
-            let stack: String = step.state_stack.iter()
-                .map(|s| s.to_string()).collect::<Vec<_>>().join(",");
-            let remaining: String = step.remaining_input.iter().collect();
-
-            println!("step {:>2}: [pre]  from_state={}  lookahead='{}'  action={}",
-                i + 1, step.from_state, step.lookahead, action_str);
-            println!("         [post] stack=[{}]  remaining='{}'", stack, remaining);
-            println!("         [sm]   {}", sm_edge);
-            println!();
-        }
+        print!("{}", format_trace(&trace));
         println!("=== END TRACE DUMP ===");
     }
 
@@ -309,17 +419,96 @@ mod tests {
 
         assert_eq!(
             result.ast,
-            AstNode::NonTerminal(
+            node(
                 'E',
+                1,
+                (0, 3),
                 vec![
-                    AstNode::NonTerminal(
-                        'E',
-                        vec![AstNode::NonTerminal('B', vec![AstNode::Terminal('1')])],
-                    ),
-                    AstNode::Terminal('+'),
-                    AstNode::NonTerminal('B', vec![AstNode::Terminal('0')]),
+                    node('E', 2, (0, 1), vec![node('B', 4, (0, 1), vec![leaf('1', 0)])]),
+                    leaf('+', 1),
+                    node('B', 3, (2, 3), vec![leaf('0', 2)]),
                 ],
             )
         );
     }
+
+    #[test]
+    fn reduce_steps_report_the_production_id() {
+        let grammar = parse_grammar_text("E -> E*B\nE -> E+B\nE -> B\nB -> 0\nB -> 1").unwrap();
+        let machine = compile(&grammar).unwrap();
+        let input = parse_input_text("1*0").unwrap();
+
+        let reduced: Vec<(usize, String)> = build_trace(&machine, &input)
+            .unwrap()
+            .into_iter()
+            .filter_map(|step| match step.action {
+                StepAction::Reduce { production, rule, .. } => Some((production, rule)),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            reduced,
+            vec![
+                (5, "B -> 1".to_string()),
+                (3, "E -> B".to_string()),
+                (4, "B -> 0".to_string()),
+                (1, "E -> E*B".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_steps_leave_the_state_untouched() {
+        let machine = compile(&parse_grammar_text("E -> E+B\nE -> B\nB -> 0\nB -> 1").unwrap()).unwrap();
+        let mut state = ParserState::new(parse_input_text("1+").unwrap(), machine.start_state());
+
+        assert_eq!(run_state(&machine, &mut state), Err(RuntimeError::InvalidAction));
+        assert_eq!(state.position, 2);
+        assert_eq!(state.remaining_input(), &[Symbol::Terminal(Terminal('$'))]);
+        assert_eq!(state.ast_stack.len(), 2);
+    }
+
+    #[test]
+    fn long_inputs_run_without_copying_the_stacks() {
+        let machine = compile(&parse_grammar_text("E -> E+B\nE -> B\nB -> 0\nB -> 1").unwrap()).unwrap();
+        let text = "1+0+".repeat(50_000) + "1";
+        let input = parse_input_text(&text).unwrap();
+
+        let recorded = trace(&machine, &input).unwrap();
+        let ast = run(&machine, &input).unwrap().ast;
+
+        // '$' 以外の各トークンを shift し、数字ごとに B と E への reduce が1回ずつ、最後に accept。
+        let digits = input.len() / 2;
+        assert_eq!(recorded.len(), (input.len() - 1) + 2 * digits + 1);
+        assert_eq!(recorded.events().last().unwrap().action, Action::Accept);
+        assert_eq!(ast.span(), Some(Span::new(0, input.len() - 1)));
+        assert_eq!(ast.depth(), digits + 2);
+        // 深い木は `dispose` などを呼ばずにそのまま drop できる。
+        drop(ast);
+
+        // 途中で止まった入力の深い部分木も、`run` と `trace` のエラーの戻りで再帰せずに捨てられる。
+        let rejected = parse_input_text(&(text + "+")).unwrap();
+        assert_eq!(run(&machine, &rejected), Err(RuntimeError::InvalidAction));
+        assert!(matches!(trace(&machine, &rejected), Err(RuntimeError::InvalidAction)));
+    }
+
+    #[test]
+    fn lazy_steps_match_the_recorded_events() {
+        let machine = compile(&parse_grammar_text("E -> E+B\nE -> B\nB -> 0\nB -> 1").unwrap()).unwrap();
+        let input = parse_input_text("1+0").unwrap();
+
+        let recorded = trace(&machine, &input).unwrap();
+        let steps: Vec<ParseStep> = recorded.steps().collect();
+
+        assert_eq!(steps.len(), recorded.len());
+        assert_eq!(steps, build_trace(&machine, &input).unwrap());
+        assert_eq!(steps[0].state_stack.len(), 2);
+        assert_eq!(steps[0].remaining_input, vec!['+', '0', '$']);
+        assert_eq!(steps.last().unwrap().ast_stack, vec![run(&machine, &input).unwrap().ast]);
+
+        let bare: Vec<ParseStep> = recorded.steps_without_trees().collect();
+        assert!(bare.iter().all(|step| step.ast_stack.is_empty()));
+        assert_eq!(format_trace(&bare), format_trace(&steps));
+    }
 }
@@ -0,0 +1,384 @@
+use std::fmt::{self, Write as _};
+
+use super::{DecodeError, MAX_NESTING};
+
+/// 最小限の JSON 値。数値は整数のみ扱い、オブジェクトはキーの挿入順を保つ。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object<K: Into<String>>(fields: impl IntoIterator<Item = (K, Json)>) -> Self {
+        Self::Object(fields.into_iter().map(|(key, value)| (key.into(), value)).collect())
+    }
+
+    pub fn string(value: impl Into<String>) -> Self {
+        Self::String(value.into())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Self::Object(fields) => fields
+                .iter()
+                .find(|(field, _)| field == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// 2 スペースインデントで整形した文字列。ゴールデンテストの差分を読みやすくする。
+    pub fn pretty(&self) -> String {
+        let mut output = String::new();
+        write_pretty(self, &mut output, 0);
+        output
+    }
+}
+
+// 深い木を符号化した値も再帰せずに捨てる。`AstNode` の drop と同じく、子を作業用のスタックに移していく。
+impl Drop for Json {
+    fn drop(&mut self) {
+        let mut pending = match self {
+            Self::Array(items) if !items.is_empty() => std::mem::take(items),
+            Self::Object(fields) if !fields.is_empty() => {
+                std::mem::take(fields).into_iter().map(|(_, value)| value).collect()
+            }
+            _ => return,
+        };
+        while let Some(mut value) = pending.pop() {
+            match &mut value {
+                Self::Array(items) => pending.append(items),
+                Self::Object(fields) => pending.extend(fields.drain(..).map(|(_, value)| value)),
+                _ => {}
+            }
+        }
+    }
+}
+
+// 書き出しの残り仕事。入れ子は再帰せず、このスタックに逆順に積んで順に片付ける。
+enum Frame<'a> {
+    Value(&'a Json, usize),
+    Key(&'a str),
+    Text(&'static str),
+    Pad(usize),
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut stack = vec![Frame::Value(self, 0)];
+        while let Some(frame) = stack.pop() {
+            match frame {
+                Frame::Text(text) => f.write_str(text)?,
+                Frame::Key(key) => {
+                    write_escaped(f, key)?;
+                    f.write_char(':')?;
+                }
+                Frame::Pad(_) => {}
+                Frame::Value(Self::Array(items), _) => {
+                    f.write_char('[')?;
+                    stack.push(Frame::Text("]"));
+                    for (index, item) in items.iter().enumerate().rev() {
+                        stack.push(Frame::Value(item, 0));
+                        if index > 0 {
+                            stack.push(Frame::Text(","));
+                        }
+                    }
+                }
+                Frame::Value(Self::Object(fields), _) => {
+                    f.write_char('{')?;
+                    stack.push(Frame::Text("}"));
+                    for (index, (key, value)) in fields.iter().enumerate().rev() {
+                        stack.push(Frame::Value(value, 0));
+                        stack.push(Frame::Key(key));
+                        if index > 0 {
+                            stack.push(Frame::Text(","));
+                        }
+                    }
+                }
+                Frame::Value(scalar, _) => write_scalar(f, scalar)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_scalar(f: &mut impl fmt::Write, value: &Json) -> fmt::Result {
+    match value {
+        Json::Null => f.write_str("null"),
+        Json::Bool(value) => write!(f, "{value}"),
+        Json::Number(value) => write!(f, "{value}"),
+        Json::String(value) => write_escaped(f, value),
+        Json::Array(_) | Json::Object(_) => unreachable!("containers are written by the caller"),
+    }
+}
+
+fn write_escaped(f: &mut impl fmt::Write, value: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in value.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+const PRETTY_WIDTH: usize = 80;
+
+fn write_pretty(value: &Json, output: &mut String, indent: usize) {
+    let mut stack = vec![Frame::Value(value, indent)];
+    while let Some(frame) = stack.pop() {
+        match frame {
+            Frame::Text(text) => output.push_str(text),
+            Frame::Key(key) => {
+                let _ = write_escaped(output, key);
+                output.push_str(": ");
+            }
+            Frame::Pad(level) => {
+                output.push('\n');
+                output.push_str(&"  ".repeat(level));
+            }
+            // 字下げが幅を使い切る深さからは1行で書く。深い木で出力が字下げだけで膨らまないように
+            Frame::Value(other, indent) if indent * 2 >= PRETTY_WIDTH => {
+                let _ = write!(output, "{other}");
+            }
+            // スカラーだけの配列（span や状態スタック）は1行に収める
+            Frame::Value(Json::Array(items), indent)
+                if items
+                    .iter()
+                    .any(|item| matches!(item, Json::Array(_) | Json::Object(_))) =>
+            {
+                output.push('[');
+                stack.push(Frame::Text("]"));
+                stack.push(Frame::Pad(indent));
+                for (index, item) in items.iter().enumerate().rev() {
+                    stack.push(Frame::Value(item, indent + 1));
+                    stack.push(Frame::Pad(indent + 1));
+                    if index > 0 {
+                        stack.push(Frame::Text(","));
+                    }
+                }
+            }
+            Frame::Value(Json::Object(fields), indent) if !fields.is_empty() => {
+                output.push('{');
+                stack.push(Frame::Text("}"));
+                stack.push(Frame::Pad(indent));
+                for (index, (key, item)) in fields.iter().enumerate().rev() {
+                    stack.push(Frame::Value(item, indent + 1));
+                    stack.push(Frame::Key(key));
+                    stack.push(Frame::Pad(indent + 1));
+                    if index > 0 {
+                        stack.push(Frame::Text(","));
+                    }
+                }
+            }
+            Frame::Value(other, _) => {
+                let _ = write!(output, "{other}");
+            }
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Result<Json, DecodeError> {
+    let mut parser = Parser {
+        chars: input.char_indices().peekable(),
+        input,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.peek() {
+        None => Ok(value),
+        Some(&(offset, _)) => Err(DecodeError::Syntax {
+            offset,
+            message: "trailing characters".to_string(),
+        }),
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    input: &'a str,
+}
+
+// 読みかけの配列とオブジェクト。オブジェクトは値を待っているキーも持つ。
+enum Open {
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>, String),
+}
+
+impl Parser<'_> {
+    fn offset(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map(|&(offset, _)| offset)
+            .unwrap_or(self.input.len())
+    }
+
+    fn error(&mut self, message: &str) -> DecodeError {
+        DecodeError::Syntax {
+            offset: self.offset(),
+            message: message.to_string(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), DecodeError> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("expected '{expected}'"))),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, DecodeError> {
+        for expected in word.chars() {
+            match self.chars.next() {
+                Some((_, c)) if c == expected => {}
+                _ => return Err(self.error(&format!("expected '{word}'"))),
+            }
+        }
+        Ok(value)
+    }
+
+    // 入れ子は再帰せず、開いている配列とオブジェクトをスタックに積んで読む。
+    fn value(&mut self) -> Result<Json, DecodeError> {
+        let mut open: Vec<Open> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let mut value = match self.chars.peek().map(|&(_, c)| c) {
+                Some('n') => self.keyword("null", Json::Null)?,
+                Some('t') => self.keyword("true", Json::Bool(true))?,
+                Some('f') => self.keyword("false", Json::Bool(false))?,
+                Some('"') => Json::String(self.string()?),
+                Some(open_char @ ('[' | '{')) => {
+                    if open.len() == MAX_NESTING {
+                        return Err(self.error(&format!("nested deeper than {MAX_NESTING} levels")));
+                    }
+                    self.chars.next();
+                    self.skip_whitespace();
+                    if open_char == '[' {
+                        if self.chars.next_if(|&(_, c)| c == ']').is_some() {
+                            Json::Array(Vec::new())
+                        } else {
+                            open.push(Open::Array(Vec::new()));
+                            continue;
+                        }
+                    } else if self.chars.next_if(|&(_, c)| c == '}').is_some() {
+                        Json::Object(Vec::new())
+                    } else {
+                        let key = self.key()?;
+                        open.push(Open::Object(Vec::new(), key));
+                        continue;
+                    }
+                }
+                Some(c) if c == '-' || c.is_ascii_digit() => self.number()?,
+                Some(_) => return Err(self.error("unexpected character")),
+                None => return Err(self.error("unexpected end of input")),
+            };
+
+            // 読めた値を開いている入れ子に入れ、閉じ括弧が続くあいだ外側へ戻る
+            loop {
+                let Some(top) = open.last_mut() else {
+                    return Ok(value);
+                };
+                self.skip_whitespace();
+                let closed = match top {
+                    Open::Array(items) => {
+                        items.push(value);
+                        match self.chars.next() {
+                            Some((_, ',')) => false,
+                            Some((_, ']')) => true,
+                            _ => return Err(self.error("expected ',' or ']'")),
+                        }
+                    }
+                    Open::Object(fields, key) => {
+                        fields.push((std::mem::take(key), value));
+                        match self.chars.next() {
+                            Some((_, ',')) => {
+                                *key = self.key()?;
+                                false
+                            }
+                            Some((_, '}')) => true,
+                            _ => return Err(self.error("expected ',' or '}'")),
+                        }
+                    }
+                };
+                if !closed {
+                    break;
+                }
+                value = match open.pop() {
+                    Some(Open::Array(items)) => Json::Array(items),
+                    Some(Open::Object(fields, _)) => Json::Object(fields),
+                    None => unreachable!("checked above"),
+                };
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String, DecodeError> {
+        self.skip_whitespace();
+        let key = self.string()?;
+        self.expect(':')?;
+        Ok(key)
+    }
+
+    fn number(&mut self) -> Result<Json, DecodeError> {
+        let start = self.offset();
+        let mut text = String::new();
+        while let Some((_, c)) = self.chars.next_if(|&(_, c)| c == '-' || c.is_ascii_digit()) {
+            text.push(c);
+        }
+        if matches!(self.chars.peek(), Some((_, '.' | 'e' | 'E'))) {
+            return Err(self.error("only integer numbers are supported"));
+        }
+        text.parse().map(Json::Number).map_err(|_| DecodeError::Syntax {
+            offset: start,
+            message: format!("invalid number '{text}'"),
+        })
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(value),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, '"')) => value.push('"'),
+                    Some((_, '\\')) => value.push('\\'),
+                    Some((_, '/')) => value.push('/'),
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 'r')) => value.push('\r'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, 'b')) => value.push('\u{8}'),
+                    Some((_, 'f')) => value.push('\u{c}'),
+                    Some((_, 'u')) => {
+                        let hex: String = (0..4).filter_map(|_| self.chars.next()).map(|(_, c)| c).collect();
+                        let decoded = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| self.error("invalid unicode escape"))?;
+                        value.push(decoded);
+                    }
+                    _ => return Err(self.error("invalid escape")),
+                },
+                Some((_, c)) => value.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+}
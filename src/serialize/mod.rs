@@ -0,0 +1,1092 @@
+//! パーサーの出力を JSON と S 式に書き出し、読み戻すための符号化。
+//!
+//! どちらの形式もキーや要素の順序を固定しており、同じ入力からは常に同じ文字列が得られる。
+
+pub mod json;
+pub mod sexpr;
+
+use std::str::FromStr;
+
+use crate::ast::{Attributes, AstNode, Fold, NodeInfo, Span, Token};
+use crate::grammar::{NonTerminal, Production, Symbol, Terminal};
+use crate::lr::{Action, LrItem, ParseTables, StateInfo};
+use crate::runtime::{ParseStep, StepAction};
+
+pub use json::Json;
+pub use sexpr::SExpr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// 文字列が JSON / S 式として読めない。`offset` はバイト位置。
+    Syntax { offset: usize, message: String },
+    /// 構文は正しいが、期待した形をしていない。
+    Shape(String),
+}
+
+/// 読み込むときに許す入れ子の深さ。読み込みと `AstNode` の復元は再帰しないが、
+/// 読んだ値の比較や複製（derive したもの）は入れ子ごとに再帰するので、これより深い入力は `DecodeError::Syntax` で断る。
+pub const MAX_NESTING: usize = 4096;
+
+pub trait Encode {
+    fn to_json(&self) -> Json;
+    fn to_sexpr(&self) -> SExpr;
+}
+
+pub trait Decode: Sized {
+    fn from_json(value: &Json) -> Result<Self, DecodeError>;
+    fn from_sexpr(value: &SExpr) -> Result<Self, DecodeError>;
+}
+
+pub fn to_json_string<T: Encode + ?Sized>(value: &T) -> String {
+    value.to_json().pretty()
+}
+
+pub fn from_json_str<T: Decode>(input: &str) -> Result<T, DecodeError> {
+    T::from_json(&json::parse(input)?)
+}
+
+pub fn to_sexpr_string<T: Encode + ?Sized>(value: &T) -> String {
+    value.to_sexpr().pretty()
+}
+
+pub fn from_sexpr_str<T: Decode>(input: &str) -> Result<T, DecodeError> {
+    T::from_sexpr(&sexpr::parse(input)?)
+}
+
+fn shape(message: impl Into<String>) -> DecodeError {
+    DecodeError::Shape(message.into())
+}
+
+// ── JSON helpers ──────────────────────────────────────────────────────────────
+
+fn field<'a>(value: &'a Json, key: &str) -> Result<&'a Json, DecodeError> {
+    value
+        .get(key)
+        .ok_or_else(|| shape(format!("missing field '{key}'")))
+}
+
+fn json_usize(value: &Json) -> Result<usize, DecodeError> {
+    match value {
+        Json::Number(number) => {
+            usize::try_from(*number).map_err(|_| shape(format!("expected index, got {number}")))
+        }
+        other => Err(shape(format!("expected number, got {other}"))),
+    }
+}
+
+fn json_str(value: &Json) -> Result<&str, DecodeError> {
+    match value {
+        Json::String(text) => Ok(text),
+        other => Err(shape(format!("expected string, got {other}"))),
+    }
+}
+
+fn json_array(value: &Json) -> Result<&[Json], DecodeError> {
+    match value {
+        Json::Array(items) => Ok(items),
+        other => Err(shape(format!("expected array, got {other}"))),
+    }
+}
+
+fn single_char(text: &str) -> Result<char, DecodeError> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(shape(format!("expected a single symbol, got \"{text}\""))),
+    }
+}
+
+fn json_char(value: &Json) -> Result<char, DecodeError> {
+    single_char(json_str(value)?)
+}
+
+fn json_span(span: Option<Span>) -> Json {
+    match span {
+        Some(span) => Json::Array(vec![
+            Json::Number(span.start as i64),
+            Json::Number(span.end as i64),
+        ]),
+        None => Json::Null,
+    }
+}
+
+fn span_from_json(value: &Json) -> Result<Option<Span>, DecodeError> {
+    match value {
+        Json::Null => Ok(None),
+        other => match json_array(other)? {
+            [start, end] => Ok(Some(Span::new(json_usize(start)?, json_usize(end)?))),
+            _ => Err(shape("span must be [start, end]")),
+        },
+    }
+}
+
+fn json_attributes(attributes: &Attributes) -> Json {
+    Json::object(
+        attributes
+            .iter()
+            .map(|(key, value)| (key.clone(), Json::string(value.clone()))),
+    )
+}
+
+fn attributes_from_json(value: &Json) -> Result<Attributes, DecodeError> {
+    match value {
+        Json::Object(fields) => fields
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), json_str(value)?.to_string())))
+            .collect(),
+        other => Err(shape(format!("expected attribute object, got {other}"))),
+    }
+}
+
+fn json_usizes(values: &[usize]) -> Json {
+    Json::Array(values.iter().map(|&value| Json::Number(value as i64)).collect())
+}
+
+fn production_from_str(text: &str) -> Result<Production, DecodeError> {
+    Production::from_str(text).map_err(|err| shape(format!("invalid production \"{text}\": {err:?}")))
+}
+
+// ── S-expression helpers ──────────────────────────────────────────────────────
+
+/// `(head :key value... positional...)` を head・キーワード引数・位置引数に分ける。
+/// `arity` は各キーワードが取る値の個数。
+struct Form<'a> {
+    keywords: Vec<(&'a str, &'a [SExpr])>,
+    positional: Vec<&'a SExpr>,
+}
+
+impl<'a> Form<'a> {
+    fn parse(
+        value: &'a SExpr,
+        head: &str,
+        arity: impl Fn(&str) -> Option<usize>,
+    ) -> Result<Self, DecodeError> {
+        let items = value
+            .as_list()
+            .ok_or_else(|| shape(format!("expected ({head} ...), got {value}")))?;
+        match items.first() {
+            Some(SExpr::Atom(atom)) if atom == head => {}
+            _ => return Err(shape(format!("expected ({head} ...), got {value}"))),
+        }
+
+        let mut form = Form {
+            keywords: Vec::new(),
+            positional: Vec::new(),
+        };
+        let mut index = 1;
+        while index < items.len() {
+            match &items[index] {
+                SExpr::Atom(atom) if atom.starts_with(':') => {
+                    let key = &atom[1..];
+                    let count =
+                        arity(key).ok_or_else(|| shape(format!("unknown keyword :{key} in ({head} ...)")))?;
+                    let values = items
+                        .get(index + 1..index + 1 + count)
+                        .ok_or_else(|| shape(format!(":{key} needs {count} value(s)")))?;
+                    form.keywords.push((key, values));
+                    index += 1 + count;
+                }
+                other => {
+                    form.positional.push(other);
+                    index += 1;
+                }
+            }
+        }
+        Ok(form)
+    }
+
+    fn all<'k>(&'k self, key: &'k str) -> impl Iterator<Item = &'a [SExpr]> + 'k {
+        self.keywords
+            .iter()
+            .filter(move |(name, _)| *name == key)
+            .map(|(_, values)| *values)
+    }
+
+    fn get(&self, key: &str) -> Option<&'a [SExpr]> {
+        self.all(key).next()
+    }
+
+    fn one(&self, key: &str) -> Result<&'a SExpr, DecodeError> {
+        self.get(key)
+            .and_then(|values| values.first())
+            .ok_or_else(|| shape(format!("missing :{key}")))
+    }
+}
+
+fn sexpr_usize(value: &SExpr) -> Result<usize, DecodeError> {
+    match value {
+        SExpr::Atom(atom) => atom
+            .parse()
+            .map_err(|_| shape(format!("expected number, got {atom}"))),
+        other => Err(shape(format!("expected number, got {other}"))),
+    }
+}
+
+fn sexpr_str(value: &SExpr) -> Result<&str, DecodeError> {
+    match value {
+        SExpr::Str(text) => Ok(text),
+        other => Err(shape(format!("expected string, got {other}"))),
+    }
+}
+
+fn sexpr_char(value: &SExpr) -> Result<char, DecodeError> {
+    single_char(sexpr_str(value)?)
+}
+
+fn sexpr_list(value: &SExpr) -> Result<&[SExpr], DecodeError> {
+    value
+        .as_list()
+        .ok_or_else(|| shape(format!("expected list, got {value}")))
+}
+
+fn sexpr_usizes(values: &[usize]) -> SExpr {
+    SExpr::List(values.iter().map(SExpr::atom).collect())
+}
+
+fn push_span(items: &mut Vec<SExpr>, span: Option<Span>) {
+    if let Some(span) = span {
+        items.push(SExpr::atom(":span"));
+        items.push(SExpr::atom(span.start));
+        items.push(SExpr::atom(span.end));
+    }
+}
+
+fn push_attributes(items: &mut Vec<SExpr>, attributes: &Attributes) {
+    for (key, value) in attributes {
+        items.push(SExpr::atom(":attr"));
+        items.push(SExpr::string(key.clone()));
+        items.push(SExpr::string(value.clone()));
+    }
+}
+
+fn span_from_form(form: &Form) -> Result<Option<Span>, DecodeError> {
+    form.get("span")
+        .map(|values| Ok(Span::new(sexpr_usize(&values[0])?, sexpr_usize(&values[1])?)))
+        .transpose()
+}
+
+fn attributes_from_form(form: &Form) -> Result<Attributes, DecodeError> {
+    form.all("attr")
+        .map(|values| Ok((sexpr_str(&values[0])?.to_string(), sexpr_str(&values[1])?.to_string())))
+        .collect()
+}
+
+// ── Sequences ─────────────────────────────────────────────────────────────────
+
+impl<T: Encode> Encode for [T] {
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(Encode::to_json).collect())
+    }
+
+    fn to_sexpr(&self) -> SExpr {
+        SExpr::List(self.iter().map(Encode::to_sexpr).collect())
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn to_json(&self) -> Json {
+        self.as_slice().to_json()
+    }
+
+    fn to_sexpr(&self) -> SExpr {
+        self.as_slice().to_sexpr()
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn from_json(value: &Json) -> Result<Self, DecodeError> {
+        json_array(value)?.iter().map(T::from_json).collect()
+    }
+
+    fn from_sexpr(value: &SExpr) -> Result<Self, DecodeError> {
+        sexpr_list(value)?.iter().map(T::from_sexpr).collect()
+    }
+}
+
+// ── AstNode ───────────────────────────────────────────────────────────────────
+//
+// JSON: {"kind":"terminal","symbol":"+","lexeme":"+","span":[1,2],"attributes":{}}
+//       {"kind":"non_terminal","symbol":"E","production":2,"span":[0,3],"attributes":{},"children":[...]}
+// S 式: (t "+" :lexeme "+" :span 1 2)
+//       (n "E" :production 2 :span 0 3 child...)
+// S 式では字句が記号と同じなら :lexeme を、値がなければ :span / :production を省く。
+
+impl Encode for AstNode {
+    fn to_json(&self) -> Json {
+        self.fold(&mut JsonNodes)
+    }
+
+    fn to_sexpr(&self) -> SExpr {
+        self.fold(&mut SExprNodes)
+    }
+}
+
+// 符号化は `fold` で子から順に組み立てる。再帰しないので深い木でもスタックを使い切らない。
+struct JsonNodes;
+
+impl Fold for JsonNodes {
+    type Output = Json;
+
+    fn fold_terminal(&mut self, token: &Token) -> Json {
+        Json::object([
+            ("kind", Json::string("terminal")),
+            ("symbol", Json::string(token.symbol)),
+            ("lexeme", Json::string(token.lexeme.clone())),
+            ("span", json_span(token.span)),
+            ("attributes", json_attributes(&token.attributes)),
+        ])
+    }
+
+    fn fold_non_terminal(&mut self, info: &NodeInfo, children: Vec<Json>) -> Json {
+        Json::object([
+            ("kind", Json::string("non_terminal")),
+            ("symbol", Json::string(info.symbol)),
+            (
+                "production",
+                info.production
+                    .map(|id| Json::Number(id as i64))
+                    .unwrap_or(Json::Null),
+            ),
+            ("span", json_span(info.span)),
+            ("attributes", json_attributes(&info.attributes)),
+            ("children", Json::Array(children)),
+        ])
+    }
+}
+
+struct SExprNodes;
+
+impl Fold for SExprNodes {
+    type Output = SExpr;
+
+    fn fold_terminal(&mut self, token: &Token) -> SExpr {
+        let mut items = vec![SExpr::atom("t"), SExpr::string(token.symbol)];
+        if token.lexeme != token.symbol.to_string() {
+            items.push(SExpr::atom(":lexeme"));
+            items.push(SExpr::string(token.lexeme.clone()));
+        }
+        push_span(&mut items, token.span);
+        push_attributes(&mut items, &token.attributes);
+        SExpr::List(items)
+    }
+
+    fn fold_non_terminal(&mut self, info: &NodeInfo, children: Vec<SExpr>) -> SExpr {
+        let mut items = vec![SExpr::atom("n"), SExpr::string(info.symbol)];
+        if let Some(production) = info.production {
+            items.push(SExpr::atom(":production"));
+            items.push(SExpr::atom(production));
+        }
+        push_span(&mut items, info.span);
+        push_attributes(&mut items, &info.attributes);
+        items.extend(children);
+        SExpr::List(items)
+    }
+}
+
+impl Decode for AstNode {
+    fn from_json(value: &Json) -> Result<Self, DecodeError> {
+        decode_tree(value, |value| {
+            let symbol = json_char(field(value, "symbol")?)?;
+            let span = span_from_json(field(value, "span")?)?;
+            let attributes = attributes_from_json(field(value, "attributes")?)?;
+            match json_str(field(value, "kind")?)? {
+                "terminal" => Ok(Part::Leaf(AstNode::Terminal(Token {
+                    symbol,
+                    lexeme: json_str(field(value, "lexeme")?)?.to_string(),
+                    span,
+                    attributes,
+                }))),
+                "non_terminal" => Ok(Part::Inner(
+                    NodeInfo {
+                        symbol,
+                        production: match field(value, "production")? {
+                            Json::Null => None,
+                            other => Some(json_usize(other)?),
+                        },
+                        span,
+                        attributes,
+                    },
+                    json_array(field(value, "children")?)?.iter().collect(),
+                )),
+                other => Err(shape(format!("unknown node kind '{other}'"))),
+            }
+        })
+    }
+
+    fn from_sexpr(value: &SExpr) -> Result<Self, DecodeError> {
+        let arity = |key: &str| match key {
+            "lexeme" | "production" => Some(1),
+            "span" | "attr" => Some(2),
+            _ => None,
+        };
+        decode_tree(value, |value| {
+            let head = sexpr_list(value)?
+                .first()
+                .ok_or_else(|| shape("empty node"))?;
+            let is_terminal = matches!(head, SExpr::Atom(atom) if atom == "t");
+            let form = Form::parse(value, if is_terminal { "t" } else { "n" }, arity)?;
+            let (symbol, children) = form
+                .positional
+                .split_first()
+                .ok_or_else(|| shape(format!("node without symbol: {value}")))?;
+            let symbol = sexpr_char(symbol)?;
+            let span = span_from_form(&form)?;
+            let attributes = attributes_from_form(&form)?;
+
+            if is_terminal {
+                if !children.is_empty() {
+                    return Err(shape(format!("terminal node with children: {value}")));
+                }
+                return Ok(Part::Leaf(AstNode::Terminal(Token {
+                    symbol,
+                    lexeme: match form.get("lexeme") {
+                        Some(values) => sexpr_str(&values[0])?.to_string(),
+                        None => symbol.to_string(),
+                    },
+                    span,
+                    attributes,
+                })));
+            }
+
+            Ok(Part::Inner(
+                NodeInfo {
+                    symbol,
+                    production: form
+                        .get("production")
+                        .map(|values| sexpr_usize(&values[0]))
+                        .transpose()?,
+                    span,
+                    attributes,
+                },
+                children.to_vec(),
+            ))
+        })
+    }
+}
+
+// 1ノード分を読んだ結果。非終端記号は子の符号化をまだ読まずに返す。
+enum Part<'a, V> {
+    Leaf(AstNode),
+    Inner(NodeInfo, Vec<&'a V>),
+}
+
+// 木の復元もスタックで辿る。子を読み終えたノードから `built` の末尾の子をまとめて親にする。
+fn decode_tree<'a, V>(
+    root: &'a V,
+    node: impl Fn(&'a V) -> Result<Part<'a, V>, DecodeError>,
+) -> Result<AstNode, DecodeError> {
+    enum Step<'a, V> {
+        Visit(&'a V),
+        Finish(NodeInfo, usize),
+    }
+
+    let mut pending = vec![Step::Visit(root)];
+    let mut built = Vec::new();
+    while let Some(step) = pending.pop() {
+        match step {
+            Step::Visit(value) => match node(value)? {
+                Part::Leaf(leaf) => built.push(leaf),
+                Part::Inner(info, children) => {
+                    pending.push(Step::Finish(info, children.len()));
+                    pending.extend(children.into_iter().rev().map(Step::Visit));
+                }
+            },
+            Step::Finish(info, count) => {
+                let children = built.split_off(built.len() - count);
+                built.push(AstNode::NonTerminal(info, children));
+            }
+        }
+    }
+    Ok(built.pop().expect("the root node is built last"))
+}
+
+// ── ParseStep / StepAction ────────────────────────────────────────────────────
+//
+// JSON: {"kind":"shift","terminal":"1","to_state":4}
+//       {"kind":"reduce","production":5,"rule":"B -> 1","pop_count":1}
+//       {"kind":"accept"}
+// S 式: (shift "1" 4) / (reduce 5 "B -> 1" 1) / (accept)
+
+impl Encode for StepAction {
+    fn to_json(&self) -> Json {
+        match self {
+            StepAction::Shift { terminal, to_state } => Json::object([
+                ("kind", Json::string("shift")),
+                ("terminal", Json::string(*terminal)),
+                ("to_state", Json::Number(*to_state as i64)),
+            ]),
+            StepAction::Reduce {
+                production,
+                rule,
+                pop_count,
+            } => Json::object([
+                ("kind", Json::string("reduce")),
+                ("production", Json::Number(*production as i64)),
+                ("rule", Json::string(rule.clone())),
+                ("pop_count", Json::Number(*pop_count as i64)),
+            ]),
+            StepAction::Accept => Json::object([("kind", Json::string("accept"))]),
+        }
+    }
+
+    fn to_sexpr(&self) -> SExpr {
+        match self {
+            StepAction::Shift { terminal, to_state } => SExpr::List(vec![
+                SExpr::atom("shift"),
+                SExpr::string(*terminal),
+                SExpr::atom(to_state),
+            ]),
+            StepAction::Reduce {
+                production,
+                rule,
+                pop_count,
+            } => SExpr::List(vec![
+                SExpr::atom("reduce"),
+                SExpr::atom(production),
+                SExpr::string(rule.clone()),
+                SExpr::atom(pop_count),
+            ]),
+            StepAction::Accept => SExpr::List(vec![SExpr::atom("accept")]),
+        }
+    }
+}
+
+impl Decode for StepAction {
+    fn from_json(value: &Json) -> Result<Self, DecodeError> {
+        match json_str(field(value, "kind")?)? {
+            "shift" => Ok(StepAction::Shift {
+                terminal: json_char(field(value, "terminal")?)?,
+                to_state: json_usize(field(value, "to_state")?)?,
+            }),
+            "reduce" => Ok(StepAction::Reduce {
+                production: json_usize(field(value, "production")?)?,
+                rule: json_str(field(value, "rule")?)?.to_string(),
+                pop_count: json_usize(field(value, "pop_count")?)?,
+            }),
+            "accept" => Ok(StepAction::Accept),
+            other => Err(shape(format!("unknown step action '{other}'"))),
+        }
+    }
+
+    fn from_sexpr(value: &SExpr) -> Result<Self, DecodeError> {
+        match sexpr_list(value)? {
+            [SExpr::Atom(head), terminal, to_state] if head == "shift" => Ok(StepAction::Shift {
+                terminal: sexpr_char(terminal)?,
+                to_state: sexpr_usize(to_state)?,
+            }),
+            [SExpr::Atom(head), production, rule, pop_count] if head == "reduce" => {
+                Ok(StepAction::Reduce {
+                    production: sexpr_usize(production)?,
+                    rule: sexpr_str(rule)?.to_string(),
+                    pop_count: sexpr_usize(pop_count)?,
+                })
+            }
+            [SExpr::Atom(head)] if head == "accept" => Ok(StepAction::Accept),
+            _ => Err(shape(format!("unknown step action {value}"))),
+        }
+    }
+}
+
+// JSON: {"from_state":0,"lookahead":"1","action":{...},"state_stack":[0,4],"remaining_input":"+1$","ast_stack":[...]}
+// S 式: (step :from 0 :lookahead "1" :action (shift "1" 4) :stack (0 4) :remaining "+1$" :ast (...))
+
+impl Encode for ParseStep {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("from_state", Json::Number(self.from_state as i64)),
+            ("lookahead", Json::string(self.lookahead)),
+            ("action", self.action.to_json()),
+            ("state_stack", json_usizes(&self.state_stack)),
+            (
+                "remaining_input",
+                Json::string(self.remaining_input.iter().collect::<String>()),
+            ),
+            ("ast_stack", self.ast_stack.to_json()),
+        ])
+    }
+
+    fn to_sexpr(&self) -> SExpr {
+        SExpr::List(vec![
+            SExpr::atom("step"),
+            SExpr::atom(":from"),
+            SExpr::atom(self.from_state),
+            SExpr::atom(":lookahead"),
+            SExpr::string(self.lookahead),
+            SExpr::atom(":action"),
+            self.action.to_sexpr(),
+            SExpr::atom(":stack"),
+            sexpr_usizes(&self.state_stack),
+            SExpr::atom(":remaining"),
+            SExpr::string(self.remaining_input.iter().collect::<String>()),
+            SExpr::atom(":ast"),
+            self.ast_stack.to_sexpr(),
+        ])
+    }
+}
+
+impl Decode for ParseStep {
+    fn from_json(value: &Json) -> Result<Self, DecodeError> {
+        Ok(ParseStep {
+            action: StepAction::from_json(field(value, "action")?)?,
+            from_state: json_usize(field(value, "from_state")?)?,
+            lookahead: json_char(field(value, "lookahead")?)?,
+            state_stack: json_array(field(value, "state_stack")?)?
+                .iter()
+                .map(json_usize)
+                .collect::<Result<_, _>>()?,
+            remaining_input: json_str(field(value, "remaining_input")?)?.chars().collect(),
+            ast_stack: Vec::from_json(field(value, "ast_stack")?)?,
+        })
+    }
+
+    fn from_sexpr(value: &SExpr) -> Result<Self, DecodeError> {
+        let form = Form::parse(value, "step", |key| {
+            matches!(key, "from" | "lookahead" | "action" | "stack" | "remaining" | "ast").then_some(1)
+        })?;
+        Ok(ParseStep {
+            action: StepAction::from_sexpr(form.one("action")?)?,
+            from_state: sexpr_usize(form.one("from")?)?,
+            lookahead: sexpr_char(form.one("lookahead")?)?,
+            state_stack: sexpr_list(form.one("stack")?)?
+                .iter()
+                .map(sexpr_usize)
+                .collect::<Result<_, _>>()?,
+            remaining_input: sexpr_str(form.one("remaining")?)?.chars().collect(),
+            ast_stack: Vec::from_sexpr(form.one("ast")?)?,
+        })
+    }
+}
+
+// ── StateInfo ─────────────────────────────────────────────────────────────────
+//
+// JSON: {"id":0,"items":[{"production":"F -> E","dot":0}],"transitions":[{"symbol":{"non_terminal":"E"},"to":1}]}
+// S 式: (state :id 0 :items ((item "F -> E" 0)) :transitions ((edge (n "E") 1)))
+
+fn symbol_to_json(symbol: &Symbol) -> Json {
+    match symbol {
+        Symbol::Terminal(terminal) => Json::object([("terminal", Json::string(terminal.0))]),
+        Symbol::NonTerminal(non_terminal) => {
+            Json::object([("non_terminal", Json::string(non_terminal.0))])
+        }
+    }
+}
+
+fn symbol_from_json(value: &Json) -> Result<Symbol, DecodeError> {
+    if let Some(terminal) = value.get("terminal") {
+        return Ok(Symbol::Terminal(Terminal(json_char(terminal)?)));
+    }
+    if let Some(non_terminal) = value.get("non_terminal") {
+        return Ok(Symbol::NonTerminal(NonTerminal(json_char(non_terminal)?)));
+    }
+    Err(shape(format!("expected symbol, got {value}")))
+}
+
+fn symbol_to_sexpr(symbol: &Symbol) -> SExpr {
+    let kind = match symbol {
+        Symbol::Terminal(_) => "t",
+        Symbol::NonTerminal(_) => "n",
+    };
+    SExpr::List(vec![SExpr::atom(kind), SExpr::string(symbol.as_char())])
+}
+
+fn symbol_from_sexpr(value: &SExpr) -> Result<Symbol, DecodeError> {
+    match sexpr_list(value)? {
+        [SExpr::Atom(kind), symbol] if kind == "t" => Ok(Symbol::Terminal(Terminal(sexpr_char(symbol)?))),
+        [SExpr::Atom(kind), symbol] if kind == "n" => {
+            Ok(Symbol::NonTerminal(NonTerminal(sexpr_char(symbol)?)))
+        }
+        _ => Err(shape(format!("expected symbol, got {value}"))),
+    }
+}
+
+impl Encode for StateInfo {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("id", Json::Number(self.id as i64)),
+            (
+                "items",
+                Json::Array(
+                    self.items
+                        .iter()
+                        .map(|item| {
+                            Json::object([
+                                ("production", Json::string(item.production.to_string())),
+                                ("dot", Json::Number(item.dot_pos as i64)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "transitions",
+                Json::Array(
+                    self.transitions
+                        .iter()
+                        .map(|(symbol, next)| {
+                            Json::object([
+                                ("symbol", symbol_to_json(symbol)),
+                                ("to", Json::Number(*next as i64)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+
+    fn to_sexpr(&self) -> SExpr {
+        SExpr::List(vec![
+            SExpr::atom("state"),
+            SExpr::atom(":id"),
+            SExpr::atom(self.id),
+            SExpr::atom(":items"),
+            SExpr::List(
+                self.items
+                    .iter()
+                    .map(|item| {
+                        SExpr::List(vec![
+                            SExpr::atom("item"),
+                            SExpr::string(item.production.to_string()),
+                            SExpr::atom(item.dot_pos),
+                        ])
+                    })
+                    .collect(),
+            ),
+            SExpr::atom(":transitions"),
+            SExpr::List(
+                self.transitions
+                    .iter()
+                    .map(|(symbol, next)| {
+                        SExpr::List(vec![
+                            SExpr::atom("edge"),
+                            symbol_to_sexpr(symbol),
+                            SExpr::atom(next),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ])
+    }
+}
+
+impl Decode for StateInfo {
+    fn from_json(value: &Json) -> Result<Self, DecodeError> {
+        Ok(StateInfo {
+            id: json_usize(field(value, "id")?)?,
+            items: json_array(field(value, "items")?)?
+                .iter()
+                .map(|item| {
+                    Ok(LrItem {
+                        production: production_from_str(json_str(field(item, "production")?)?)?,
+                        dot_pos: json_usize(field(item, "dot")?)?,
+                    })
+                })
+                .collect::<Result<_, DecodeError>>()?,
+            transitions: json_array(field(value, "transitions")?)?
+                .iter()
+                .map(|edge| {
+                    Ok((
+                        symbol_from_json(field(edge, "symbol")?)?,
+                        json_usize(field(edge, "to")?)?,
+                    ))
+                })
+                .collect::<Result<_, DecodeError>>()?,
+        })
+    }
+
+    fn from_sexpr(value: &SExpr) -> Result<Self, DecodeError> {
+        let form = Form::parse(value, "state", |key| {
+            matches!(key, "id" | "items" | "transitions").then_some(1)
+        })?;
+        Ok(StateInfo {
+            id: sexpr_usize(form.one("id")?)?,
+            items: sexpr_list(form.one("items")?)?
+                .iter()
+                .map(|item| match sexpr_list(item)? {
+                    [SExpr::Atom(head), production, dot] if head == "item" => Ok(LrItem {
+                        production: production_from_str(sexpr_str(production)?)?,
+                        dot_pos: sexpr_usize(dot)?,
+                    }),
+                    _ => Err(shape(format!("expected (item ...), got {item}"))),
+                })
+                .collect::<Result<_, _>>()?,
+            transitions: sexpr_list(form.one("transitions")?)?
+                .iter()
+                .map(|edge| match sexpr_list(edge)? {
+                    [SExpr::Atom(head), symbol, next] if head == "edge" => {
+                        Ok((symbol_from_sexpr(symbol)?, sexpr_usize(next)?))
+                    }
+                    _ => Err(shape(format!("expected (edge ...), got {edge}"))),
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+// ── ParseTables ───────────────────────────────────────────────────────────────
+//
+// JSON: {"state_count":9,"productions":["F -> E",...],
+//        "action":[{"state":0,"terminal":"0","action":"s4"}],"goto":[{"state":0,"non_terminal":"E","to":1}]}
+// S 式: (tables :state-count 9 :productions ("F -> E" ...) :action ((0 "0" s4) ...) :goto ((0 "E" 1) ...))
+
+fn action_from_str(text: &str) -> Result<Action, DecodeError> {
+    Action::from_str(text).map_err(shape)
+}
+
+impl Encode for ParseTables {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("state_count", Json::Number(self.state_count as i64)),
+            (
+                "productions",
+                Json::Array(
+                    self.productions
+                        .iter()
+                        .map(|production| Json::string(production.to_string()))
+                        .collect(),
+                ),
+            ),
+            (
+                "action",
+                Json::Array(
+                    self.actions
+                        .iter()
+                        .map(|(state, terminal, action)| {
+                            Json::object([
+                                ("state", Json::Number(*state as i64)),
+                                ("terminal", Json::string(terminal.0)),
+                                ("action", Json::string(action.to_string())),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "goto",
+                Json::Array(
+                    self.gotos
+                        .iter()
+                        .map(|(state, non_terminal, next)| {
+                            Json::object([
+                                ("state", Json::Number(*state as i64)),
+                                ("non_terminal", Json::string(non_terminal.0)),
+                                ("to", Json::Number(*next as i64)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+
+    fn to_sexpr(&self) -> SExpr {
+        SExpr::List(vec![
+            SExpr::atom("tables"),
+            SExpr::atom(":state-count"),
+            SExpr::atom(self.state_count),
+            SExpr::atom(":productions"),
+            SExpr::List(
+                self.productions
+                    .iter()
+                    .map(|production| SExpr::string(production.to_string()))
+                    .collect(),
+            ),
+            SExpr::atom(":action"),
+            SExpr::List(
+                self.actions
+                    .iter()
+                    .map(|(state, terminal, action)| {
+                        SExpr::List(vec![
+                            SExpr::atom(state),
+                            SExpr::string(terminal.0),
+                            SExpr::atom(action),
+                        ])
+                    })
+                    .collect(),
+            ),
+            SExpr::atom(":goto"),
+            SExpr::List(
+                self.gotos
+                    .iter()
+                    .map(|(state, non_terminal, next)| {
+                        SExpr::List(vec![
+                            SExpr::atom(state),
+                            SExpr::string(non_terminal.0),
+                            SExpr::atom(next),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ])
+    }
+}
+
+impl Decode for ParseTables {
+    fn from_json(value: &Json) -> Result<Self, DecodeError> {
+        Ok(ParseTables {
+            state_count: json_usize(field(value, "state_count")?)?,
+            productions: json_array(field(value, "productions")?)?
+                .iter()
+                .map(|production| production_from_str(json_str(production)?))
+                .collect::<Result<_, _>>()?,
+            actions: json_array(field(value, "action")?)?
+                .iter()
+                .map(|entry| {
+                    Ok((
+                        json_usize(field(entry, "state")?)?,
+                        Terminal(json_char(field(entry, "terminal")?)?),
+                        action_from_str(json_str(field(entry, "action")?)?)?,
+                    ))
+                })
+                .collect::<Result<_, DecodeError>>()?,
+            gotos: json_array(field(value, "goto")?)?
+                .iter()
+                .map(|entry| {
+                    Ok((
+                        json_usize(field(entry, "state")?)?,
+                        NonTerminal(json_char(field(entry, "non_terminal")?)?),
+                        json_usize(field(entry, "to")?)?,
+                    ))
+                })
+                .collect::<Result<_, DecodeError>>()?,
+        })
+    }
+
+    fn from_sexpr(value: &SExpr) -> Result<Self, DecodeError> {
+        let form = Form::parse(value, "tables", |key| {
+            matches!(key, "state-count" | "productions" | "action" | "goto").then_some(1)
+        })?;
+        Ok(ParseTables {
+            state_count: sexpr_usize(form.one("state-count")?)?,
+            productions: sexpr_list(form.one("productions")?)?
+                .iter()
+                .map(|production| production_from_str(sexpr_str(production)?))
+                .collect::<Result<_, _>>()?,
+            actions: sexpr_list(form.one("action")?)?
+                .iter()
+                .map(|entry| match sexpr_list(entry)? {
+                    [state, terminal, SExpr::Atom(action)] => Ok((
+                        sexpr_usize(state)?,
+                        Terminal(sexpr_char(terminal)?),
+                        action_from_str(action)?,
+                    )),
+                    _ => Err(shape(format!("expected (state \"t\" action), got {entry}"))),
+                })
+                .collect::<Result<_, _>>()?,
+            gotos: sexpr_list(form.one("goto")?)?
+                .iter()
+                .map(|entry| match sexpr_list(entry)? {
+                    [state, non_terminal, next] => Ok((
+                        sexpr_usize(state)?,
+                        NonTerminal(sexpr_char(non_terminal)?),
+                        sexpr_usize(next)?,
+                    )),
+                    _ => Err(shape(format!("expected (state \"N\" next), got {entry}"))),
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{parse_grammar_text, parse_input_text};
+    use crate::lr::compile;
+    use crate::runtime::{build_trace, run};
+
+    const GRAMMAR: &str = "E -> E*B\nE -> E+B\nE -> B\nB -> 0\nB -> 1";
+
+    fn parsed(input: &str) -> (crate::lr::CompiledParser, Vec<Symbol>) {
+        let machine = compile(&parse_grammar_text(GRAMMAR).unwrap()).unwrap();
+        (machine, parse_input_text(input).unwrap())
+    }
+
+    #[test]
+    fn ast_has_a_stable_sexpr_encoding() {
+        let (machine, input) = parsed("1+0");
+        let ast = run(&machine, &input).unwrap().ast;
+
+        assert_eq!(
+            ast.to_sexpr().to_string(),
+            "(n \"E\" :production 2 :span 0 3 \
+             (n \"E\" :production 3 :span 0 1 (n \"B\" :production 5 :span 0 1 (t \"1\" :span 0 1))) \
+             (t \"+\" :span 1 2) \
+             (n \"B\" :production 4 :span 2 3 (t \"0\" :span 2 3)))"
+        );
+    }
+
+    #[test]
+    fn ast_round_trips_through_json_and_sexpr() {
+        let (machine, input) = parsed("1+1*0");
+        let mut ast = run(&machine, &input).unwrap().ast;
+        ast.attributes_mut().insert("note".to_string(), "quoted \"value\"".to_string());
+
+        assert_eq!(from_json_str::<AstNode>(&to_json_string(&ast)), Ok(ast.clone()));
+        assert_eq!(from_sexpr_str::<AstNode>(&to_sexpr_string(&ast)), Ok(ast));
+    }
+
+    #[test]
+    fn trace_round_trips_through_json_and_sexpr() {
+        let (machine, input) = parsed("1*0+1");
+        let trace = build_trace(&machine, &input).unwrap();
+
+        assert_eq!(from_json_str::<Vec<ParseStep>>(&to_json_string(&trace)), Ok(trace.clone()));
+        assert_eq!(from_sexpr_str::<Vec<ParseStep>>(&to_sexpr_string(&trace)), Ok(trace));
+    }
+
+    #[test]
+    fn states_and_tables_round_trip() {
+        let (machine, _) = parsed("1");
+        let states = machine.state_infos().to_vec();
+        let tables = machine.tables();
+
+        assert_eq!(from_json_str::<Vec<StateInfo>>(&to_json_string(&states)), Ok(states.clone()));
+        assert_eq!(from_sexpr_str::<Vec<StateInfo>>(&to_sexpr_string(&states)), Ok(states));
+        assert_eq!(from_json_str::<ParseTables>(&to_json_string(&tables)), Ok(tables.clone()));
+        assert_eq!(from_sexpr_str::<ParseTables>(&to_sexpr_string(&tables)), Ok(tables));
+    }
+
+    #[test]
+    fn malformed_input_reports_where_it_failed() {
+        assert!(matches!(
+            from_json_str::<AstNode>("{\"kind\": \"terminal\", "),
+            Err(DecodeError::Syntax { .. })
+        ));
+        assert!(matches!(
+            from_sexpr_str::<AstNode>("(x \"E\")"),
+            Err(DecodeError::Shape(_))
+        ));
+    }
+
+    #[test]
+    fn deep_trees_are_written_without_recursion() {
+        let (machine, input) = parsed(&format!("{}1", "1+0+".repeat(25_000)));
+        let ast = run(&machine, &input).unwrap().ast;
+
+        let json = to_json_string(&ast);
+        let sexpr = to_sexpr_string(&ast);
+        assert!(json.starts_with("{\n  \"kind\": \"non_terminal\""));
+        assert!(sexpr.starts_with("(n \"E\"\n  :production 2"));
+
+        // 読み戻しは入れ子の深さに上限があり、越えた入力はエラーで断る
+        assert!(matches!(from_json_str::<AstNode>(&json), Err(DecodeError::Syntax { .. })));
+        assert!(matches!(from_sexpr_str::<AstNode>(&sexpr), Err(DecodeError::Syntax { .. })));
+    }
+
+    #[test]
+    fn nesting_up_to_the_limit_is_read_back() {
+        let nested = |open: &str, close: &str, depth: usize| open.repeat(depth) + &close.repeat(depth);
+
+        assert!(json::parse(&nested("[", "]", MAX_NESTING)).is_ok());
+        assert!(json::parse(&nested("[", "]", MAX_NESTING + 1)).is_err());
+        assert!(sexpr::parse(&nested("(", ")", MAX_NESTING)).is_ok());
+        assert!(sexpr::parse(&nested("(", ")", MAX_NESTING + 1)).is_err());
+
+        // 深さ d の木は JSON で 2d - 1 段になる。上限の手前まで積んだ木も読み戻せる
+        let (machine, input) = parsed(&format!("{}1", "1+".repeat(MAX_NESTING / 2 - 3)));
+        let ast = run(&machine, &input).unwrap().ast;
+        assert_eq!(from_json_str::<AstNode>(&to_json_string(&ast)), Ok(ast.clone()));
+        assert_eq!(from_sexpr_str::<AstNode>(&to_sexpr_string(&ast)), Ok(ast));
+    }
+}
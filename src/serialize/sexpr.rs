@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+
+use super::{DecodeError, MAX_NESTING};
+
+/// S 式の値。`Atom` はキーワードや数値などの裸の語、`Str` は二重引用符で囲んだ文字列。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SExpr {
+    Atom(String),
+    Str(String),
+    List(Vec<SExpr>),
+}
+
+impl SExpr {
+    pub fn atom(value: impl ToString) -> Self {
+        Self::Atom(value.to_string())
+    }
+
+    pub fn string(value: impl Into<String>) -> Self {
+        Self::Str(value.into())
+    }
+
+    pub fn as_list(&self) -> Option<&[SExpr]> {
+        match self {
+            Self::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// 長いリストを字下げして複数行に分けた文字列。
+    pub fn pretty(&self) -> String {
+        let mut output = String::new();
+        write_pretty(self, &mut output, 0);
+        output
+    }
+}
+
+// 深い木を符号化した値も再帰せずに捨てる。`AstNode` の drop と同じく、子を作業用のスタックに移していく。
+impl Drop for SExpr {
+    fn drop(&mut self) {
+        let Self::List(items) = self else { return };
+        let mut pending = std::mem::take(items);
+        while let Some(mut value) = pending.pop() {
+            if let Self::List(items) = &mut value {
+                pending.append(items);
+            }
+        }
+    }
+}
+
+impl fmt::Display for SExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // 入れ子は再帰せず、残りの仕事を逆順にスタックへ積む
+        let mut stack = vec![Frame::Flat(self)];
+        while let Some(frame) = stack.pop() {
+            match frame {
+                Frame::Text(text) => f.write_str(text)?,
+                Frame::Flat(Self::Atom(value)) => f.write_str(value)?,
+                Frame::Flat(Self::Str(value)) => {
+                    f.write_char('"')?;
+                    for c in value.chars() {
+                        match c {
+                            '"' => f.write_str("\\\"")?,
+                            '\\' => f.write_str("\\\\")?,
+                            '\n' => f.write_str("\\n")?,
+                            c => f.write_char(c)?,
+                        }
+                    }
+                    f.write_char('"')?;
+                }
+                Frame::Flat(Self::List(items)) => {
+                    f.write_char('(')?;
+                    stack.push(Frame::Text(")"));
+                    for (index, item) in items.iter().enumerate().rev() {
+                        stack.push(Frame::Flat(item));
+                        if index > 0 {
+                            stack.push(Frame::Text(" "));
+                        }
+                    }
+                }
+                Frame::Pretty(..) | Frame::Newline(_) => unreachable!("only written by `write_pretty`"),
+            }
+        }
+        Ok(())
+    }
+}
+
+const PRETTY_WIDTH: usize = 80;
+
+// 1行で書いたときのバイト数。リストの分は `write_pretty` が先に数えておく。
+fn flat_len(value: &SExpr, lists: &HashMap<*const SExpr, usize>) -> usize {
+    match value {
+        SExpr::Atom(value) => value.len(),
+        SExpr::Str(value) => {
+            2 + value
+                .chars()
+                .map(|c| match c {
+                    '"' | '\\' | '\n' => 2,
+                    c => c.len_utf8(),
+                })
+                .sum::<usize>()
+        }
+        SExpr::List(_) => lists[&std::ptr::from_ref(value)],
+    }
+}
+
+// 書き出しの残り仕事。`Pretty` は幅に応じて1行か複数行に、`Flat` はそのまま1行で書く。
+enum Frame<'a> {
+    Pretty(&'a SExpr, usize),
+    Flat(&'a SExpr),
+    Text(&'static str),
+    Newline(usize),
+}
+
+// 1行に収まるリストはそのまま、収まらなければ `:key 値` と子リストを1行ずつ並べる。
+// キーワードの値は続くスカラーすべて、スカラーがなければ直後のリスト1つとみなす。
+// 各リストの1行の長さを先に後順で数えておき、入れ子はスタックで辿るので深い木でも再帰しない。
+fn write_pretty(value: &SExpr, output: &mut String, indent: usize) {
+    let mut lists = HashMap::new();
+    let mut pending = vec![(value, false)];
+    while let Some((node, visited)) = pending.pop() {
+        let SExpr::List(items) = node else { continue };
+        if visited {
+            let len = items.iter().map(|item| flat_len(item, &lists)).sum::<usize>()
+                + items.len().saturating_sub(1)
+                + 2;
+            lists.insert(std::ptr::from_ref(node), len);
+        } else {
+            pending.push((node, true));
+            pending.extend(items.iter().map(|item| (item, false)));
+        }
+    }
+
+    let is_keyword = |item: &SExpr| matches!(item, SExpr::Atom(atom) if atom.starts_with(':'));
+    let is_scalar = |item: &SExpr| !matches!(item, SExpr::List(_)) && !is_keyword(item);
+
+    let mut stack = vec![Frame::Pretty(value, indent)];
+    while let Some(frame) = stack.pop() {
+        let (value, indent) = match frame {
+            Frame::Text(text) => {
+                output.push_str(text);
+                continue;
+            }
+            Frame::Newline(level) => {
+                output.push('\n');
+                output.push_str(&"  ".repeat(level));
+                continue;
+            }
+            Frame::Flat(value) => {
+                let _ = write!(output, "{value}");
+                continue;
+            }
+            Frame::Pretty(value, indent) => (value, indent),
+        };
+        let SExpr::List(items) = value else {
+            let _ = write!(output, "{value}");
+            continue;
+        };
+        // 字下げが幅を使い切る深さからは折り返しても収まらないので、残りは1行で書く
+        if flat_len(value, &lists) + indent * 2 <= PRETTY_WIDTH
+            || indent * 2 >= PRETTY_WIDTH
+            || items.is_empty()
+        {
+            let _ = write!(output, "{value}");
+            continue;
+        }
+
+        let mut frames = vec![Frame::Text("("), Frame::Pretty(&items[0], indent + 1)];
+        let mut index = 1;
+        while index < items.len() && is_scalar(&items[index]) {
+            frames.extend([Frame::Text(" "), Frame::Flat(&items[index])]);
+            index += 1;
+        }
+
+        while index < items.len() {
+            frames.push(Frame::Newline(indent + 1));
+            let item = &items[index];
+            index += 1;
+            if !is_keyword(item) {
+                frames.push(Frame::Pretty(item, indent + 1));
+                continue;
+            }
+            frames.push(Frame::Flat(item));
+            let value_start = index;
+            while index < items.len() && is_scalar(&items[index]) {
+                frames.extend([Frame::Text(" "), Frame::Flat(&items[index])]);
+                index += 1;
+            }
+            if index == value_start && index < items.len() && !is_keyword(&items[index]) {
+                frames.extend([Frame::Text(" "), Frame::Pretty(&items[index], indent + 1)]);
+                index += 1;
+            }
+        }
+        frames.push(Frame::Text(")"));
+        stack.extend(frames.into_iter().rev());
+    }
+}
+
+pub fn parse(input: &str) -> Result<SExpr, DecodeError> {
+    let mut parser = Parser {
+        chars: input.char_indices().peekable(),
+        input,
+    };
+    let value = parser.value()?;
+    parser.skip_trivia();
+    match parser.chars.peek() {
+        None => Ok(value),
+        Some(&(offset, _)) => Err(DecodeError::Syntax {
+            offset,
+            message: "trailing characters".to_string(),
+        }),
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    input: &'a str,
+}
+
+impl Parser<'_> {
+    fn error(&mut self, message: &str) -> DecodeError {
+        DecodeError::Syntax {
+            offset: self
+                .chars
+                .peek()
+                .map(|&(offset, _)| offset)
+                .unwrap_or(self.input.len()),
+            message: message.to_string(),
+        }
+    }
+
+    // 空白と `;` から行末までのコメントを読み飛ばす。
+    fn skip_trivia(&mut self) {
+        loop {
+            while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+            if self.chars.next_if(|&(_, c)| c == ';').is_none() {
+                return;
+            }
+            while self.chars.next_if(|&(_, c)| c != '\n').is_some() {}
+        }
+    }
+
+    // 入れ子は再帰せず、開いているリストをスタックに積んで読む。
+    fn value(&mut self) -> Result<SExpr, DecodeError> {
+        let mut open: Vec<Vec<SExpr>> = Vec::new();
+        loop {
+            let value = self.item(&mut open)?;
+            match (value, open.last_mut()) {
+                (Some(value), Some(items)) => items.push(value),
+                (Some(value), None) => return Ok(value),
+                (None, _) => {}
+            }
+        }
+    }
+
+    // 値を1つ読む。リストが開いただけなら `open` に積んで `None` を返す。
+    fn item(&mut self, open: &mut Vec<Vec<SExpr>>) -> Result<Option<SExpr>, DecodeError> {
+        self.skip_trivia();
+        match self.chars.peek().map(|&(_, c)| c) {
+            Some('(') => {
+                if open.len() == MAX_NESTING {
+                    return Err(self.error(&format!("nested deeper than {MAX_NESTING} levels")));
+                }
+                self.chars.next();
+                open.push(Vec::new());
+                Ok(None)
+            }
+            Some(')') => match open.pop() {
+                Some(items) => {
+                    self.chars.next();
+                    Ok(Some(SExpr::List(items)))
+                }
+                None => Err(self.error("unexpected ')'")),
+            },
+            Some('"') => {
+                self.chars.next();
+                let mut value = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some((_, '"')) => return Ok(Some(SExpr::Str(value))),
+                        Some((_, '\\')) => match self.chars.next() {
+                            Some((_, 'n')) => value.push('\n'),
+                            Some((_, c)) => value.push(c),
+                            None => return Err(self.error("unterminated string")),
+                        },
+                        Some((_, c)) => value.push(c),
+                        None => return Err(self.error("unterminated string")),
+                    }
+                }
+            }
+            Some(_) => {
+                let mut atom = String::new();
+                while let Some((_, c)) = self
+                    .chars
+                    .next_if(|&(_, c)| !c.is_whitespace() && !matches!(c, '(' | ')' | '"' | ';'))
+                {
+                    atom.push(c);
+                }
+                Ok(Some(SExpr::Atom(atom)))
+            }
+            None if open.is_empty() => Err(self.error("unexpected end of input")),
+            None => Err(self.error("unterminated list")),
+        }
+    }
+}
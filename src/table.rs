@@ -0,0 +1,382 @@
+use std::fmt;
+
+use crate::grammar::{Grammar, NonTerminal, Terminal};
+use crate::lr::{Action, CompiledParser};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseTableAction {
+    Shift(usize),
+    Reduce(usize),
+    Accept,
+    Goto(usize),
+    Error,
+}
+
+impl ParseTableAction {
+    pub fn as_label(self) -> String {
+        match self {
+            Self::Shift(state) => format!("s{}", state),
+            Self::Reduce(production) => format!("r{}", production),
+            Self::Accept => "acc".to_string(),
+            Self::Goto(state) => format!("g{}", state),
+            Self::Error => String::new(),
+        }
+    }
+}
+
+/// 終端記号（`$` を含む）→ 非終端記号の順に並べた列見出しと、状態ごとの行。
+pub fn build_parse_table(
+    grammar: &Grammar,
+    compiled_parser: &CompiledParser,
+) -> (Vec<char>, Vec<Vec<ParseTableAction>>) {
+    let mut symbols: Vec<char> =
+        grammar.terminals().into_iter().map(|terminal| terminal.0).collect();
+    if !symbols.contains(&'$') {
+        symbols.push('$');
+    }
+    symbols.extend(
+        grammar
+            .non_terminals()
+            .into_iter()
+            .map(|non_terminal| non_terminal.0),
+    );
+
+    let mut table =
+        vec![vec![ParseTableAction::Error; symbols.len()]; compiled_parser.state_count()];
+
+    for (state, row) in table.iter_mut().enumerate() {
+        for (column, symbol) in symbols.iter().copied().enumerate() {
+            row[column] = if symbol == '$' || !symbol.is_ascii_uppercase() {
+                compiled_parser
+                    .action(state, Terminal(symbol))
+                    .map(|action| match action {
+                        Action::Shift(next) => ParseTableAction::Shift(next),
+                        Action::Reduce(production) => ParseTableAction::Reduce(production),
+                        Action::Accept => ParseTableAction::Accept,
+                    })
+                    .unwrap_or(ParseTableAction::Error)
+            } else {
+                compiled_parser
+                    .goto(state, NonTerminal(symbol))
+                    .map(ParseTableAction::Goto)
+                    .unwrap_or(ParseTableAction::Error)
+            };
+        }
+    }
+
+    (symbols, table)
+}
+
+/// `export_table` の出力形式。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableFormat {
+    Markdown,
+    Csv,
+    Html,
+    Latex,
+}
+
+impl TableFormat {
+    pub const ALL: [TableFormat; 4] = [Self::Markdown, Self::Csv, Self::Html, Self::Latex];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Markdown => "markdown",
+            Self::Csv => "csv",
+            Self::Html => "html",
+            Self::Latex => "latex",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Csv => "csv",
+            Self::Html => "html",
+            Self::Latex => "tex",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.name() == name)
+    }
+}
+
+/// `build_parse_table` の表を文書に貼れる形にする。列は action（終端記号と `$`）と goto（非終端記号）に
+/// まとめ、goto の欄は遷移先の状態番号だけを書く。`productions` は `r<n>` の凡例として表の後に並べる。
+pub fn export_table<P: fmt::Display>(
+    symbols: &[char],
+    table: &[Vec<ParseTableAction>],
+    productions: &[P],
+    format: TableFormat,
+) -> String {
+    let layout = Layout::new(symbols, table, productions);
+    match format {
+        TableFormat::Markdown => layout.markdown(),
+        TableFormat::Csv => layout.csv(),
+        TableFormat::Html => layout.html(),
+        TableFormat::Latex => layout.latex(),
+    }
+}
+
+struct Layout {
+    action_symbols: Vec<char>,
+    goto_symbols: Vec<char>,
+    rows: Vec<Vec<String>>,
+    legend: Vec<String>,
+}
+
+impl Layout {
+    fn new<P: fmt::Display>(
+        symbols: &[char],
+        table: &[Vec<ParseTableAction>],
+        productions: &[P],
+    ) -> Self {
+        // build_parse_table は action の列を先に、goto の列を後に並べる。
+        let split = symbols
+            .iter()
+            .position(|&symbol| symbol != '$' && symbol.is_ascii_uppercase())
+            .unwrap_or(symbols.len());
+        let rows = table
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| match cell {
+                        ParseTableAction::Goto(state) => state.to_string(),
+                        other => other.as_label(),
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            action_symbols: symbols[..split].to_vec(),
+            goto_symbols: symbols[split..].to_vec(),
+            rows,
+            legend: productions.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    fn symbols(&self) -> impl Iterator<Item = char> + '_ {
+        self.action_symbols.iter().chain(&self.goto_symbols).copied()
+    }
+
+    // GFM の表は見出しが1行しかないので、見出しに列グループを置き、記号の行を本体の先頭に太字で置く。
+    // `|` を `\|` にするのは表の行の中だけ。凡例のリストでは `\|` がそのまま表示されてしまう。
+    fn markdown(&self) -> String {
+        let code = |text: &str| {
+            if text.contains('`') { format!("`` {} ``", text) } else { format!("`{}`", text) }
+        };
+        let cell = |text: &str| code(&text.replace('|', "\\|"));
+        let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+
+        let mut groups = vec![String::new()];
+        groups.extend(group_header(self.action_symbols.len(), "action"));
+        groups.extend(group_header(self.goto_symbols.len(), "goto"));
+        let mut output = line(groups);
+        output.push_str(&line(vec!["---:".to_string(); self.symbols().count() + 1]));
+
+        let mut header = vec!["**state**".to_string()];
+        header.extend(self.symbols().map(|symbol| format!("**{}**", cell(&symbol.to_string()))));
+        output.push_str(&line(header));
+        for (state, row) in self.rows.iter().enumerate() {
+            let mut cells = vec![state.to_string()];
+            cells.extend(row.iter().cloned());
+            output.push_str(&line(cells));
+        }
+
+        output.push('\n');
+        for (id, production) in self.legend.iter().enumerate() {
+            output.push_str(&format!("- r{}: {}\n", id, code(production)));
+        }
+        output
+    }
+
+    fn csv(&self) -> String {
+        let field = |text: &str| {
+            if text.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", text.replace('"', "\"\""))
+            } else {
+                text.to_string()
+            }
+        };
+        let line = |cells: Vec<String>| format!("{}\r\n", cells.join(","));
+
+        let mut groups = vec![String::new()];
+        groups.extend(group_header(self.action_symbols.len(), "action"));
+        groups.extend(group_header(self.goto_symbols.len(), "goto"));
+        let mut output = line(groups);
+
+        let mut header = vec!["state".to_string()];
+        header.extend(self.symbols().map(|symbol| field(&symbol.to_string())));
+        output.push_str(&line(header));
+        for (state, row) in self.rows.iter().enumerate() {
+            let mut cells = vec![state.to_string()];
+            cells.extend(row.iter().map(|cell| field(cell)));
+            output.push_str(&line(cells));
+        }
+
+        output.push_str("\r\n");
+        output.push_str(&line(vec!["production".to_string(), "rule".to_string()]));
+        for (id, production) in self.legend.iter().enumerate() {
+            output.push_str(&line(vec![format!("r{}", id), field(production)]));
+        }
+        output
+    }
+
+    fn html(&self) -> String {
+        let mut output = String::from("<table class=\"lr-parse-table\">\n  <thead>\n    <tr>");
+        output.push_str("<th rowspan=\"2\">state</th>");
+        for (count, name) in [(self.action_symbols.len(), "action"), (self.goto_symbols.len(), "goto")] {
+            if count > 0 {
+                output.push_str(&format!("<th colspan=\"{}\">{}</th>", count, name));
+            }
+        }
+        output.push_str("</tr>\n    <tr>");
+        for symbol in self.symbols() {
+            output.push_str(&format!("<th><code>{}</code></th>", html_escape(&symbol.to_string())));
+        }
+        output.push_str("</tr>\n  </thead>\n  <tbody>\n");
+        for (state, row) in self.rows.iter().enumerate() {
+            output.push_str(&format!("    <tr><th>{}</th>", state));
+            for cell in row {
+                output.push_str(&format!("<td>{}</td>", html_escape(cell)));
+            }
+            output.push_str("</tr>\n");
+        }
+        output.push_str("  </tbody>\n</table>\n<ul class=\"lr-productions\">\n");
+        for (id, production) in self.legend.iter().enumerate() {
+            output.push_str(&format!(
+                "  <li>r{}: <code>{}</code></li>\n",
+                id,
+                html_escape(production)
+            ));
+        }
+        output.push_str("</ul>\n");
+        output
+    }
+
+    fn latex(&self) -> String {
+        let mut spec = String::from("r|");
+        spec.push_str(&"c".repeat(self.action_symbols.len()));
+        if !self.goto_symbols.is_empty() {
+            spec.push('|');
+            spec.push_str(&"c".repeat(self.goto_symbols.len()));
+        }
+
+        let mut output = format!("\\begin{{tabular}}{{{}}}\n", spec);
+        let mut groups = Vec::new();
+        if !self.action_symbols.is_empty() {
+            let border = if self.goto_symbols.is_empty() { "c" } else { "c|" };
+            groups.push(format!(
+                "\\multicolumn{{{}}}{{{}}}{{action}}",
+                self.action_symbols.len(),
+                border
+            ));
+        }
+        if !self.goto_symbols.is_empty() {
+            groups.push(format!("\\multicolumn{{{}}}{{c}}{{goto}}", self.goto_symbols.len()));
+        }
+        output.push_str(&format!(" & {} \\\\\n", groups.join(" & ")));
+
+        let header: Vec<String> = self
+            .symbols()
+            .map(|symbol| format!("\\texttt{{{}}}", latex_escape(&symbol.to_string())))
+            .collect();
+        output.push_str(&format!("state & {} \\\\\n\\hline\n", header.join(" & ")));
+        for (state, row) in self.rows.iter().enumerate() {
+            let cells: Vec<String> = row.iter().map(|cell| latex_escape(cell)).collect();
+            output.push_str(&format!("{} & {} \\\\\n", state, cells.join(" & ")));
+        }
+        output.push_str("\\end{tabular}\n\n\\begin{tabular}{rl}\n");
+        for (id, production) in self.legend.iter().enumerate() {
+            output.push_str(&format!(
+                "r{} & \\texttt{{{}}} \\\\\n",
+                id,
+                latex_escape(production)
+            ));
+        }
+        output.push_str("\\end{tabular}\n");
+        output
+    }
+}
+
+// グループ名を先頭の列にだけ書き、残りの列は空にする。
+fn group_header(count: usize, name: &str) -> Vec<String> {
+    (0..count)
+        .map(|column| if column == 0 { name.to_string() } else { String::new() })
+        .collect()
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn latex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '<' => escaped.push_str("\\textless{}"),
+            '>' => escaped.push_str("\\textgreater{}"),
+            '#' | '$' | '%' | '&' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::parse_grammar_text;
+    use crate::lr::compile;
+
+    fn export(format: TableFormat) -> String {
+        let grammar = parse_grammar_text(include_str!("../reducer")).unwrap();
+        let machine = compile(&grammar).unwrap();
+        let (symbols, table) = build_parse_table(&grammar, &machine);
+        export_table(&symbols, &table, machine.productions(), format)
+    }
+
+    #[test]
+    fn every_format_groups_action_and_goto_columns() {
+        let markdown = export(TableFormat::Markdown);
+        assert!(markdown.starts_with("|  | action |  |  |  |  | goto |  |\n"));
+        assert!(markdown.contains("| **state** | **`*`** | **`+`** | **`0`** | **`1`** | **`$`** | **`B`** | **`E`** |"));
+        assert!(markdown.contains("| 0 |  |  | s1 | s2 |  | 3 | 4 |"));
+        assert!(markdown.contains("- r1: `E -> E*B`"));
+
+        let csv = export(TableFormat::Csv);
+        assert!(csv.starts_with(",action,,,,,goto,\r\nstate,*,+,0,1,$,B,E\r\n0,,,s1,s2,,3,4\r\n"));
+        assert!(csv.contains("r0,F -> E\r\n"));
+
+        let html = export(TableFormat::Html);
+        assert!(html.contains("<th rowspan=\"2\">state</th><th colspan=\"5\">action</th><th colspan=\"2\">goto</th>"));
+        assert!(html.contains("<li>r2: <code>E -&gt; E+B</code></li>"));
+
+        let latex = export(TableFormat::Latex);
+        assert!(latex.starts_with("\\begin{tabular}{r|ccccc|cc}\n & \\multicolumn{5}{c|}{action} & \\multicolumn{2}{c}{goto} \\\\\n"));
+        assert!(latex.contains("\\texttt{\\$}"));
+        assert!(latex.contains("r3 & \\texttt{E -\\textgreater{} B} \\\\\n"));
+    }
+
+    #[test]
+    fn special_characters_are_escaped_per_format() {
+        let grammar = parse_grammar_text("S -> |S,\nS -> &").unwrap();
+        let machine = compile(&grammar).unwrap();
+        let (symbols, table) = build_parse_table(&grammar, &machine);
+        let export = |format| export_table(&symbols, &table, machine.productions(), format);
+
+        let markdown = export(TableFormat::Markdown);
+        assert!(markdown.contains("**`\\|`**"));
+        assert!(markdown.contains("- r1: `S -> |S,`"));
+        assert!(export(TableFormat::Csv).contains("state,&,\",\",|,$,S\r\n"));
+        assert!(export(TableFormat::Html).contains("<code>&amp;</code>"));
+        assert!(export(TableFormat::Latex).contains("\\texttt{\\&}"));
+    }
+}
@@ -0,0 +1,89 @@
+use crate::ast::{AstNode, Fold, NodeInfo, Token};
+use crate::grammar::{Grammar, Translation, TranslationPiece};
+
+/// 生成規則 id ごとの翻訳テンプレート（構文主導翻訳スキーム）。
+/// id は `CompiledParser` と同じ番号付け（0 が拡大規則、文法の n 番目の規則が n + 1）。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranslationScheme {
+    templates: Vec<Option<Translation>>,
+}
+
+impl TranslationScheme {
+    pub fn from_grammar(grammar: &Grammar) -> Self {
+        let mut templates = vec![None];
+        templates.extend(grammar.translations.iter().cloned());
+        Self { templates }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.iter().all(Option::is_none)
+    }
+
+    pub fn template(&self, production: usize) -> Option<&Translation> {
+        self.templates.get(production).and_then(Option::as_ref)
+    }
+}
+
+/// 具象構文木を下からたどって翻訳する。終端記号は綴りそのもの、
+/// テンプレートのない規則は子の翻訳をそのまま連結する。
+pub fn translate(node: &AstNode, scheme: &TranslationScheme) -> String {
+    node.fold(&mut Translator { scheme })
+}
+
+struct Translator<'s> {
+    scheme: &'s TranslationScheme,
+}
+
+impl Fold for Translator<'_> {
+    type Output = String;
+
+    fn fold_terminal(&mut self, token: &Token) -> String {
+        token.lexeme.clone()
+    }
+
+    fn fold_non_terminal(&mut self, info: &NodeInfo, translated: Vec<String>) -> String {
+        match info.production.and_then(|production| self.scheme.template(production)) {
+            Some(template) => template
+                .pieces
+                .iter()
+                .map(|piece| match piece {
+                    TranslationPiece::Text(text) => text.as_str(),
+                    TranslationPiece::Child(index) => translated.get(*index).map_or("", String::as_str),
+                })
+                .collect(),
+            None => translated.concat(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{parse_grammar_text, parse_input_text};
+    use crate::lr::compile;
+    use crate::runtime::run;
+
+    fn translated(grammar: &str, input: &str) -> String {
+        let grammar = parse_grammar_text(grammar).unwrap();
+        let machine = compile(&grammar).unwrap();
+        let ast = run(&machine, &parse_input_text(input).unwrap()).unwrap().ast;
+        translate(&ast, &TranslationScheme::from_grammar(&grammar))
+    }
+
+    #[test]
+    fn schemes_translate_infix_to_postfix_and_s_expressions() {
+        let postfix = "E -> E+B => \"{0} {2} +\"\nE -> E*B => \"{0} {2} *\"\nE -> B\nB -> 0\nB -> 1";
+        assert_eq!(translated(postfix, "1+1*0"), "1 1 + 0 *");
+
+        let sexpr = "E -> E+B => \"(+ {0} {2})\"\nE -> E*B => \"(* {0} {2})\"\nE -> B\n\
+                     B -> (E) => \"{1}\"\nB -> 0\nB -> 1";
+        assert_eq!(translated(sexpr, "1*(1+0)"), "(* 1 (+ 1 0))");
+    }
+
+    #[test]
+    fn productions_without_templates_concatenate_their_children() {
+        let grammar = "E -> E+B\nE -> B\nB -> 1 => \"one\"";
+        assert_eq!(translated(grammar, "1+1"), "one+one");
+        assert!(TranslationScheme::from_grammar(&parse_grammar_text("E -> 1").unwrap()).is_empty());
+    }
+}
@@ -0,0 +1,284 @@
+use crate::app::ParserKind;
+use crate::evaluator::Environment;
+use crate::lexer::is_identifier;
+use crate::roles::{Arity, Operation, RoleDefinition, RoleRegistry, TerminalRole};
+use lr0_parser_rs::serialize::{DecodeError, Json, json};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// ワークスペースファイルの版。形を変えたら上げる。
+/// 2 で `role_definitions`、3 で `variables` を足し、4 で役割の `rust` を `evaluation` に改めた。それより前のファイルもそのまま読める。
+const VERSION: i64 = 4;
+
+/// File → Open/Save で読み書きする作業状態。JSON で保存し、キーの順序は固定。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceFile {
+    pub grammar: String,
+    pub input: String,
+    pub terminal_roles: BTreeMap<char, TerminalRole>,
+    /// ユーザーが定義した役割。登録順に並ぶ。
+    pub role_definitions: Vec<RoleDefinition>,
+    /// Ident の終端記号に与える値。
+    pub variables: Environment,
+    pub parser_kind: ParserKind,
+}
+
+#[derive(Debug)]
+pub enum WorkspaceError {
+    Io(std::io::Error),
+    Decode(DecodeError),
+}
+
+impl fmt::Display for WorkspaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "I/O error: {}", error),
+            Self::Decode(DecodeError::Syntax { offset, message }) => {
+                write!(f, "invalid workspace file at byte {}: {}", offset, message)
+            }
+            Self::Decode(DecodeError::Shape(message)) => {
+                write!(f, "invalid workspace file: {}", message)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for WorkspaceError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<DecodeError> for WorkspaceError {
+    fn from(error: DecodeError) -> Self {
+        Self::Decode(error)
+    }
+}
+
+impl WorkspaceFile {
+    pub fn to_json(&self) -> Json {
+        Json::object([
+            ("version", Json::Number(VERSION)),
+            ("parser_kind", Json::string(self.parser_kind.key())),
+            ("grammar", Json::string(&self.grammar)),
+            ("input", Json::string(&self.input)),
+            (
+                "terminal_roles",
+                Json::object(
+                    self.terminal_roles
+                        .iter()
+                        .map(|(terminal, role)| (terminal.to_string(), Json::string(role.name()))),
+                ),
+            ),
+            (
+                "role_definitions",
+                Json::Array(self.role_definitions.iter().map(definition_to_json).collect()),
+            ),
+            (
+                "variables",
+                Json::object(self.variables.iter().map(|(name, value)| (name.clone(), Json::Number(*value)))),
+            ),
+        ])
+    }
+
+    pub fn from_json(value: &Json) -> Result<Self, DecodeError> {
+        match value.get("version") {
+            Some(Json::Number(1..=VERSION)) => {}
+            Some(Json::Number(other)) => return Err(shape(format!("unsupported version {}", other))),
+            _ => return Err(shape("missing `version`")),
+        }
+
+        let kind = string_field(value, "parser_kind")?;
+        let parser_kind = ParserKind::from_key(kind)
+            .ok_or_else(|| shape(format!("unknown parser kind `{}`", kind)))?;
+
+        let terminal_roles = match value.get("terminal_roles") {
+            Some(Json::Object(fields)) => fields
+                .iter()
+                .map(|(key, role)| {
+                    let mut chars = key.chars();
+                    match (chars.next(), chars.next(), role) {
+                        (Some(terminal), None, Json::String(role)) => {
+                            Ok((terminal, TerminalRole::from_name(role)))
+                        }
+                        _ => Err(shape(format!("invalid terminal role `{}`", key))),
+                    }
+                })
+                .collect::<Result<_, _>>()?,
+            None => BTreeMap::new(),
+            Some(_) => return Err(shape("`terminal_roles` must be an object")),
+        };
+
+        let role_definitions = match value.get("role_definitions") {
+            Some(Json::Array(items)) => items.iter().map(definition_from_json).collect::<Result<_, _>>()?,
+            None => Vec::new(),
+            Some(_) => return Err(shape("`role_definitions` must be an array")),
+        };
+        // 壊れた定義はここで弾き、`role_registry` では失敗しないようにする。
+        let mut registry = RoleRegistry::default();
+        for definition in &role_definitions {
+            registry.define(definition.clone()).map_err(|error| shape(error.to_string()))?;
+        }
+
+        let variables = match value.get("variables") {
+            Some(Json::Object(fields)) => fields
+                .iter()
+                .map(|(name, value)| match value {
+                    Json::Number(value) if is_identifier(name) => Ok((name.clone(), *value)),
+                    _ => Err(shape(format!("invalid variable `{}`", name))),
+                })
+                .collect::<Result<_, _>>()?,
+            None => Environment::new(),
+            Some(_) => return Err(shape("`variables` must be an object")),
+        };
+
+        Ok(Self {
+            grammar: string_field(value, "grammar")?.to_string(),
+            input: string_field(value, "input")?.to_string(),
+            terminal_roles,
+            role_definitions,
+            variables,
+            parser_kind,
+        })
+    }
+
+    pub fn role_registry(&self) -> RoleRegistry {
+        let mut registry = RoleRegistry::default();
+        for definition in &self.role_definitions {
+            let _ = registry.define(definition.clone());
+        }
+        registry
+    }
+
+    pub fn parse(text: &str) -> Result<Self, WorkspaceError> {
+        Ok(Self::from_json(&json::parse(text)?)?)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, WorkspaceError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), WorkspaceError> {
+        let mut text = self.to_json().pretty();
+        text.push('\n');
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+fn shape(message: impl Into<String>) -> DecodeError {
+    DecodeError::Shape(message.into())
+}
+
+fn definition_to_json(definition: &RoleDefinition) -> Json {
+    Json::object([
+        ("name", Json::string(&definition.name)),
+        ("source", Json::string(&definition.source)),
+        ("evaluation", Json::string(&definition.evaluation)),
+        ("arity", Json::string(definition.arity.key())),
+        ("precedence", Json::Number(i64::from(definition.precedence))),
+        ("operation", Json::string(definition.operation.key())),
+    ])
+}
+
+fn definition_from_json(value: &Json) -> Result<RoleDefinition, DecodeError> {
+    let arity = string_field(value, "arity")?;
+    let operation = string_field(value, "operation")?;
+    let precedence = match value.get("precedence") {
+        Some(Json::Number(precedence)) => u8::try_from(*precedence)
+            .map_err(|_| shape(format!("precedence {} is out of range", precedence)))?,
+        _ => return Err(shape("missing `precedence`")),
+    };
+    Ok(RoleDefinition {
+        name: string_field(value, "name")?.to_string(),
+        source: string_field(value, "source")?.to_string(),
+        // version 3 までは `rust` という名前で保存していた。
+        evaluation: string_field(value, "evaluation")
+            .or_else(|_| string_field(value, "rust"))?
+            .to_string(),
+        arity: Arity::from_key(arity).ok_or_else(|| shape(format!("unknown arity `{}`", arity)))?,
+        precedence,
+        operation: Operation::from_key(operation)
+            .ok_or_else(|| shape(format!("unknown operation `{}`", operation)))?,
+    })
+}
+
+fn string_field<'a>(value: &'a Json, key: &str) -> Result<&'a str, DecodeError> {
+    match value.get(key) {
+        Some(Json::String(text)) => Ok(text),
+        Some(_) => Err(shape(format!("`{}` must be a string", key))),
+        None => Err(shape(format!("missing `{}`", key))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> WorkspaceFile {
+        WorkspaceFile {
+            grammar: include_str!("../reducer").to_string(),
+            input: "1+\"0\"".to_string(),
+            terminal_roles: [
+                ('+', TerminalRole::Add),
+                ('0', TerminalRole::Num),
+                ('^', TerminalRole::Custom("Pow".to_string())),
+            ]
+            .into(),
+            role_definitions: vec![RoleDefinition {
+                name: "Pow".to_string(),
+                source: String::new(),
+                evaluation: "i64::pow({0}, {1} as u32)".to_string(),
+                arity: Arity::Binary,
+                precedence: 3,
+                operation: Operation::Pow,
+            }],
+            variables: [("x".to_string(), -4), ("y_2".to_string(), 10)].into(),
+            parser_kind: ParserKind::Slr,
+        }
+    }
+
+    #[test]
+    fn workspace_files_round_trip() {
+        let workspace = sample();
+        let text = workspace.to_json().pretty();
+
+        assert!(text.contains("\"parser_kind\": \"slr\""));
+        assert_eq!(WorkspaceFile::parse(&text).unwrap(), workspace);
+        assert!(workspace.role_registry().operator(&TerminalRole::Custom("Pow".to_string())).is_some());
+
+        let version_3 = text.replace("\"evaluation\":", "\"rust\":").replace("\"version\": 4", "\"version\": 3");
+        assert_eq!(WorkspaceFile::parse(&version_3).unwrap(), workspace);
+    }
+
+    #[test]
+    fn version_1_workspaces_still_open() {
+        let text = r#"{"version": 1, "parser_kind": "lr0", "grammar": "S -> (1)", "input": "(1)",
+                       "terminal_roles": {"(": "L_paren", ")": "R_paren"}}"#;
+        let workspace = WorkspaceFile::parse(text).unwrap();
+
+        assert_eq!(workspace.terminal_roles[&'('], TerminalRole::LParen);
+        assert_eq!(workspace.terminal_roles[&')'], TerminalRole::RParen);
+        assert!(workspace.role_definitions.is_empty());
+        assert!(workspace.variables.is_empty());
+    }
+
+    #[test]
+    fn malformed_workspaces_are_rejected() {
+        let mut json = sample().to_json();
+        if let Json::Object(fields) = &mut json {
+            fields[1].1 = Json::string("lr2");
+        }
+
+        assert!(matches!(
+            WorkspaceFile::from_json(&json),
+            Err(DecodeError::Shape(message)) if message.contains("lr2")
+        ));
+        assert!(matches!(WorkspaceFile::parse("{"), Err(WorkspaceError::Decode(_))));
+        assert!(matches!(
+            WorkspaceFile::parse("{\"version\": 5}"),
+            Err(WorkspaceError::Decode(DecodeError::Shape(_)))
+        ));
+    }
+}